@@ -16,3 +16,9 @@ pub enum FileError {
     #[error("unsuported file type error")]
     UnsuportedFileType,
 }
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("vector has {found} dimensions, the store holds {expected}")]
+    DimensionMismatch { expected: usize, found: usize },
+}
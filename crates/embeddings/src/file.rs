@@ -1,9 +1,24 @@
+use std::io::Read;
+use std::ops::Range;
+
 use lopdf::Document;
+use sha2::{Digest, Sha256};
 
 use crate::error::FileError;
 
 pub struct Content {
     content: String,
+    metadata: PdfMetadata,
+    // char offset where each page ends in `content`, empty when it was not read from pages
+    page_ends: Vec<usize>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PdfMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub page_count: u32,
+    pub producer: Option<String>,
 }
 
 impl Content {
@@ -14,40 +29,185 @@ impl Content {
                 "file type not supported".to_string(),
             ))?;
         match kind.mime_type() {
-            "application/pdf" => Content::parse_pdf(path),
-            _ => Err(FileError::ParsingError("unsuported file".to_string())),
+            "application/pdf" => Content::parse_pdf(Document::load(path)),
+            _ => Err(FileError::UnsuportedFileType),
+        }
+    }
+
+    // from_bytes parses content already in memory, e.g. an upload, `mime_type` picks the parser
+    pub fn from_bytes(bytes: &[u8], mime_type: &str) -> Result<Self, FileError> {
+        match mime_type {
+            "application/pdf" => Content::parse_pdf(Document::load_mem(bytes)),
+            _ => Err(FileError::UnsuportedFileType),
         }
     }
 
-    fn parse_pdf(path: &str) -> Result<Content, FileError> {
-        let documet = Document::load(path).map_err(FileError::PdfError)?;
+    pub fn from_reader<R: Read>(mut reader: R, mime_type: &str) -> Result<Self, FileError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(FileError::IOError)?;
+        Content::from_bytes(&bytes, mime_type)
+    }
+
+    fn parse_pdf(document: Result<Document, lopdf::Error>) -> Result<Content, FileError> {
+        let documet = document.map_err(FileError::PdfError)?;
         let pages = documet.get_pages();
         let mut texts = Vec::new();
+        let mut page_ends = Vec::new();
+        let mut length = 0;
 
         for (i, _) in pages.iter().enumerate() {
             let page_number = (i + 1) as u32;
-            let text = documet.extract_text(&[page_number]);
-            texts.push(text.unwrap_or_default());
+            let text = documet.extract_text(&[page_number]).unwrap_or_default();
+            length += text.chars().count();
+            page_ends.push(length);
+            texts.push(text);
         }
 
         Ok(Content {
             content: texts.join(""),
+            metadata: Content::pdf_metadata(&documet),
+            page_ends,
         })
     }
 
-    fn gen_chunks(&self, generator: impl ChunkGenerator) -> Vec<String> {
+    // pdf_metadata reads the document info dictionary, missing or empty entries are left as None
+    fn pdf_metadata(document: &Document) -> PdfMetadata {
+        let info = document
+            .trailer
+            .get_deref(b"Info", document)
+            .and_then(|info| info.as_dict());
+        let field = |key: &[u8]| {
+            info.as_ref()
+                .ok()
+                .and_then(|info| info.get_deref(key, document).ok())
+                .and_then(|value| value.as_str().ok())
+                .map(decode_pdf_string)
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        PdfMetadata {
+            title: field(b"Title"),
+            author: field(b"Author"),
+            page_count: document.get_pages().len() as u32,
+            producer: field(b"Producer"),
+        }
+    }
+
+    pub fn metadata(&self) -> PdfMetadata {
+        self.metadata.clone()
+    }
+
+    // content_hash is the hex SHA-256 of the text with its whitespace collapsed, so the same
+    // document extracted again, or with another layout, hashes the same
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (n, word) in self.content.split_whitespace().enumerate() {
+            if n > 0 {
+                hasher.update(b" ");
+            }
+            hasher.update(word.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn gen_chunks(&self, generator: impl ChunkGenerator) -> Vec<String> {
         generator.generate(&self.content.clone())
     }
+
+    // gen_chunks_with_meta also tells where every chunk comes from, including the page it
+    // starts on for content read from pages
+    pub fn gen_chunks_with_meta(&self, generator: impl ChunkGenerator) -> Vec<ChunkWithMeta> {
+        let mut chunks = generator.generate_with_meta(&self.content);
+        for chunk in &mut chunks {
+            chunk.page = self.page_at(chunk.char_range.start);
+        }
+        chunks
+    }
+
+    // page_at is the 1-based page of the char at `offset`
+    fn page_at(&self, offset: usize) -> Option<u32> {
+        let last = self.page_ends.len().checked_sub(1)?;
+        let page = self.page_ends.partition_point(|&end| end <= offset);
+        Some(page.min(last) as u32 + 1)
+    }
+}
+
+impl From<String> for Content {
+    fn from(content: String) -> Self {
+        Content {
+            content,
+            metadata: PdfMetadata::default(),
+            page_ends: Vec::new(),
+        }
+    }
+}
+
+// PDF text strings are either UTF-16BE prefixed with a byte order mark or PDFDocEncoding,
+// which matches UTF-8 for the ASCII range.
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+/// A chunk and where it comes from: its position among the chunks of the content, the page it
+/// starts on and the chars of the content it spans, counted in chars rather than bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkWithMeta {
+    pub text: String,
+    pub index: usize,
+    pub page: Option<u32>,
+    pub char_range: Range<usize>,
 }
 
 pub trait ChunkGenerator {
     fn generate(&self, content: &str) -> Vec<String>;
+
+    // generate_with_meta finds every chunk of `generate` in `content` after the previous one, a
+    // chunk that is not a slice of the content gets an empty range where the previous one
+    // ended. The page is left to Content::gen_chunks_with_meta, which knows the pages.
+    fn generate_with_meta(&self, content: &str) -> Vec<ChunkWithMeta> {
+        let mut chunks = Vec::new();
+        let (mut byte_cursor, mut char_cursor) = (0, 0);
+        for (index, text) in self.generate(content).into_iter().enumerate() {
+            let char_range = match content[byte_cursor..].find(text.as_str()) {
+                Some(offset) => {
+                    let start_byte = byte_cursor + offset;
+                    let start = char_cursor + content[byte_cursor..start_byte].chars().count();
+                    let end = start + text.chars().count();
+                    byte_cursor = start_byte + text.len();
+                    char_cursor = end;
+                    start..end
+                }
+                None => char_cursor..char_cursor,
+            };
+            chunks.push(ChunkWithMeta {
+                text,
+                index,
+                page: None,
+                char_range,
+            });
+        }
+        chunks
+    }
 }
 
+#[derive(Default)]
 pub struct SentenseGenerator {}
 
 impl SentenseGenerator {
-    fn new() -> Self {
+    pub fn new() -> Self {
         SentenseGenerator {}
     }
 }
@@ -60,10 +220,11 @@ impl ChunkGenerator for SentenseGenerator {
     }
 }
 
+#[derive(Default)]
 pub struct ParagraphGenerator {}
 
 impl ParagraphGenerator {
-    fn new() -> Self {
+    pub fn new() -> Self {
         ParagraphGenerator {}
     }
 }
@@ -74,6 +235,30 @@ impl ChunkGenerator for ParagraphGenerator {
     }
 }
 
+// FilteredGenerator wraps another generator, trimming every chunk and dropping the ones
+// with fewer than `min_len` non-whitespace characters.
+pub struct FilteredGenerator<G: ChunkGenerator> {
+    inner: G,
+    min_len: usize,
+}
+
+impl<G: ChunkGenerator> FilteredGenerator<G> {
+    pub fn new(inner: G, min_len: usize) -> Self {
+        FilteredGenerator { inner, min_len }
+    }
+}
+
+impl<G: ChunkGenerator> ChunkGenerator for FilteredGenerator<G> {
+    fn generate(&self, content: &str) -> Vec<String> {
+        self.inner
+            .generate(content)
+            .into_iter()
+            .map(|chunk| chunk.trim().to_string())
+            .filter(|chunk| chunk.chars().filter(|c| !c.is_whitespace()).count() >= self.min_len)
+            .collect()
+    }
+}
+
 #[test]
 fn test_extract_text_from_pdf() {
     let path = "testdata/sample.pdf";
@@ -89,3 +274,149 @@ fn test_extract_text_from_pdf() {
         );
     }
 }
+
+#[test]
+fn test_filtered_generator_drops_blank_and_tiny_chunks() {
+    let content =
+        Content::from("  first paragraph  \n\n\n\n \n\nok\n\nsecond paragraph".to_string());
+
+    let unfiltered = content.gen_chunks(ParagraphGenerator::new());
+    assert_eq!(unfiltered.len(), 5);
+
+    let chunks = content.gen_chunks(FilteredGenerator::new(ParagraphGenerator::new(), 3));
+    assert_eq!(chunks, vec!["first paragraph", "second paragraph"]);
+}
+
+#[test]
+fn test_chunk_meta_indices_and_char_ranges() {
+    let text = "Colombia adaptó su plan. ¿Qué sigue? El clima cambia.";
+    let content = Content::from(text.to_string());
+    let chars: Vec<char> = text.chars().collect();
+    let slice = |range: &Range<usize>| chars[range.clone()].iter().collect::<String>();
+
+    // the sentences of a single paragraph cover all of it
+    let sentences = content.gen_chunks_with_meta(SentenseGenerator::new());
+    assert_eq!(
+        sentences.iter().map(|c| c.text.clone()).collect::<Vec<_>>(),
+        content.gen_chunks(SentenseGenerator::new())
+    );
+    let mut end = 0;
+    for (i, chunk) in sentences.iter().enumerate() {
+        assert_eq!(chunk.index, i);
+        assert_eq!(chunk.char_range.start, end, "{:?}", chunk);
+        assert_eq!(slice(&chunk.char_range), chunk.text);
+        assert_eq!(chunk.page, None);
+        end = chunk.char_range.end;
+    }
+    assert_eq!(end, chars.len());
+    assert_eq!(sentences.len(), 3);
+
+    let content = Content::from(format!("{}\n\n  Sí, en Bogotá  \n\nok", text));
+    // trimmed chunks point at the trimmed text, dropped ones leave no gap in the indices
+    let paragraphs =
+        content.gen_chunks_with_meta(FilteredGenerator::new(ParagraphGenerator::new(), 3));
+    let spans: Vec<(usize, &str, Range<usize>)> = paragraphs
+        .iter()
+        .map(|c| (c.index, c.text.as_str(), c.char_range.clone()))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (
+                0,
+                "Colombia adaptó su plan. ¿Qué sigue? El clima cambia.",
+                0..53
+            ),
+            (1, "Sí, en Bogotá", 57..70),
+        ]
+    );
+}
+
+#[test]
+fn test_chunk_meta_pages() {
+    let content = Content {
+        content: "First page. Second page. Still second. Third.".to_string(),
+        metadata: PdfMetadata::default(),
+        page_ends: vec![12, 39, 45],
+    };
+
+    let pages: Vec<(usize, Option<u32>)> = content
+        .gen_chunks_with_meta(SentenseGenerator::new())
+        .iter()
+        .map(|c| (c.char_range.start, c.page))
+        .collect();
+    assert_eq!(
+        pages,
+        vec![(0, Some(1)), (12, Some(2)), (25, Some(2)), (39, Some(3))]
+    );
+
+    let pdf = Content::from_path("testdata/sample.pdf").unwrap();
+    let chunks = pdf.gen_chunks_with_meta(ParagraphGenerator::new());
+    assert!(!chunks.is_empty());
+    assert!(chunks.iter().all(|c| c.page == Some(1)));
+}
+
+#[test]
+fn test_pdf_metadata() {
+    let file = Content::from_path("testdata/sample.pdf").unwrap();
+    let metadata = file.metadata();
+    assert_eq!(metadata.title.as_deref(), Some("sample"));
+    assert_eq!(metadata.author.as_deref(), Some("Philip Hutchison"));
+    assert_eq!(
+        metadata.producer.as_deref(),
+        Some("Mac OS X 10.5.4 Quartz PDFContext")
+    );
+    assert_eq!(metadata.page_count, 1);
+
+    // test.pdf has empty Title and Author entries
+    let file = Content::from_path("testdata/test.pdf").unwrap();
+    let metadata = file.metadata();
+    assert_eq!(metadata.title, None);
+    assert_eq!(metadata.author, None);
+    assert_eq!(metadata.producer.as_deref(), Some("pdfTeX-1.40.25"));
+}
+
+#[test]
+fn test_pdf_from_bytes_and_reader_match_path() {
+    let path = "testdata/sample.pdf";
+    let from_path = Content::from_path(path).unwrap();
+    let bytes = std::fs::read(path).unwrap();
+
+    let from_bytes = Content::from_bytes(&bytes, "application/pdf").unwrap();
+    let from_reader =
+        Content::from_reader(std::fs::File::open(path).unwrap(), "application/pdf").unwrap();
+
+    assert!(!from_path.content.is_empty());
+    assert_eq!(from_bytes.content, from_path.content);
+    assert_eq!(from_reader.content, from_path.content);
+    assert_eq!(from_bytes.metadata(), from_path.metadata());
+    assert!(matches!(
+        Content::from_bytes(&bytes, "image/png"),
+        Err(FileError::UnsuportedFileType)
+    ));
+}
+
+#[test]
+fn test_content_hash_ignores_whitespace_layout() {
+    let content = Content::from("Climate  adaptation\n\nplan ".to_string());
+    let relaid = Content::from("Climate adaptation plan".to_string());
+    let other = Content::from("Climate adaptation plans".to_string());
+
+    assert_eq!(content.content_hash(), relaid.content_hash());
+    assert_ne!(content.content_hash(), other.content_hash());
+    assert_eq!(
+        content.content_hash(),
+        "9a05cd6f780f2f003b4812ee82480c1c2d61ea7acaea76aa5d88d30d009abc49"
+    );
+
+    let pdf = Content::from_path("testdata/sample.pdf").unwrap();
+    assert_eq!(
+        pdf.content_hash(),
+        Content::from_bytes(
+            &std::fs::read("testdata/sample.pdf").unwrap(),
+            "application/pdf"
+        )
+        .unwrap()
+        .content_hash()
+    );
+}
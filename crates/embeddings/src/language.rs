@@ -0,0 +1,44 @@
+use whatlang::Lang;
+
+/// # Detect Language
+/// Detects the language of `chunk` and returns its ISO 639-1 code, e.g. `en` or `es`, falling
+/// back to the ISO 639-3 code for languages without a two letter code.
+///
+/// Returns `None` when the text is too short or ambiguous for a reliable guess.
+pub fn detect_language(chunk: &str) -> Option<String> {
+    let info = whatlang::detect(chunk).filter(|info| info.is_reliable())?;
+    let code = match info.lang() {
+        Lang::Eng => "en",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ita => "it",
+        Lang::Nld => "nl",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Jpn => "ja",
+        Lang::Kor => "ko",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        lang => lang.code(),
+    };
+    Some(code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_english_and_spanish() {
+        let english = "The national adaptation plan sets out how the country will reduce \
+            the risks of climate change for its cities and rural communities.";
+        let spanish = "El plan nacional de adaptación establece cómo el país reducirá los \
+            riesgos del cambio climático para sus ciudades y comunidades rurales.";
+
+        assert_eq!(detect_language(english).as_deref(), Some("en"));
+        assert_eq!(detect_language(spanish).as_deref(), Some("es"));
+        assert_eq!(detect_language(""), None);
+    }
+}
@@ -1,3 +1,7 @@
 #[allow(dead_code)]
 pub mod error;
-mod file;
+pub mod file;
+pub mod pipeline;
+pub mod store;
+#[cfg(feature = "language-detection")]
+pub mod language;
@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::ops::Range;
+
+use futures::stream::{self, StreamExt};
+
+use crate::file::{ChunkGenerator, ChunkWithMeta, Content};
+
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+/// A chunk that could not be embedded, together with the error returned by the embedder.
+#[derive(Debug)]
+pub struct ChunkError<E> {
+    pub index: usize,
+    pub chunk: String,
+    pub error: E,
+}
+
+/// A chunk with its embedding. `language` is the ISO code detected for the chunk when the
+/// `language-detection` feature is enabled, `None` otherwise.
+/// `index`, `page` and `char_range` locate the chunk in the content, see `ChunkWithMeta`.
+#[derive(Debug)]
+pub struct EmbeddedChunk {
+    pub chunk: String,
+    pub index: usize,
+    pub page: Option<u32>,
+    pub char_range: Range<usize>,
+    pub embedding: Vec<f32>,
+    pub language: Option<String>,
+}
+
+/// Result of embedding every chunk of a `Content`.
+/// `embeddings` keeps the order in which the generator produced the chunks.
+#[derive(Debug)]
+pub struct EmbeddingBatch<E> {
+    pub embeddings: Vec<EmbeddedChunk>,
+    pub errors: Vec<ChunkError<E>>,
+}
+
+impl<E> EmbeddingBatch<E> {
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// # Embed Content
+/// Chunks `content` with `generator` and calls `embedder` for every chunk, running at most
+/// `parallelism` calls at the same time.
+///
+/// A failing chunk does not abort the batch, its error is collected in `EmbeddingBatch::errors`.
+pub async fn embed_content<G, F, Fut, E>(
+    content: &Content,
+    generator: G,
+    embedder: F,
+    parallelism: usize,
+) -> EmbeddingBatch<E>
+where
+    G: ChunkGenerator,
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<f32>, E>>,
+{
+    let chunks = content.gen_chunks_with_meta(generator);
+
+    let mut results = stream::iter(chunks)
+        .map(|chunk| {
+            let embedding = embedder(chunk.text.clone());
+            async move { (chunk, embedding.await) }
+        })
+        .buffer_unordered(parallelism.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_by_key(|(chunk, _)| chunk.index);
+
+    let mut batch = EmbeddingBatch {
+        embeddings: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (chunk, result) in results {
+        let ChunkWithMeta {
+            text,
+            index,
+            page,
+            char_range,
+        } = chunk;
+        match result {
+            Ok(embedding) => batch.embeddings.push(EmbeddedChunk {
+                language: chunk_language(&text),
+                chunk: text,
+                index,
+                page,
+                char_range,
+                embedding,
+            }),
+            Err(error) => batch.errors.push(ChunkError {
+                index,
+                chunk: text,
+                error,
+            }),
+        }
+    }
+    batch
+}
+
+#[cfg(feature = "language-detection")]
+fn chunk_language(chunk: &str) -> Option<String> {
+    crate::language::detect_language(chunk)
+}
+
+#[cfg(not(feature = "language-detection"))]
+fn chunk_language(_chunk: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::ParagraphGenerator;
+    use std::time::Duration;
+
+    async fn fake_embedder(chunk: String) -> Result<Vec<f32>, String> {
+        if chunk.contains("fail") {
+            return Err(format!("unable to embed {}", chunk));
+        }
+        // Later chunks finish first so the pipeline has to restore the order.
+        let delay = 50u64.saturating_sub(chunk.len() as u64 * 5);
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        Ok(vec![chunk.len() as f32])
+    }
+
+    #[tokio::test]
+    async fn test_embed_content_keeps_order_and_collects_errors() {
+        let content = Content::from("a\n\nbb\n\nfail\n\ncccc\n\nfail again".to_string());
+
+        let batch = embed_content(&content, ParagraphGenerator::new(), fake_embedder, 3).await;
+
+        let chunks: Vec<&str> = batch.embeddings.iter().map(|e| e.chunk.as_str()).collect();
+        assert_eq!(chunks, vec!["a", "bb", "cccc"]);
+        assert_eq!(batch.embeddings[2].embedding, vec![4.0]);
+        let indices: Vec<usize> = batch.embeddings.iter().map(|e| e.index).collect();
+        assert_eq!(indices, vec![0, 1, 3]);
+        assert_eq!(batch.embeddings[2].char_range, 13..17);
+
+        assert!(!batch.is_complete());
+        let failed: Vec<usize> = batch.errors.iter().map(|e| e.index).collect();
+        assert_eq!(failed, vec![2, 4]);
+        assert_eq!(batch.errors[1].chunk, "fail again");
+    }
+
+    #[cfg(feature = "language-detection")]
+    #[tokio::test]
+    async fn test_embed_content_tags_chunk_language() {
+        let content = Content::from(
+            "The national adaptation plan sets out how the country will reduce the risks of \
+            climate change.\n\nEl plan nacional de adaptación establece cómo el país reducirá \
+            los riesgos del cambio climático."
+                .to_string(),
+        );
+
+        let batch = embed_content(&content, ParagraphGenerator::new(), fake_embedder, 2).await;
+
+        let languages: Vec<Option<&str>> = batch
+            .embeddings
+            .iter()
+            .map(|e| e.language.as_deref())
+            .collect();
+        assert_eq!(languages, vec![Some("en"), Some("es")]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+
+use crate::error::StoreError;
+
+pub type Metadata = HashMap<String, String>;
+
+/// Persists embeddings and finds the ones closest to a query vector.
+///
+/// Implementations decide where the vectors live, `InMemoryEmbeddingStore` keeps them in the
+/// process for tests and local development.
+pub trait EmbeddingStore {
+    type Error;
+
+    /// Stores `vector` and `metadata` under `id`, replacing any previous entry with that id.
+    fn upsert(
+        &self,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Metadata,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Returns the ids of the `top_k` entries most similar to `vector` with their score,
+    /// most similar first.
+    fn query(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+    ) -> impl Future<Output = Result<Vec<(String, f32)>, Self::Error>> + Send;
+}
+
+struct StoredEmbedding {
+    vector: Vec<f32>,
+    metadata: Metadata,
+}
+
+/// An `EmbeddingStore` kept in memory that ranks entries by cosine similarity.
+///
+/// Every vector must have the same number of dimensions as the first one stored.
+#[derive(Default)]
+pub struct InMemoryEmbeddingStore {
+    entries: RwLock<HashMap<String, StoredEmbedding>>,
+}
+
+impl InMemoryEmbeddingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn metadata(&self, id: &str) -> Option<Metadata> {
+        let entries = self.entries.read().unwrap();
+        entries.get(id).map(|entry| entry.metadata.clone())
+    }
+
+    fn check_dimensions(
+        entries: &HashMap<String, StoredEmbedding>,
+        vector: &[f32],
+    ) -> Result<(), StoreError> {
+        match entries.values().next() {
+            Some(entry) if entry.vector.len() != vector.len() => {
+                Err(StoreError::DimensionMismatch {
+                    expected: entry.vector.len(),
+                    found: vector.len(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl EmbeddingStore for InMemoryEmbeddingStore {
+    type Error = StoreError;
+
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Metadata,
+    ) -> Result<(), StoreError> {
+        let mut entries = self.entries.write().unwrap();
+        // the entry being replaced does not constrain the dimensions when it is the only one
+        if !(entries.len() == 1 && entries.contains_key(id)) {
+            Self::check_dimensions(&entries, &vector)?;
+        }
+        entries.insert(id.to_string(), StoredEmbedding { vector, metadata });
+        Ok(())
+    }
+
+    async fn query(&self, vector: &[f32], top_k: usize) -> Result<Vec<(String, f32)>, StoreError> {
+        let entries = self.entries.read().unwrap();
+        Self::check_dimensions(&entries, vector)?;
+
+        let mut scores: Vec<(String, f32)> = entries
+            .iter()
+            .map(|(id, entry)| (id.clone(), cosine_similarity(vector, &entry.vector)))
+            .collect();
+        scores.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then_with(|| a_id.cmp(b_id)));
+        scores.truncate(top_k);
+        Ok(scores)
+    }
+}
+
+// cosine_similarity is 0 when either vector has no length
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(title: &str) -> Metadata {
+        HashMap::from([("title".to_string(), title.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_query_returns_nearest_neighbors_first() {
+        let store = InMemoryEmbeddingStore::new();
+        store
+            .upsert(
+                "adaptation",
+                vec![1.0, 0.0, 0.0],
+                metadata("Adaptation plan"),
+            )
+            .await
+            .unwrap();
+        store
+            .upsert(
+                "mitigation",
+                vec![0.0, 1.0, 0.0],
+                metadata("Mitigation plan"),
+            )
+            .await
+            .unwrap();
+        store
+            .upsert("water", vec![0.7, 0.7, 0.0], metadata("Water management"))
+            .await
+            .unwrap();
+
+        let results = store.query(&[0.9, 0.1, 0.0], 2).await.unwrap();
+
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["adaptation", "water"]);
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[tokio::test]
+    async fn test_upsert_replaces_existing_entry() {
+        let store = InMemoryEmbeddingStore::new();
+        store
+            .upsert("chunk-1", vec![1.0, 0.0], metadata("first"))
+            .await
+            .unwrap();
+        store
+            .upsert("chunk-2", vec![0.0, 1.0], metadata("second"))
+            .await
+            .unwrap();
+        store
+            .upsert("chunk-1", vec![0.0, 2.0], metadata("first, updated"))
+            .await
+            .unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.metadata("chunk-1"), Some(metadata("first, updated")));
+        let results = store.query(&[0.0, 1.0], 10).await.unwrap();
+        assert_eq!(results[0], ("chunk-1".to_string(), 1.0));
+        assert_eq!(results[1], ("chunk-2".to_string(), 1.0));
+    }
+
+    #[tokio::test]
+    async fn test_vectors_must_have_the_same_dimensions() {
+        let store = InMemoryEmbeddingStore::new();
+        store
+            .upsert("chunk-1", vec![1.0, 0.0], Metadata::new())
+            .await
+            .unwrap();
+
+        let upsert = store.upsert("chunk-2", vec![1.0], Metadata::new()).await;
+        assert!(matches!(
+            upsert,
+            Err(StoreError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(store.query(&[1.0, 0.0, 0.0], 1).await.is_err());
+    }
+}
@@ -0,0 +1,41 @@
+// Searches the default app of a project using nothing but the vertex_ai crate, with the
+// credentials in GOOGLE_APPLICATION_CREDENTIALS:
+//
+//     cargo run -p vertex_ai --example search -- <project-id> <query>
+use std::env;
+
+use vertex_ai::discovery_engine::client::{
+    DataStoreClient, DiscoveryEngineSearchRequest, SearchRequest,
+};
+use vertex_ai::discovery_engine::error::Error;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let (Some(project_id), Some(query)) = (args.next(), args.next()) else {
+        eprintln!("usage: search <project-id> <query>");
+        std::process::exit(2);
+    };
+
+    let client = DataStoreClient::new().await?;
+    let response = client
+        .search(SearchRequest {
+            project_id,
+            serving_config: None,
+            discovery_engine_search_request: DiscoveryEngineSearchRequest {
+                query,
+                page_size: 10,
+                ..Default::default()
+            },
+        })
+        .await?;
+
+    for result in response.results.unwrap_or_default() {
+        let id = result.id.as_deref().unwrap_or("-");
+        match result.relevance() {
+            Some(relevance) => println!("{} ({:.2})", id, relevance),
+            None => println!("{}", id),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::error::Error;
+
+// CircuitBreaker stops calling a saturated upstream: after `failure_threshold` consecutive
+// failures every call fails fast with Error::CircuitOpen for `cooldown`. Then a single call, the
+// probe, goes through: its success closes the circuit, its failure opens it for another cooldown.
+// A Client shares its breaker with its clones.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // a probe that never reports, e.g. because its future was dropped, is replaced by another
+    // one after a cooldown
+    HalfOpen { probe_started: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), State::Closed { .. })
+    }
+
+    // acquire lets a call through unless the circuit is open, Error::CircuitOpen carries how
+    // long until the next probe
+    pub(crate) fn acquire(&self) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(Error::CircuitOpen(until - now)),
+            State::HalfOpen { probe_started } if now < probe_started + self.cooldown => {
+                Err(Error::CircuitOpen(probe_started + self.cooldown - now))
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { probe_started: now };
+                Ok(())
+            }
+        }
+    }
+
+    // record reports the outcome of a call let through by acquire
+    pub(crate) fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        *state = match (*state, success) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            // a call sent before the circuit opened does not extend the cooldown
+            (State::Open { until }, false) => State::Open { until },
+            (State::Closed { .. } | State::HalfOpen { .. }, false) => State::Open {
+                until: Instant::now() + self.cooldown,
+            },
+        };
+    }
+}
+
+// is_upstream_failure tells which responses count against the breaker: the ones of an upstream
+// that is down or saturated, not the errors of a bad request
+pub(crate) fn is_upstream_failure(result: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match result {
+        Ok(response) => {
+            response.status().is_server_error()
+                || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        breaker.record(false);
+        breaker.record(false);
+        breaker.record(true);
+        breaker.record(false);
+        breaker.record(false);
+        assert!(breaker.acquire().is_ok(), "a success resets the count");
+
+        breaker.record(false);
+        assert!(breaker.is_open());
+        assert!(matches!(
+            breaker.acquire(),
+            Err(Error::CircuitOpen(left)) if left <= Duration::from_secs(60)
+        ));
+    }
+
+    #[test]
+    fn test_half_open_probe_after_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record(false);
+        assert!(breaker.acquire().is_err());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.acquire().is_ok(), "the probe goes through");
+        assert!(breaker.acquire().is_err(), "only one probe at a time");
+
+        // a failing probe opens the circuit again
+        breaker.record(false);
+        assert!(breaker.acquire().is_err());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.acquire().is_ok());
+        breaker.record(true);
+        assert!(!breaker.is_open());
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_ok());
+    }
+}
@@ -9,9 +9,21 @@ pub enum Error {
     #[error("url parsing error reason: {0}")]
     UrlParseError(String),
 
+    #[error("invalid value for header {0}")]
+    InvalidHeader(String),
+
     #[error("HTTP status error: {0}")]
     HttpStatus(String),
 
     #[error("JSON parsing error")]
     ResponseJsonParsing(#[from] reqwest::Error),
+
+    #[error("circuit open, calls resume in {0:?}")]
+    CircuitOpen(std::time::Duration),
+
+    #[error("fixture error: {0}")]
+    Fixture(String),
+
+    #[error("request timed out after {0:?}")]
+    Timeout(std::time::Duration),
 }
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+// ApiMetrics counts and times the Google API requests of the clients built with_metrics, in the
+// registry of the application. Clients without metrics record nothing.
+#[derive(Clone)]
+pub struct ApiMetrics {
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+}
+
+impl ApiMetrics {
+    // register adds the metrics to `registry`. Registering twice in the same registry fails with
+    // prometheus::Error::AlreadyReg, clients share the metrics by cloning them.
+    pub fn register(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "discovery_engine_requests_total",
+                "Google API requests by endpoint, method and response status.",
+            ),
+            &["endpoint", "method", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "discovery_engine_request_duration_seconds",
+                "Latency of the Google API requests by endpoint and method.",
+            ),
+            &["endpoint", "method"],
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        Ok(Self {
+            requests,
+            request_duration,
+        })
+    }
+
+    // observe records a request that got a response with `status`, or None when it failed
+    // before one was received
+    pub(crate) fn observe(
+        &self,
+        endpoint: &str,
+        method: &str,
+        status: Option<u16>,
+        elapsed: Duration,
+    ) {
+        let status = status.map_or_else(|| "error".to_string(), |status| status.to_string());
+        self.requests
+            .with_label_values(&[endpoint, method, &status])
+            .inc();
+        self.request_duration
+            .with_label_values(&[endpoint, method])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+// endpoint_label names the API method of `url` without the resource ids in it, so the label
+// values stay few: `dataStores` for .../collections/{c}/dataStores/{d} and
+// `servingConfigs:search` for .../servingConfigs/{s}:search
+pub(crate) fn endpoint_label(url: &reqwest::Url) -> String {
+    let mut segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    if segments.first().is_some_and(|version| {
+        version.starts_with('v') && version[1..].starts_with(char::is_numeric)
+    }) {
+        segments.remove(0);
+    }
+    let Some(last) = segments.last() else {
+        return "root".to_string();
+    };
+    let (name, custom_method) = match last.split_once(':') {
+        Some((name, custom_method)) => (name, Some(custom_method)),
+        None => (*last, None),
+    };
+    // resource names alternate collections and ids, an even count ends with an id
+    let collection = if segments.len().is_multiple_of(2) {
+        segments[segments.len() - 2]
+    } else {
+        name
+    };
+    match custom_method {
+        Some(custom_method) => format!("{}:{}", collection, custom_method),
+        None => collection.to_string(),
+    }
+}
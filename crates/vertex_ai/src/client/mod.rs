@@ -1,50 +1,353 @@
+pub mod circuit_breaker;
 pub mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(any(test, feature = "recording"))]
+pub mod recording;
 
+use std::collections::HashMap;
+use std::future::Future;
+#[cfg(any(test, feature = "recording"))]
+use std::path::Path;
 use std::sync::Arc;
+#[cfg(any(test, feature = "metrics"))]
+use std::time::Instant;
+use std::time::{Duration, SystemTime};
 
+use circuit_breaker::CircuitBreaker;
 use error::Error;
 use gcp_auth::TokenProvider;
+#[cfg(feature = "metrics")]
+use metrics::ApiMetrics;
+#[cfg(any(test, feature = "recording"))]
+use recording::{FixtureMode, Recorder};
 use serde_json::Value;
-use tokio::sync::OnceCell;
+use tokio::sync::RwLock;
 
-static TOKEN_PROVIDER: OnceCell<Arc<dyn TokenProvider>> = OnceCell::const_new();
+pub const DEFAULT_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// quota and billing of the requests go to this project instead of the credentials' project
+pub const QUOTA_PROJECT_HEADER: &str = "x-goog-user-project";
+
+// cached tokens are replaced this long before they expire, so a request never goes out with a
+// token that expires on the way
+const TOKEN_EXPIRY_SKEW: Duration = Duration::from_secs(60);
+
+static TOKEN_PROVIDER: RwLock<Option<Arc<dyn TokenProvider>>> = RwLock::const_new(None);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// with_request_id runs `f` with `request_id` attached, every API call made inside it sends the
+// id in the x-request-id header so it can be traced across services
+pub async fn with_request_id<F: Future>(request_id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(request_id, f).await
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
 
 // token_provider expect a enviorment variable called GOOGLE_APPLICATION_CREDENTIALS to be set
-async fn token_provider() -> &'static Arc<dyn TokenProvider> {
-    TOKEN_PROVIDER
-        .get_or_init(|| async {
-            gcp_auth::provider()
-                .await
-                .expect("unable to initialize token provider")
-        })
-        .await
+async fn token_provider() -> Result<Arc<dyn TokenProvider>, Error> {
+    if let Some(provider) = TOKEN_PROVIDER.read().await.as_ref() {
+        return Ok(provider.clone());
+    }
+    let mut provider = TOKEN_PROVIDER.write().await;
+    match provider.as_ref() {
+        Some(provider) => Ok(provider.clone()),
+        None => {
+            let new_provider = gcp_auth::provider().await.map_err(Error::ProviderError)?;
+            *provider = Some(new_provider.clone());
+            Ok(new_provider)
+        }
+    }
+}
+
+// refresh_token_provider replaces the shared provider, dropping the tokens it cached
+async fn refresh_token_provider() -> Result<Arc<dyn TokenProvider>, Error> {
+    let new_provider = gcp_auth::provider().await.map_err(Error::ProviderError)?;
+    *TOKEN_PROVIDER.write().await = Some(new_provider.clone());
+    Ok(new_provider)
+}
+
+struct CachedToken {
+    token: Arc<gcp_auth::Token>,
+    expires_at: SystemTime,
+}
+
+impl CachedToken {
+    fn fresh(&self) -> Option<Arc<gcp_auth::Token>> {
+        let left = self.expires_at.duration_since(SystemTime::now()).ok()?;
+        (left > TOKEN_EXPIRY_SKEW).then(|| self.token.clone())
+    }
 }
 
+// Client is cheap to clone, clones share the reqwest connection pool, the token provider and the
+// cached tokens
 #[derive(Clone)]
 pub struct Client {
     client: reqwest::Client,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    // tokens by the space separated scopes they were requested for
+    tokens: Arc<RwLock<HashMap<String, CachedToken>>>,
+    scopes: Vec<String>,
+    // sent on every request next to the auth headers
+    headers: reqwest::header::HeaderMap,
+    breaker: Option<Arc<CircuitBreaker>>,
+    // of every request, from sending it to reading the response body, no limit when None
+    timeout: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<ApiMetrics>,
+    #[cfg(any(test, feature = "recording"))]
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl Client {
     pub async fn new() -> Result<Self, Error> {
         let client = reqwest::Client::new();
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            token_provider: None,
+            tokens: Arc::default(),
+            scopes: vec![DEFAULT_SCOPE.to_string()],
+            headers: reqwest::header::HeaderMap::new(),
+            breaker: None,
+            timeout: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(any(test, feature = "recording"))]
+            recorder: None,
+        })
+    }
+
+    // with_scopes sets the scopes requested by the higher level clients, narrower than the
+    // default cloud-platform scope for least-privilege deployments
+    pub fn with_scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    // with_token_provider uses the given provider instead of the shared one built from
+    // GOOGLE_APPLICATION_CREDENTIALS
+    pub fn with_token_provider(token_provider: Arc<dyn TokenProvider>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            token_provider: Some(token_provider),
+            tokens: Arc::default(),
+            scopes: vec![DEFAULT_SCOPE.to_string()],
+            headers: reqwest::header::HeaderMap::new(),
+            breaker: None,
+            timeout: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(any(test, feature = "recording"))]
+            recorder: None,
+        }
+    }
+
+    // with_circuit_breaker fails the calls fast with Error::CircuitOpen while `breaker` is open,
+    // 5xx and 429 responses and transport errors count as failures
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(Arc::new(breaker));
+        self
+    }
+
+    /// # With Timeout
+    /// Fails the requests of the client, and of its clones, that take longer than `timeout`
+    /// with `Error::Timeout`. The timeout covers the whole request, from connecting to reading
+    /// the response body. Dropping the future of a call aborts its request, so callers can also
+    /// cancel a call earlier, e.g. with `tokio::time::timeout` or `tokio::select!`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
     }
 
-    async fn auth_headers(&self, scopes: &[&str]) -> Result<reqwest::header::HeaderMap, Error> {
-        let token_provider = token_provider().await;
+    // with_metrics counts and times the requests of the client, and of its clones, in `metrics`
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: ApiMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // with_fixture answers the calls with the responses recorded in the JSON file at `path`,
+    // without credentials nor network, for the tests. With VERTEX_AI_RECORD set the calls go to
+    // the API and the file is recorded again, see recording::FixtureMode.
+    #[cfg(any(test, feature = "recording"))]
+    pub fn with_fixture(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        self.with_fixture_mode(path, FixtureMode::from_env())
+    }
+
+    #[cfg(any(test, feature = "recording"))]
+    pub fn with_fixture_mode(
+        mut self,
+        path: impl AsRef<Path>,
+        mode: FixtureMode,
+    ) -> Result<Self, Error> {
+        self.recorder = Some(Arc::new(Recorder::open(path.as_ref(), mode)?));
+        Ok(self)
+    }
+
+    // with_header sends `name: value` on every request of the client, e.g. QUOTA_PROJECT_HEADER.
+    // The authorization and request id headers are always set by the client.
+    pub fn with_header(
+        mut self,
+        name: reqwest::header::HeaderName,
+        value: &str,
+    ) -> Result<Self, Error> {
+        let value = value
+            .parse()
+            .map_err(|_| Error::InvalidHeader(name.to_string()))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn scopes(&self) -> Vec<&str> {
+        self.scopes.iter().map(String::as_str).collect()
+    }
+
+    // token returns the cached token for `scopes` until it is about to expire, then fetches a
+    // new one. `refresh` skips the cache, for when the API rejected the cached token.
+    async fn token(&self, scopes: &[&str], refresh: bool) -> Result<Arc<gcp_auth::Token>, Error> {
+        let key = scopes.join(" ");
+        if !refresh {
+            if let Some(token) = self
+                .tokens
+                .read()
+                .await
+                .get(&key)
+                .and_then(CachedToken::fresh)
+            {
+                return Ok(token);
+            }
+        }
+
+        // holding the write lock while fetching makes concurrent callers wait for one fetch
+        let mut tokens = self.tokens.write().await;
+        if !refresh {
+            if let Some(token) = tokens.get(&key).and_then(CachedToken::fresh) {
+                return Ok(token);
+            }
+        }
+        let token_provider = match (&self.token_provider, refresh) {
+            (Some(token_provider), _) => token_provider.clone(),
+            (None, false) => token_provider().await?,
+            (None, true) => refresh_token_provider().await?,
+        };
         let token = token_provider
             .token(scopes)
             .await
             .map_err(Error::ProviderError)?;
-        let mut headers = reqwest::header::HeaderMap::new();
+        tokens.insert(
+            key,
+            CachedToken {
+                token: token.clone(),
+                expires_at: token.expires_at().into(),
+            },
+        );
+        Ok(token)
+    }
+
+    async fn auth_headers(
+        &self,
+        scopes: &[&str],
+        refresh: bool,
+    ) -> Result<reqwest::header::HeaderMap, Error> {
+        let token = self.token(scopes, refresh).await?;
+        let mut headers = self.headers.clone();
         headers.insert(
             reqwest::header::AUTHORIZATION,
             format!("Bearer {}", token.as_str()).parse().unwrap(),
         );
+        if let Some(request_id) = current_request_id().and_then(|id| id.parse().ok()) {
+            headers.insert(REQUEST_ID_HEADER, request_id);
+        }
         Ok(headers)
     }
 
+    // send authorizes and sends the request built by `request`. A 401 usually means the cached
+    // token expired, so the token is refreshed and the request is retried once.
+    async fn send<F>(&self, scopes: &[&str], request: F) -> Result<reqwest::Response, Error>
+    where
+        F: Fn(reqwest::header::HeaderMap) -> reqwest::RequestBuilder,
+    {
+        // replayed responses need no token
+        #[cfg(any(test, feature = "recording"))]
+        if self
+            .recorder
+            .as_ref()
+            .is_some_and(|recorder| recorder.mode() == FixtureMode::Replay)
+        {
+            return self.execute(request(self.headers.clone())).await;
+        }
+
+        let headers = self.auth_headers(scopes, false).await?;
+        let response = self.execute(request(headers)).await?;
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let headers = self.auth_headers(scopes, true).await?;
+        self.execute(request(headers)).await
+    }
+
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Error> {
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let request = request.build().map_err(Error::ClientError)?;
+        #[cfg(any(test, feature = "recording"))]
+        if let Some(recorder) = &self.recorder {
+            return match recorder.mode() {
+                FixtureMode::Replay => recorder.replay(&request),
+                FixtureMode::Record => {
+                    let recorded = recording::RecordedRequest::from(&request);
+                    let response = self.execute_request(request).await?;
+                    recorder.record(recorded, response).await
+                }
+            };
+        }
+        self.execute_request(request).await
+    }
+
+    // execute_request sends the request, counting it and timing it in the metrics of its
+    // endpoint when the client has metrics
+    async fn execute_request(&self, request: reqwest::Request) -> Result<reqwest::Response, Error> {
+        if let Some(breaker) = &self.breaker {
+            breaker.acquire()?;
+        }
+        #[cfg(feature = "metrics")]
+        let observed = self.metrics.as_ref().map(|metrics| {
+            let endpoint = metrics::endpoint_label(request.url());
+            (
+                metrics,
+                endpoint,
+                request.method().to_string(),
+                Instant::now(),
+            )
+        });
+        let result = self.client.execute(request).await;
+        if let Some(breaker) = &self.breaker {
+            breaker.record(!circuit_breaker::is_upstream_failure(&result));
+        }
+        #[cfg(feature = "metrics")]
+        if let Some((metrics, endpoint, method, start)) = observed {
+            let status = result
+                .as_ref()
+                .ok()
+                .map(|response| response.status().as_u16());
+            metrics.observe(&endpoint, &method, status, start.elapsed());
+        }
+        result.map_err(|e| match self.timeout {
+            Some(timeout) if e.is_timeout() => Error::Timeout(timeout),
+            _ => Error::ClientError(e),
+        })
+    }
+
     pub async fn api_post<T>(
         &self,
         scopes: &[&str],
@@ -54,17 +357,25 @@ impl Client {
     where
         T: serde::Serialize,
     {
-        let headers = self.auth_headers(scopes).await?;
-
-        let response = self
-            .client
-            .post(url)
-            .json(&body)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(Error::ClientError)?;
-        Ok(response)
+        self.send(scopes, |headers| {
+            self.client.post(url).json(&body).headers(headers)
+        })
+        .await
+    }
+
+    pub async fn api_patch<T>(
+        &self,
+        scopes: &[&str],
+        url: &str,
+        body: T,
+    ) -> Result<reqwest::Response, Error>
+    where
+        T: serde::Serialize,
+    {
+        self.send(scopes, |headers| {
+            self.client.patch(url).json(&body).headers(headers)
+        })
+        .await
     }
 
     pub async fn api_get_with_params(
@@ -73,21 +384,16 @@ impl Client {
         url: &str,
         params: Option<Vec<(&str, &str)>>,
     ) -> Result<reqwest::Response, Error> {
-        let headers = self.auth_headers(scopes).await?;
         let url = match params {
             None => reqwest::Url::parse(url),
             Some(ref query_params) => reqwest::Url::parse_with_params(url, query_params),
         }
         .map_err(|e| Error::UrlParseError(e.to_string()))?;
 
-        let response = self
-            .client
-            .get(url)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(Error::ClientError)?;
-        Ok(response)
+        self.send(scopes, |headers| {
+            self.client.get(url.clone()).headers(headers)
+        })
+        .await
     }
 
     pub async fn api_get(&self, scopes: &[&str], url: &str) -> Result<reqwest::Response, Error> {
@@ -100,20 +406,424 @@ impl Client {
         url: &str,
         params: Option<Vec<(&str, &str)>>,
     ) -> Result<reqwest::Response, Error> {
-        let headers = self.auth_headers(scopes).await?;
         let url = match params {
             None => reqwest::Url::parse(url),
             Some(ref query_params) => reqwest::Url::parse_with_params(url, query_params),
         }
         .map_err(|e| Error::UrlParseError(e.to_string()))?;
 
-        let response = self
-            .client
-            .delete(url)
-            .headers(headers)
-            .send()
+        self.send(scopes, |headers| {
+            self.client.delete(url.clone()).headers(headers)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // CountingTokenProvider hands out a new token on every call, like a provider after its
+    // cache has been dropped. Tokens expire after `expires_in` seconds, by default right away.
+    #[derive(Default)]
+    struct CountingTokenProvider {
+        calls: AtomicUsize,
+        scopes: Mutex<Vec<String>>,
+        expires_in: i64,
+    }
+
+    impl CountingTokenProvider {
+        fn expiring_in(expires_in: i64) -> Self {
+            Self {
+                expires_in,
+                ..Self::default()
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TokenProvider for CountingTokenProvider {
+        async fn token(&self, scopes: &[&str]) -> Result<Arc<gcp_auth::Token>, gcp_auth::Error> {
+            self.scopes
+                .lock()
+                .unwrap()
+                .extend(scopes.iter().map(|scope| scope.to_string()));
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let token = serde_json::from_value(serde_json::json!({
+                "access_token": format!("token-{}", call),
+                "expires_in": self.expires_in,
+            }))
+            .unwrap();
+            Ok(Arc::new(token))
+        }
+
+        async fn project_id(&self) -> Result<Arc<str>, gcp_auth::Error> {
+            Ok(Arc::from("project"))
+        }
+    }
+
+    type Recorded = Arc<Mutex<Vec<String>>>;
+
+    // serve answers each connection with the next canned response and records the
+    // request head of every request, lowercased.
+    async fn serve(responses: Vec<(u16, &'static str)>) -> (String, Recorded) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/resource", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 8192];
+                let read = stream.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+                recorded.lock().unwrap().push(request);
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    // header returns the value of `name` in every recorded request, empty when it was not sent
+    fn header(requests: &Recorded, name: &str) -> Vec<String> {
+        let prefix = format!("{}: ", name);
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                request
+                    .lines()
+                    .find_map(|line| line.strip_prefix(prefix.as_str()))
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_retries_once_with_refreshed_token_on_401() {
+        let expired = r#"{"error": {"code": 401, "message": "Request had invalid authentication credentials. Expected OAuth 2 access token, login cookie or other valid authentication credential.", "status": "UNAUTHENTICATED"}}"#;
+        let (url, requests) = serve(vec![(401, expired), (200, r#"{"done": true}"#)]).await;
+        let client = Client::with_token_provider(Arc::new(CountingTokenProvider::default()));
+
+        let response = client.api_get(&["scope"], &url).await.unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(
+            header(&requests, "authorization"),
+            vec!["bearer token-1", "bearer token-2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_times_out_on_a_slow_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/resource", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // reads the request and never answers it
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 8192];
+            let _ = stream.read(&mut buffer).await;
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+        let client = Client::with_token_provider(Arc::new(CountingTokenProvider::default()))
+            .with_timeout(Duration::from_secs(1));
+
+        let start = Instant::now();
+        let result = client.api_get(&["scope"], &url).await;
+
+        assert!(
+            matches!(result, Err(Error::Timeout(timeout)) if timeout == Duration::from_secs(1))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_twice() {
+        let (url, requests) = serve(vec![(401, "{}"), (401, "{}"), (200, "{}")]).await;
+        let client = Client::with_token_provider(Arc::new(CountingTokenProvider::default()));
+
+        let response = client
+            .api_post(&["scope"], &url, Value::Null)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_requests_token_for_configured_scopes() {
+        let (url, _) = serve(vec![(200, "{}")]).await;
+        let provider = Arc::new(CountingTokenProvider::default());
+        let client = Client::with_token_provider(provider.clone())
+            .with_scopes(&["https://www.googleapis.com/auth/discoveryengine.readonly"]);
+
+        client.api_get(&client.scopes(), &url).await.unwrap();
+
+        assert_eq!(
+            *provider.scopes.lock().unwrap(),
+            vec!["https://www.googleapis.com/auth/discoveryengine.readonly"]
+        );
+        assert_eq!(
+            Client::with_token_provider(provider).scopes(),
+            vec![DEFAULT_SCOPE]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sends_request_id_of_the_current_task() {
+        let (url, requests) = serve(vec![(200, "{}"), (200, "{}")]).await;
+        let client = Client::with_token_provider(Arc::new(CountingTokenProvider::default()));
+
+        with_request_id("req-42".to_string(), client.api_get(&["scope"], &url))
             .await
-            .map_err(Error::ClientError)?;
-        Ok(response)
+            .unwrap();
+        client.api_get(&["scope"], &url).await.unwrap();
+
+        assert_eq!(header(&requests, REQUEST_ID_HEADER), vec!["req-42", ""]);
+    }
+
+    #[tokio::test]
+    async fn test_reuses_token_until_it_is_about_to_expire() {
+        let (url, requests) = serve(vec![(200, "{}"); 4]).await;
+        let provider = Arc::new(CountingTokenProvider::expiring_in(3600));
+        let client = Client::with_token_provider(provider.clone());
+
+        client.api_get(&["scope"], &url).await.unwrap();
+        client.api_get(&["scope"], &url).await.unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        // a token expiring within the skew window is replaced before it is sent
+        let provider = Arc::new(CountingTokenProvider::expiring_in(30));
+        let client = Client::with_token_provider(provider.clone());
+
+        client.api_get(&["scope"], &url).await.unwrap();
+        client.api_get(&["scope"], &url).await.unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            header(&requests, "authorization"),
+            vec![
+                "bearer token-1",
+                "bearer token-1",
+                "bearer token-1",
+                "bearer token-2"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sends_custom_headers() {
+        let (url, requests) = serve(vec![(200, "{}")]).await;
+        let client = Client::with_token_provider(Arc::new(CountingTokenProvider::default()))
+            .with_header(
+                reqwest::header::HeaderName::from_static(QUOTA_PROJECT_HEADER),
+                "moni-billing",
+            )
+            .unwrap();
+
+        client
+            .api_post(&["scope"], &url, Value::Null)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            header(&requests, QUOTA_PROJECT_HEADER),
+            vec!["moni-billing"]
+        );
+        assert_eq!(header(&requests, "authorization"), vec!["bearer token-1"]);
+        assert!(client
+            .with_header(
+                reqwest::header::HeaderName::from_static(QUOTA_PROJECT_HEADER),
+                "bad\nvalue"
+            )
+            .is_err());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_requests_are_counted_by_endpoint_and_status() {
+        let (url, _) = serve(vec![(200, "{}"), (404, "{}")]).await;
+        let url = url.replace("/v1/resource", "/v1/projects/moni/operations/counted-1");
+        let registry = prometheus::Registry::new();
+        let client =
+            Client::with_token_provider(Arc::new(CountingTokenProvider::expiring_in(3600)))
+                .with_metrics(ApiMetrics::register(&registry).unwrap());
+
+        client.api_get(&["scope"], &url).await.unwrap();
+        client.api_get(&["scope"], &url).await.unwrap();
+
+        let families = registry.gather();
+        let family = |name: &str| {
+            families
+                .iter()
+                .find(|family| family.get_name() == name)
+                .unwrap()
+        };
+        let counts: Vec<(String, f64)> = family("discovery_engine_requests_total")
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels: Vec<&str> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| label.get_value())
+                    .collect();
+                (labels.join(" "), metric.get_counter().get_value())
+            })
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("operations GET 200".to_string(), 1.0),
+                ("operations GET 404".to_string(), 1.0)
+            ]
+        );
+        let duration = &family("discovery_engine_request_duration_seconds").get_metric()[0];
+        assert_eq!(duration.get_histogram().get_sample_count(), 2);
+        assert!(ApiMetrics::register(&registry).is_err());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_endpoint_labels_leave_out_resource_ids() {
+        let base = "https://discoveryengine.googleapis.com/v1/projects/moni/locations/global/collections/default_collection";
+        for (path, label) in [
+            ("/dataStores", "dataStores"),
+            ("/dataStores/moni-demo", "dataStores"),
+            (
+                "/dataStores/moni-demo/branches/0/documents:import",
+                "documents:import",
+            ),
+            (
+                "/engines/moni/servingConfigs/default_search:search",
+                "servingConfigs:search",
+            ),
+            (
+                "/dataStores/moni-demo/operations/import-1:cancel",
+                "operations:cancel",
+            ),
+        ] {
+            let url = reqwest::Url::parse(&format!("{}{}", base, path)).unwrap();
+            assert_eq!(metrics::endpoint_label(&url), label, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast_until_the_probe_succeeds() {
+        let (url, requests) = serve(vec![(503, "{}"), (503, "{}"), (200, "{}")]).await;
+        let client =
+            Client::with_token_provider(Arc::new(CountingTokenProvider::expiring_in(3600)))
+                .with_circuit_breaker(CircuitBreaker::new(2, Duration::from_millis(50)));
+
+        for _ in 0..2 {
+            let response = client.api_get(&["scope"], &url).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        }
+        // clones share the open circuit
+        let result = client.clone().api_get(&["scope"], &url).await;
+        assert!(matches!(result, Err(Error::CircuitOpen(_))));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let response = client.api_get(&["scope"], &url).await.unwrap();
+
+        assert!(response.status().is_success());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert!(!client.breaker.as_ref().unwrap().is_open());
+    }
+
+    #[tokio::test]
+    async fn test_fixture_records_then_replays_without_network() {
+        let fixture = std::env::temp_dir().join(format!(
+            "vertex_ai-fixture-{}-{:?}.json",
+            std::process::id(),
+            Instant::now()
+        ));
+        let (url, requests) = serve(vec![
+            (200, r#"{"name": "operations/import-1"}"#),
+            (200, r#"{"name": "operations/import-1", "done": true}"#),
+        ])
+        .await;
+        let recording =
+            Client::with_token_provider(Arc::new(CountingTokenProvider::expiring_in(3600)))
+                .with_fixture_mode(&fixture, FixtureMode::Record)
+                .unwrap();
+
+        let body = serde_json::json!({"gcsSource": {"inputUris": ["gs://moni/*.pdf"]}});
+        let started: Value = recording
+            .api_post(&["scope"], &url, &body)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(started["name"], "operations/import-1");
+        recording.api_get(&["scope"], &url).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // the server is gone after two requests and the replaying client has no credentials
+        let replaying = Client::new()
+            .await
+            .unwrap()
+            .with_fixture_mode(&fixture, FixtureMode::Replay)
+            .unwrap();
+
+        let response = replaying.api_post(&["scope"], &url, &body).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let done: Value = replaying
+            .api_get(&["scope"], &url)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(done["done"], true);
+
+        // every recorded response is replayed once, a different body was never recorded
+        assert!(matches!(
+            replaying.api_get(&["scope"], &url).await,
+            Err(Error::Fixture(_))
+        ));
+        let other = serde_json::json!({"gcsSource": {"inputUris": ["gs://other/*.pdf"]}});
+        assert!(matches!(
+            replaying.api_post(&["scope"], &url, &other).await,
+            Err(Error::Fixture(_))
+        ));
+        std::fs::remove_file(&fixture).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_token_provider() {
+        let (url, requests) = serve(vec![(200, "{}"), (200, "{}")]).await;
+        let provider = Arc::new(CountingTokenProvider::expiring_in(3600));
+        let client = Client::with_token_provider(provider.clone());
+        let clone = client.clone();
+
+        let (first, second) = tokio::join!(
+            client.api_get(&["scope"], &url),
+            clone.api_get(&["scope"], &url)
+        );
+
+        assert!(first.unwrap().status().is_success());
+        assert!(second.unwrap().status().is_success());
+        assert_eq!(Arc::strong_count(&provider), 3);
+        // concurrent requests wait for a single token fetch
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            header(&requests, "authorization"),
+            vec!["bearer token-1", "bearer token-1"]
+        );
     }
 }
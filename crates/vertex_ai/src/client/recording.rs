@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::error::Error;
+
+// RECORD_ENV_VAR set to anything but "" or "0" makes Client::with_fixture call the API and record
+// its responses instead of replaying them, the credentials in GOOGLE_APPLICATION_CREDENTIALS are
+// used as usual
+pub const RECORD_ENV_VAR: &str = "VERTEX_AI_RECORD";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    // answers the requests with the recorded responses, nothing is sent
+    Replay,
+    // sends the requests and writes every request/response pair to the fixture
+    Record,
+}
+
+impl FixtureMode {
+    pub fn from_env() -> Self {
+        match std::env::var(RECORD_ENV_VAR) {
+            Ok(value) if !value.is_empty() && value != "0" => FixtureMode::Record,
+            _ => FixtureMode::Replay,
+        }
+    }
+}
+
+// A fixture is a JSON array of interactions. Requests are matched on their method, url and body,
+// so the authorization headers are never recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub body: Value,
+}
+
+impl From<&reqwest::Request> for RecordedRequest {
+    fn from(request: &reqwest::Request) -> Self {
+        let body = request
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .map_or(Value::Null, body_value);
+        RecordedRequest {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            body,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub body: Value,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<reqwest::Response, Error> {
+        // the API answers with JSON, a body that was not JSON is kept as a string
+        let body = match &self.body {
+            Value::String(text) => text.clone().into_bytes(),
+            body => serde_json::to_vec(body).map_err(|e| Error::Fixture(e.to_string()))?,
+        };
+        let response = http::Response::builder()
+            .status(self.status)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(|e| Error::Fixture(e.to_string()))?;
+        Ok(reqwest::Response::from(response))
+    }
+}
+
+fn body_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+// Recorder is shared by the clones of a Client, each recorded response is replayed once, in the
+// order they were recorded, so polling the same url replays the successive states
+pub(crate) struct Recorder {
+    path: PathBuf,
+    mode: FixtureMode,
+    // the interactions and whether they were replayed already
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Recorder {
+    // open reads the fixture to replay, a recording starts from an empty fixture
+    pub(crate) fn open(path: &Path, mode: FixtureMode) -> Result<Self, Error> {
+        let interactions = match mode {
+            FixtureMode::Record => Vec::new(),
+            FixtureMode::Replay => {
+                let fixture = std::fs::read(path)
+                    .map_err(|e| Error::Fixture(format!("{}: {}", path.display(), e)))?;
+                let interactions: Vec<Interaction> = serde_json::from_slice(&fixture)
+                    .map_err(|e| Error::Fixture(format!("{}: {}", path.display(), e)))?;
+                interactions.into_iter().map(|i| (i, false)).collect()
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode,
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    pub(crate) fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    pub(crate) fn replay(&self, request: &reqwest::Request) -> Result<reqwest::Response, Error> {
+        let request = RecordedRequest::from(request);
+        let mut interactions = self.interactions.lock().unwrap();
+        let Some((interaction, replayed)) = interactions
+            .iter_mut()
+            .find(|(interaction, replayed)| !replayed && interaction.request == request)
+        else {
+            return Err(Error::Fixture(format!(
+                "no recorded response in {} for {} {} {}",
+                self.path.display(),
+                request.method,
+                request.url,
+                request.body
+            )));
+        };
+        *replayed = true;
+        interaction.response.to_response()
+    }
+
+    // record writes the fixture again with the response of `request` and hands back a copy of
+    // the response, its body was consumed to record it
+    pub(crate) async fn record(
+        &self,
+        request: RecordedRequest,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, Error> {
+        let status = response.status().as_u16();
+        let body = response.bytes().await.map_err(Error::ClientError)?;
+        let recorded = RecordedResponse {
+            status,
+            body: body_value(&body),
+        };
+        let response = recorded.to_response()?;
+
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.push((
+            Interaction {
+                request,
+                response: recorded,
+            },
+            true,
+        ));
+        let fixture: Vec<&Interaction> = interactions.iter().map(|(i, _)| i).collect();
+        let fixture =
+            serde_json::to_vec_pretty(&fixture).map_err(|e| Error::Fixture(e.to_string()))?;
+        std::fs::write(&self.path, fixture)
+            .map_err(|e| Error::Fixture(format!("{}: {}", self.path.display(), e)))?;
+        Ok(response)
+    }
+}
@@ -1,19 +1,75 @@
 use crate::discovery_engine::error::Error;
-use serde::{Deserialize, Serialize};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use futures::future;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, default};
+use std::{
+    collections::{BTreeMap, HashMap},
+    default,
+    time::Duration,
+};
 
-use crate::client::Client;
-const BASE_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+use crate::client::{Client, QUOTA_PROJECT_HEADER};
 
+// DataStoreClient is cheap to clone, clones share the http connection pool and token provider
+#[derive(Clone)]
 pub struct DataStoreClient {
     client: Client,
+    // collection of the requests that leave theirs empty, DEFAULT_COLLECTION when None
+    collection: Option<String>,
+    // location of the resources of every request, Location::Global unless set with_location
+    location: Location,
 }
 
 impl DataStoreClient {
     pub async fn new() -> Result<Self, Error> {
-        let client = Client::new().await.map_err(Error::ClientError)?;
-        Ok(Self { client })
+        let client = Client::new().await?;
+        Ok(Self::with_client(client))
+    }
+
+    // with_client builds a DataStoreClient on top of a configured Client, e.g. one built
+    // with Client::with_scopes
+    pub fn with_client(client: Client) -> Self {
+        Self {
+            client,
+            collection: None,
+            location: Location::default(),
+        }
+    }
+
+    // with_collection makes `collection` the collection of every request that leaves its
+    // `collections` empty, instead of DEFAULT_COLLECTION
+    pub fn with_collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = Some(collection.into());
+        self
+    }
+
+    // with_location sends every request to the resources in `location` and to the endpoint
+    // serving it
+    pub fn with_location(mut self, location: Location) -> Self {
+        self.location = location;
+        self
+    }
+
+    // with_quota_project charges the quota of every call to `project`, the credentials need
+    // the serviceusage.services.use permission on it
+    pub fn with_quota_project(self, project: &str) -> Result<Self, Error> {
+        let client = self.client.with_header(
+            reqwest::header::HeaderName::from_static(QUOTA_PROJECT_HEADER),
+            project,
+        )?;
+        Ok(Self {
+            client,
+            collection: self.collection,
+            location: self.location,
+        })
+    }
+
+    // collection is the collection a request is sent to: its own, else the client default,
+    // else DEFAULT_COLLECTION
+    fn collection<'a>(&'a self, requested: &'a str) -> &'a str {
+        resolve_collection(requested, self.collection.as_deref())
     }
 
     /// # Create Data Store
@@ -28,6 +84,8 @@ impl DataStoreClient {
     ///   - `data_store_id`: The identifier for the data store, conforming to RFC-1034 with a 63 character limit.
     ///   - `create_advance_site_search`: Optional boolean flag indicating whether to create an advanced data store for site search.
     ///
+    /// Use `DataStoreBuilder` to build a request with a validated `data_store_id`.
+    ///
     /// # Returns
     /// Returns an `Operation` if successful or a `VertexError` in case of an error.
     ///
@@ -35,34 +93,91 @@ impl DataStoreClient {
     /// ```
     /// ```
     ///
-    /// Note: The endpoint URL is built using the project ID, location ("global" by default), and collection name.
+    /// Note: The endpoint URL is built using the project ID, location (`Location::Global` by default), and collection name.
     pub async fn create_data_store(
         &self,
         request: CreateDataStoreRequest,
     ) -> Result<Operation, Error> {
-        let location = "global";
+        let location = self.location.as_str();
         let create_advance_site_search = request.create_advance_site_search.unwrap_or(false);
 
         let url = reqwest::Url::parse_with_params(
             format!(
-                "https://discoveryengine.googleapis.com/v1beta/projects/{}/locations/{}/collections/{}/dataStores",
-                request.project_id, location, request.collections
+                "{}/v1beta/projects/{}/locations/{}/collections/{}/dataStores",
+                self.location.endpoint(),
+                request.project_id,
+                location,
+                self.collection(&request.collections)
             )
             .as_str(),
-            &[("dataStoreId", request.data_store_id), ("createAdvancedSiteSearch", create_advance_site_search.to_string())],
+            &[
+                ("dataStoreId", request.data_store_id),
+                (
+                    "createAdvancedSiteSearch",
+                    create_advance_site_search.to_string(),
+                ),
+            ],
         );
 
         let response = self
             .client
-            .api_post(&[BASE_SCOPE], url.unwrap().as_str(), request.data_store)
-            .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
+            .api_post(
+                &self.client.scopes(),
+                url.unwrap().as_str(),
+                request.data_store,
+            )
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Create Data Store And Wait
+    /// Creates the data store like `create_data_store`, polls the creation operation with
+    /// `config` until it is done and then fetches the created data store.
+    ///
+    /// # Returns
+    /// Returns the `DataStore`, `Error::OperationFailed` if the creation failed or
+    /// `Error::OperationTimeout` if it was still running when the deadline passed.
+    pub async fn create_data_store_and_wait(
+        &self,
+        request: CreateDataStoreRequest,
+        config: PollConfig,
+    ) -> Result<DataStore, Error> {
+        create_data_store_and_wait(self, request, &config).await
+    }
 
-        let operation: Operation = response.json().await.map_err(Error::ResponseJsonParsing)?;
+    /// # Data Store Exists
+    /// Gets the data store and tells whether it was found, a 404 is `Ok(false)`.
+    ///
+    /// # Returns
+    /// Returns whether the data store exists, or the error of the get for any other failure.
+    pub async fn data_store_exists(
+        &self,
+        project_id: &str,
+        collection: &str,
+        data_store_id: &str,
+    ) -> Result<bool, Error> {
+        data_store_exists(
+            self,
+            GetDataStoreRequest {
+                collections: collection.to_string(),
+                project_id: project_id.to_string(),
+                data_store_id: data_store_id.to_string(),
+            },
+        )
+        .await
+    }
 
-        Ok(operation)
+    /// # Create Data Store If Absent
+    /// Creates the data store like `create_data_store` unless a data store with the same id
+    /// already exists in the collection, so the setup can be run more than once.
+    ///
+    /// # Returns
+    /// Returns the creation `Operation`, or None when the data store already existed.
+    pub async fn create_data_store_if_absent(
+        &self,
+        request: CreateDataStoreRequest,
+    ) -> Result<Option<Operation>, Error> {
+        create_data_store_if_absent(self, request).await
     }
 
     // Sets up a Google cloud storage data store
@@ -70,28 +185,23 @@ impl DataStoreClient {
         &self,
         request: SetupDataConnectorRequest,
     ) -> Result<SetupDataConnectorResponse, Error> {
-        let location = "global";
+        let location = self.location.as_str();
 
         let url = reqwest::Url::parse(
             format!(
-                "https://discoveryengine.googleapis.com/v1/projects/{}/locations/{}/global:setUpDataConnector",
-                request.project_id, location,
+                "{}/v1/projects/{}/locations/{}/global:setUpDataConnector",
+                self.location.endpoint(),
+                request.project_id,
+                location,
             )
-                .as_str(),
+            .as_str(),
         );
 
         let response = self
             .client
-            .api_post(&[BASE_SCOPE], url.unwrap().as_str(), request)
-            .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
-
-        let operation: SetupDataConnectorResponse =
-            response.json().await.map_err(Error::ResponseJsonParsing)?;
-
-        Ok(operation)
+            .api_post(&self.client.scopes(), url.unwrap().as_str(), request)
+            .await?;
+        parse_json(response).await
     }
 
     /// # Delete Data Store
@@ -111,7 +221,7 @@ impl DataStoreClient {
     /// # HTTP Request
     /// DELETE `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores`
     ///
-    /// The URL uses gRPC Transcoding syntax. The location is set to "global" by default.
+    /// The URL uses gRPC Transcoding syntax. The location is `Location::Global` by default.
     ///
     /// # Authorization Scopes
     /// Requires the following OAuth scope:
@@ -132,20 +242,20 @@ impl DataStoreClient {
         &self,
         request: DeleteDataStoreRequest,
     ) -> Result<Operation, Error> {
-        let location = "global";
+        let location = self.location.as_str();
         let url = format!(
-                "https://discoveryengine.googleapis.com/v1/projects/{}/locations/{}/collections/{}/dataStores/{}",
-                request.project_id, location, request.collections, request.data_store_id
-            );
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.data_store_id
+        );
         let response = self
             .client
-            .api_delete(&[BASE_SCOPE], &url, None)
-            .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
-        let operation: Operation = response.json().await.map_err(Error::ResponseJsonParsing)?;
-        Ok(operation)
+            .api_delete(&self.client.scopes(), &url, None)
+            .await?;
+        parse_json(response).await
     }
 
     /// # Get Data Store
@@ -162,8 +272,8 @@ impl DataStoreClient {
     ///  Returns a `DataStore` if successful or an `Error` in case of an error.
     //
     ///  # HTTP Request
-    ///  GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores`
-    /// The URL uses gRPC Transcoding syntax. The location is set to "global" by default.
+    ///  GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}`
+    /// The URL uses gRPC Transcoding syntax. The location is `Location::Global` by default.
     ///
     /// # Authorization Scopes
     /// Requires the following OAuth scope:
@@ -178,1342 +288,7460 @@ impl DataStoreClient {
     /// # Examples
     ///    Note: Ensure that the `request` parameter is correctly formatted with the project ID, collection, and data store ID.
     pub async fn get_data_store(&self, request: GetDataStoreRequest) -> Result<DataStore, Error> {
-        let location = "global";
+        let location = self.location.as_str();
         let url = format!(
-                "https://discoveryengine.googleapis.com/v1/projects/{}/locations/{}/collections/{}/dataStores",
-                request.project_id, location, request.collections
-            );
-        let response = self
-            .client
-            .api_get_with_params(
-                &[BASE_SCOPE],
-                &url,
-                Some([("data_store_id", request.data_store_id.as_str())].to_vec()),
-            )
-            .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
-        let data_store: DataStore = response.json().await.map_err(Error::ResponseJsonParsing)?;
-        Ok(data_store)
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.data_store_id
+        );
+        let response = self.client.api_get(&self.client.scopes(), &url).await?;
+        parse_json(response).await
     }
 
     /// # List Chunks
-    /// Lists the chunks in a document.
-    /// This function constructs and sends a GET request to the Discovery Engine's chunk listing endpoint.
+    /// Lists the chunks a document was split into, one page at a time.
     ///
     /// # Parameters
     /// - `request`: A `ListChunksRequest` containing:
-    ///  - `project_id`: The project identifier.
-    ///  - `collections`: The collection associated with the data store.
-    ///  - `data_store_id`: The identifier for the data store.
-    ///  - `branch`: The branch identifier.
-    ///  - `documet_id`: The document identifier.
+    ///   - `project_id`: The project identifier.
+    ///   - `collections`: The collection associated with the data store.
+    ///   - `data_store_id`: The identifier for the data store.
+    ///   - `branch`: The branch identifier.
+    ///   - `document_id`: The document identifier.
+    ///   - `page_size`: Optional maximum number of chunks to return, at most
+    ///     `MAX_LIST_CHUNKS_PAGE_SIZE`.
+    ///   - `page_token`: Optional `next_page_token` of the previous page.
     ///
-    ///  # Returns
-    ///  Returns a `ListChunksResponse` if successful or an `Error` in case of an error.
+    /// # Returns
+    /// Returns a `ListChunksResponse` if successful, `Error::PageSizeTooLarge` without calling the
+    /// API when `page_size` is over the limit, or an `Error` in case of an error. When
+    /// `next_page_token` is set there are more chunks to fetch.
     ///
-    ///  # HTTP Request
-    ///  GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents/{document}/chunks`
-    ///  The URL uses gRPC Transcoding syntax. The location is set to "global" by default.
-    ///  # Authorization Scopes
-    ///  Requires the following OAuth scope:
-    ///  - `https://www.googleapis.com/auth/cloud-platform`
-    ///  For more information, see the [Authentication Overview](https://cloud.google.com/docs/authentication).
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1alpha/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents/{document}/chunks`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.chunks.list`
+    pub async fn list_chunks(
+        &self,
+        request: ListChunksRequest,
+    ) -> Result<ListChunksResponse, Error> {
+        if let Some(page_size) = request.page_size {
+            check_page_size(page_size, MAX_LIST_CHUNKS_PAGE_SIZE)?;
+        }
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1alpha/projects/{}/locations/{}/collections/{}/dataStores/{}/branches/{}/documents/{}/chunks",
+            self.location.endpoint(), request.project_id, location, self.collection(&request.collections), request.data_store_id, request.branch, request.document_id
+        );
+        let page_size = request.page_size.map(|size| size.to_string());
+        let mut params = Vec::new();
+        if let Some(page_size) = &page_size {
+            params.push(("pageSize", page_size.as_str()));
+        }
+        if let Some(page_token) = &request.page_token {
+            params.push(("pageToken", page_token.as_str()));
+        }
+        let response = self
+            .client
+            .api_get_with_params(&self.client.scopes(), &url, Some(params))
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Complete Query
+    /// Suggests completions of a partial `query`, e.g. for search-as-you-type.
+    ///
+    /// # Parameters
+    /// - `request`: A `CompleteQueryRequest` containing the data store path, the typed `query`,
+    ///   an optional `query_model` (`document`, `search-history`, `user-event` or
+    ///   `document-completable`, the API picks one when unset) and
+    ///   `include_tail_suggestions` to also complete the last words of longer queries.
+    ///
+    /// # Returns
+    /// Returns a `CompleteQueryResponse`, `CompleteQueryResponse::suggestions` gives the
+    /// suggested queries in the order they should be shown.
     ///
-    ///  # IAM Permissions
-    ///  Requires the following IAM permission on the `name` resource:
-    ///  - `discoveryengine.dataStores.chunks.list`
-    ///  For more information, see the [IAM documentation](https://cloud.google.com/iam/docs/).
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}:completeQuery`
     ///
-    ///  Note: Ensure that the `request` parameter is correctly formatted with the project ID, collection, data store ID, branch, and document ID.
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `dataStore` resource:
+    /// - `discoveryengine.dataStores.completeQuery`
+    pub async fn complete_query(
+        &self,
+        request: CompleteQueryRequest,
+    ) -> Result<CompleteQueryResponse, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}:completeQuery",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.data_store_id
+        );
+        let include_tail_suggestions = request.include_tail_suggestions.to_string();
+        let mut params = vec![
+            ("query", request.query.as_str()),
+            ("includeTailSuggestions", include_tail_suggestions.as_str()),
+        ];
+        if let Some(query_model) = &request.query_model {
+            params.push(("queryModel", query_model.as_str()));
+        }
+        let response = self
+            .client
+            .api_get_with_params(&self.client.scopes(), &url, Some(params))
+            .await?;
+        parse_json(response).await
+    }
 
+    /// # Search Chunks
+    /// Searches the chunks of a data store through its `serving_config`, `default_search` when
+    /// empty, use `list_chunks` to get the chunks of a single document. A `page_size` over
+    /// `MAX_SEARCH_CHUNKS_PAGE_SIZE` is rejected with `Error::PageSizeTooLarge`.
     pub async fn search_chunks(
         &self,
         request: SearchChunksRequest,
     ) -> Result<SearchChunksResponse, Error> {
-        let location = "global";
+        if let Some(page_size) = request.page_size {
+            check_page_size(page_size, MAX_SEARCH_CHUNKS_PAGE_SIZE)?;
+        }
+        let location = self.location.as_str();
+        let serving_config = match request.serving_config.as_str() {
+            "" => "default_search",
+            serving_config => serving_config,
+        };
 
         let url = format!(
-            "https://discoveryengine.googleapis.com/v1alpha/projects/{}/locations/{}/collections/{}/dataStores/{}/servingConfigs/default_search:search",
-            request.project_id, location, request.collections, request.data_store_id
+            "{}/v1alpha/projects/{}/locations/{}/collections/{}/dataStores/{}/servingConfigs/{}:search",
+            self.location.endpoint(), request.project_id, location, self.collection(&request.collections), request.data_store_id, serving_config
         );
         let response = self
             .client
-            .api_get_with_params(&[BASE_SCOPE], &url, None)
-            .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
-        let search_chunks_response: SearchChunksResponse =
-            response.json().await.map_err(Error::ResponseJsonParsing)?;
-        Ok(search_chunks_response)
+            .api_post(&self.client.scopes(), &url, &request)
+            .await?;
+        parse_json(response).await
     }
 
+    // search rejects a page_size over MAX_SEARCH_PAGE_SIZE with Error::PageSizeTooLarge
     pub async fn search(&self, request: SearchRequest) -> Result<SearchResponse, Error> {
-        let location = "global";
-        let app_id = "moni-demo-final_1722720080773";
+        check_page_size(
+            request.discovery_engine_search_request.page_size,
+            MAX_SEARCH_PAGE_SIZE,
+        )?;
         // let data_store = "moni-demo_1722720098936";
-        let server_config = format!("projects/{}/locations/{}/collections/default_collection/engines/{}/servingConfigs/default_serving_config", request.project_id, location, app_id);
-        let url = format!(
-            "https://discoveryengine.googleapis.com/v1beta/{}:search",
-            server_config
-        );
-        let response = self
-            .client
-            .api_post(&[BASE_SCOPE], &url, request.discovery_engine_search_request)
+        let serving_config =
+            request.serving_config_name(self.location.as_str(), self.collection(""))?;
+        self.search_serving_config(&serving_config, &request.discovery_engine_search_request)
             .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
+    }
 
-        let search_response: SearchResponse =
-            response.json().await.map_err(Error::ResponseJsonParsing)?;
-        Ok(search_response)
+    /// # Search Stream
+    /// Runs `search` and yields the results one by one, fetching the next page with
+    /// `next_page_token` only once the current page has been consumed. The stream ends after
+    /// a page without a `next_page_token` or after the first error.
+    pub fn search_stream(
+        &self,
+        request: SearchRequest,
+    ) -> impl Stream<Item = Result<SearchResult, Error>> + '_ {
+        let results = request
+            .serving_config_name(self.location.as_str(), self.collection(""))
+            .map(|serving_config| {
+                search_stream(
+                    self,
+                    serving_config,
+                    request.discovery_engine_search_request,
+                )
+            });
+        // an invalid serving config is the only item of the stream
+        stream::once(future::ready(results)).try_flatten()
     }
 
     pub async fn answer(
         &self,
         request: AnswerRequest,
     ) -> Result<FeedbackAnswerQueryResponse, Error> {
-        let location = "global";
-        let app_id = "moni-demo-final_1722720080773";
-        let server_config = format!("projects/{}/locations/{}/collections/default_collection/engines/{}/servingConfigs/default_serving_config", request.project_id, location, app_id);
-        let url = format!(
-            "https://discoveryengine.googleapis.com/v1beta/{}:answer",
-            server_config
-        );
-        let response = self
-            .client
-            .api_post(&[BASE_SCOPE], &url, request.discovery_engine_answer_request)
+        let serving_config =
+            request.serving_config_name(self.location.as_str(), self.collection(""))?;
+        self.answer_serving_config(&serving_config, request.discovery_engine_answer_request)
             .await
-            .map_err(Error::ClientError)?
-            .error_for_status()
-            .map_err(Error::HttpStatus)?;
-
-        let search_response: FeedbackAnswerQueryResponse =
-            response.json().await.map_err(Error::ResponseJsonParsing)?;
-        Ok(search_response)
     }
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct FeedbackAnswerQueryResponse {
-    pub answer: Answer,
-    pub session: Session,
-    pub answer_query_token: String,
-}
+    /// # Answer With Results
+    /// Answers `query` from the given `results` instead of searching the data stores, for
+    /// callers that already have the results or use their own retriever.
+    pub async fn answer_with_results(
+        &self,
+        project_id: &str,
+        query: &str,
+        results: Vec<AnswerSearchResult>,
+    ) -> Result<FeedbackAnswerQueryResponse, Error> {
+        self.answer(AnswerRequest::with_search_results(
+            project_id, query, results,
+        ))
+        .await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Session {
-    pub name: String,
-    pub state: SessionState,
-    pub user_pseudo_id: String,
-    pub turns: Vec<Turn>,
-    pub start_time: String,
-    pub end_time: String,
-}
+    async fn search_serving_config(
+        &self,
+        serving_config: &str,
+        request: &DiscoveryEngineSearchRequest,
+    ) -> Result<SearchResponse, Error> {
+        let url = serving_config_url(&self.location, serving_config, "search");
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request)
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Turn {
-    pub query: Query,
-    pub answer: String,
-}
+    async fn answer_serving_config(
+        &self,
+        serving_config: &str,
+        request: DiscoveryEngineAnswerRequest,
+    ) -> Result<FeedbackAnswerQueryResponse, Error> {
+        let url = serving_config_url(&self.location, serving_config, "answer");
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request)
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum SessionState {
-    SateUnspecified,
-    InProgress,
-}
+    /// # Search Raw
+    /// Sends `body` verbatim as the search request of the `engine`'s default serving config and
+    /// returns the response as is. An escape hatch for request fields `search` does not model
+    /// yet, the body is not checked beyond being a JSON object.
+    ///
+    /// # Returns
+    /// Returns the JSON response, `Error::InvalidRequestBody` without calling the API when
+    /// `body` is not an object, or an `Error` in case of an error.
+    pub async fn search_raw(
+        &self,
+        project_id: &str,
+        engine: &str,
+        body: Value,
+    ) -> Result<Value, Error> {
+        search_raw(
+            self,
+            &serving_config(
+                self.location.as_str(),
+                project_id,
+                self.collection(""),
+                engine,
+            ),
+            body,
+        )
+        .await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Answer {
-    pub name: String,
-    pub state: State,
-    pub answer_text: String,
-    pub citations: Vec<Citation>,
-    pub references: Vec<AnswerReference>,
-    pub related_questions: Vec<String>,
-    pub steps: Vec<Step>,
-    pub query_understanding_info: QueryUnderstandingInfo,
-    pub answer_skipped_reasons: Vec<AnswerSkippedReason>,
-    pub create_time: String,
-    pub complete_time: String,
-}
+    async fn search_serving_config_raw(
+        &self,
+        serving_config: &str,
+        body: Value,
+    ) -> Result<Value, Error> {
+        let url = serving_config_url(&self.location, serving_config, "search");
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, body)
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum AnswerSkippedReason {
-    AnswerSkippedReasonUnspecified, // Default value. The answer skipped reason is not specified.
-    AdversarialQueryIgnored,        // The adversarial query ignored case.
-    NonAnswerSeekingQueryIgnored,   // The non-answer seeking query ignored case.
-    OutOfDomainQueryIgnored,        // The out-of-domain query ignored case.
-    PotentialPolicyViolation,       // The potential policy violation case.
-    NoRelevantContent,              // The no relevant content case.
-    JailBreakingQueryIgnored,       // The jail-breaking query ignored case.
-}
+    /// # Grounded Answer
+    /// Runs a search on the `engine` to open a session and answers `query` within that session,
+    /// with citations and related questions enabled. `safe_search` filters explicit content out
+    /// of the search results and turns on the answer safety filter.
+    ///
+    /// # Returns
+    /// Returns the `Answer`, `Answer::cited_titles` gives the titles of the cited documents.
+    pub async fn grounded_answer(
+        &self,
+        project_id: &str,
+        engine: &str,
+        query: &str,
+        safe_search: bool,
+    ) -> Result<Answer, Error> {
+        grounded_answer(
+            self,
+            &serving_config(
+                self.location.as_str(),
+                project_id,
+                self.collection(""),
+                engine,
+            ),
+            query,
+            safe_search,
+        )
+        .await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct QueryUnderstandingInfo {
-    pub query_classification_info: Vec<QueryClassificationInfo>,
-}
+    /// # Write User Event
+    /// Records an event of a user of the data store, e.g. a click on a search result, which the
+    /// engine uses to tune its results. Events carrying the `attribution_token` of the search
+    /// they come from are credited to that search.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/userEvents:write`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.userEvents.create`
+    pub async fn write_user_event(
+        &self,
+        request: WriteUserEventRequest,
+    ) -> Result<UserEvent, Error> {
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/userEvents:write",
+            self.location.endpoint(),
+            request.project_id,
+            self.location.as_str(),
+            self.collection(&request.collections),
+            request.data_store_id
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request.user_event)
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct QueryClassificationInfo {
-    #[serde(rename = "type")]
-    pub query_classification_info_type: QueryClasificationInfoType,
-    pub positive: bool,
-}
+    /// # Get Document
+    /// Retrieves a `Document` of a data store branch.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents/{document}`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `name` resource:
+    /// - `discoveryengine.documents.get`
+    pub async fn get_document(&self, request: GetDocumentRequest) -> Result<Document, Error> {
+        let response = self
+            .client
+            .api_get(
+                &self.client.scopes(),
+                &document_url(
+                    &self.location,
+                    self.collection(&request.collections),
+                    &request,
+                ),
+            )
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum QueryClasificationInfoType {
-    TypeUnspecified,       // Unspecified query classification type.
-    AdversarialQuery,      // Adversarial query classification type.
-    NonAnswerSeekingQuery, // Non-answer-seeking query classification type.
-    JailBreakingQuery,     // Jail-breaking query classification type.
-}
+    // check_schema validates the document of `request` as its `validation` asks
+    #[cfg(feature = "schema-validation")]
+    async fn check_schema(&self, request: &CreateDocumentRequest) -> Result<(), Error> {
+        match &request.validation {
+            SchemaValidation::Off => Ok(()),
+            SchemaValidation::DataStoreSchema => {
+                let schema = self
+                    .get_schema(GetSchemaRequest {
+                        collections: request.collections.clone(),
+                        project_id: request.project_id.clone(),
+                        data_store_id: request.data_store_id.clone(),
+                        schema_id: DEFAULT_SCHEMA_ID.to_string(),
+                    })
+                    .await?;
+                validate_document(&schema, &request.document)
+            }
+            SchemaValidation::Schema(schema) => validate_document(schema, &request.document),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Step {
-    pub state: State,
-    pub description: String,
-    pub thought: String,
-}
+    /// # Create Document
+    /// Creates a `Document` in a data store branch under `document_id`.
+    ///
+    /// # Parameters
+    /// - `request`: A `CreateDocumentRequest` containing the branch path, the `document` and
+    ///   its `document_id`. With `validation` set the `struct_data` or `json_data` of the
+    ///   document is checked against the data store schema first, so a mismatch is reported
+    ///   field by field instead of as an opaque API error.
+    ///
+    /// # Returns
+    /// Returns the created `Document`, `Error::SchemaViolation` listing the offending fields
+    /// without calling the API when the validation fails, or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.documents.create`
+    pub async fn create_document(&self, request: CreateDocumentRequest) -> Result<Document, Error> {
+        #[cfg(feature = "schema-validation")]
+        self.check_schema(&request).await?;
+        let location = self.location.as_str();
+        let url = reqwest::Url::parse_with_params(
+            format!(
+                "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/branches/{}/documents",
+                self.location.endpoint(),
+                request.project_id,
+                location,
+                self.collection(&request.collections),
+                request.data_store_id,
+                request.branch
+            )
+            .as_str(),
+            &[("documentId", request.document_id)],
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Action {
-    pub observation: Observation,
-    pub search_actions: Vec<SearchAction>,
-}
+        let response = self
+            .client
+            .api_post(
+                &self.client.scopes(),
+                url.unwrap().as_str(),
+                request.document,
+            )
+            .await?;
+        parse_json(response).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Observation {
-    pub search_results: Vec<SearchResult>,
-}
+    /// # Batch Get Documents
+    /// Retrieves the `Document`s with the given ids, running at most
+    /// `BATCH_GET_DOCUMENTS_CONCURRENCY` `get_document` calls at the same time.
+    ///
+    /// # Returns
+    /// Returns the documents found, in the order of `document_ids`, and the error of every id
+    /// that could not be retrieved, e.g. an `Error::ApiError` with code 404 for a missing
+    /// document. A failing id does not fail the batch.
+    pub async fn batch_get_documents(
+        &self,
+        request: BatchGetDocumentsRequest,
+    ) -> BatchGetDocumentsResponse {
+        batch_get_documents(self, request).await
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchAction {
-    pub query: String,
-}
+    /// # Purge Documents
+    /// Permanently deletes all selected `Document`s in a branch.
+    ///
+    /// # Parameters
+    /// - `request`: A `PurgeDocumentsRequest` containing the branch path and the `filter`
+    ///   selecting the documents to delete, `*` selects every document.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents:purge`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.documents.purge`
+    pub async fn purge_documents(
+        &self,
+        request: PurgeDocumentsRequest,
+    ) -> Result<Operation, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/branches/{}/documents:purge",
+            self.location.endpoint(), request.project_id, location, self.collection(&request.collections), request.data_store_id, request.branch
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request.body)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Import Documents
+    /// Bulk imports multiple `Document`s into a branch.
+    ///
+    /// # Parameters
+    /// - `request`: An `ImportDocumentsRequest` containing the branch path and the import source.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/branches/{branch}/documents:import`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.documents.import`
+    pub async fn import_documents(
+        &self,
+        request: ImportDocumentsRequest,
+    ) -> Result<Operation, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/branches/{}/documents:import",
+            self.location.endpoint(), request.project_id, location, self.collection(&request.collections), request.data_store_id, request.branch
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request.body)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Get Operation
+    /// Gets the latest state of a long running operation.
+    ///
+    /// # Parameters
+    /// - `operation_name`: The full resource name returned in `Operation::name`.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/{name}`
+    pub async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error> {
+        let url = format!("{}/v1/{}", self.location.endpoint(), operation_name);
+        let response = self.client.api_get(&self.client.scopes(), &url).await?;
+        parse_json(response).await
+    }
+
+    /// # Cancel Operation
+    /// Asks the API to cancel a long running operation, e.g. an import. Cancelling is best
+    /// effort: the operation may still finish, check it with `get_operation`.
+    ///
+    /// # Parameters
+    /// - `operation_name`: The full resource name returned in `Operation::name`.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/{name}:cancel`
+    ///
+    /// # Returns
+    /// Returns `Error::OperationNotCancellable` if the operation is already done.
+    pub async fn cancel_operation(&self, operation_name: &str) -> Result<(), Error> {
+        let url = format!("{}/v1/{}:cancel", self.location.endpoint(), operation_name);
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, serde_json::json!({}))
+            .await?;
+        parse_cancel_response(operation_name, response).await
+    }
+
+    /// # List Operations
+    /// Lists the long running operations of a resource, following every page, e.g. the imports
+    /// and purges of a data store.
+    ///
+    /// # Parameters
+    /// - `parent`: The resource the operations belong to, e.g.
+    ///   `projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}`.
+    /// - `name_prefix`: Optional prefix of the operation ids to keep, the part of the name after
+    ///   `/operations/`, e.g. `import-documents`.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/{parent}/operations`
+    ///
+    /// # Returns
+    /// Returns the operations done or still running, in the order the API lists them.
+    pub async fn list_operations(
+        &self,
+        parent: &str,
+        name_prefix: Option<&str>,
+    ) -> Result<Vec<Operation>, Error> {
+        list_operations(self, parent, name_prefix).await
+    }
+
+    async fn list_operations_page(
+        &self,
+        parent: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListOperationsResponse, Error> {
+        let url = format!("{}/v1/{}/operations", self.location.endpoint(), parent);
+        let params = page_token.map(|page_token| vec![("pageToken", page_token)]);
+        let response = self
+            .client
+            .api_get_with_params(&self.client.scopes(), &url, params)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Poll Operation
+    /// Fetches the operation every `interval` (5 seconds by default) until it is done, giving up
+    /// after `max_attempts` (60 by default).
+    ///
+    /// # Returns
+    /// Returns the finished `Operation`, `Error::OperationFailed` if it finished with an error or
+    /// `Error::OperationTimeout` if it was still running after the last attempt.
+    pub async fn poll_operation(
+        &self,
+        request: PollOperationRequest,
+        interval: Option<Duration>,
+        max_attempts: Option<u32>,
+    ) -> Result<Operation, Error> {
+        let config = PollConfig::fixed(
+            interval.unwrap_or(DEFAULT_POLL_INTERVAL),
+            max_attempts.unwrap_or(DEFAULT_POLL_ATTEMPTS),
+        );
+        self.poll_until_done(&request.operation_name, config).await
+    }
+
+    /// # Poll Until Done
+    /// Fetches any long running operation (data store creation, import, purge...) until it is
+    /// done, waiting between calls with the exponential backoff described by `config`.
+    ///
+    /// # Returns
+    /// Returns the finished `Operation`, `Error::OperationFailed` if it finished with an error or
+    /// `Error::OperationTimeout` if it was still running when the deadline passed.
+    pub async fn poll_until_done(
+        &self,
+        operation_name: &str,
+        config: PollConfig,
+    ) -> Result<Operation, Error> {
+        poll_until_done(self, operation_name, &config).await
+    }
+
+    /// # Refresh From GCS
+    /// Purges the documents of a branch matching `filter` (every document when `None`), waits
+    /// for the purge to finish and then imports the documents from the given GCS uris.
+    ///
+    /// # Returns
+    /// Returns the import `Operation`, it is not polled.
+    pub async fn refresh_from_gcs(&self, request: RefreshRequest) -> Result<Operation, Error> {
+        refresh_from_gcs(self, request).await
+    }
+
+    /// # Get Schema
+    /// Retrieves a data store `Schema`.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/schemas/{schema}`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `name` resource:
+    /// - `discoveryengine.schemas.get`
+    pub async fn get_schema(&self, request: GetSchemaRequest) -> Result<Schema, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/schemas/{}",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.data_store_id,
+            request.schema_id
+        );
+        let response = self.client.api_get(&self.client.scopes(), &url).await?;
+        parse_json(response).await
+    }
+
+    /// # Update Schema
+    /// Updates a data store `Schema`, the new schema is applied once the returned operation is done.
+    ///
+    /// # Parameters
+    /// - `request`: An `UpdateSchemaRequest` containing the schema path, the new `schema` and
+    ///   `allow_missing` to create the schema when it does not exist.
+    ///
+    /// # HTTP Request
+    /// PATCH `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/schemas/{schema}`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `name` resource:
+    /// - `discoveryengine.schemas.update`
+    pub async fn update_schema(&self, request: UpdateSchemaRequest) -> Result<Operation, Error> {
+        let location = self.location.as_str();
+        let url = reqwest::Url::parse_with_params(
+            format!(
+                "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/schemas/{}",
+                self.location.endpoint(),
+                request.project_id,
+                location,
+                self.collection(&request.collections),
+                request.data_store_id,
+                request.schema_id
+            )
+            .as_str(),
+            &[(
+                "allowMissing",
+                request.allow_missing.unwrap_or(false).to_string(),
+            )],
+        );
+
+        let response = self
+            .client
+            .api_patch(&self.client.scopes(), url.unwrap().as_str(), request.schema)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Create Engine
+    /// Creates an `Engine` (app) serving the given data stores.
+    ///
+    /// # Parameters
+    /// - `request`: A `CreateEngineRequest` containing:
+    ///   - `project_id`: The project identifier.
+    ///   - `collections`: The collection the engine is created in.
+    ///   - `engine_id`: The identifier for the engine, conforming to RFC-1034 with a 63 character limit.
+    ///   - `engine`: The engine to create.
+    ///
+    /// # Returns
+    /// Returns an `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/engines`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.engines.create`
+    pub async fn create_engine(&self, request: CreateEngineRequest) -> Result<Operation, Error> {
+        let location = self.location.as_str();
+        let url = reqwest::Url::parse_with_params(
+            format!(
+                "{}/v1/projects/{}/locations/{}/collections/{}/engines",
+                self.location.endpoint(),
+                request.project_id,
+                location,
+                self.collection(&request.collections)
+            )
+            .as_str(),
+            &[("engineId", request.engine_id)],
+        );
+
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), url.unwrap().as_str(), request.engine)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Get Engine
+    /// Retrieves an `Engine`.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/engines/{engine}`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `name` resource:
+    /// - `discoveryengine.engines.get`
+    pub async fn get_engine(&self, request: GetEngineRequest) -> Result<Engine, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/engines/{}",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.engine_id
+        );
+        let response = self.client.api_get(&self.client.scopes(), &url).await?;
+        parse_json(response).await
+    }
+
+    /// # List Engines
+    /// Lists the `Engine`s of a collection.
+    ///
+    /// # HTTP Request
+    /// GET `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/engines`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.engines.list`
+    pub async fn list_engines(
+        &self,
+        request: ListEnginesRequest,
+    ) -> Result<ListEnginesResponse, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/engines",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections)
+        );
+        let page_size = request.page_size.map(|page_size| page_size.to_string());
+        let mut params = Vec::new();
+        if let Some(page_size) = page_size.as_deref() {
+            params.push(("pageSize", page_size));
+        }
+        if let Some(page_token) = request.page_token.as_deref() {
+            params.push(("pageToken", page_token));
+        }
+        if let Some(filter) = request.filter.as_deref() {
+            params.push(("filter", filter));
+        }
+
+        let response = self
+            .client
+            .api_get_with_params(&self.client.scopes(), &url, Some(params))
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Delete Engine
+    /// Deletes an `Engine`, the data stores it serves are kept.
+    ///
+    /// # HTTP Request
+    /// DELETE `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/engines/{engine}`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `name` resource:
+    /// - `discoveryengine.engines.delete`
+    pub async fn delete_engine(&self, request: DeleteEngineRequest) -> Result<Operation, Error> {
+        let location = self.location.as_str();
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/collections/{}/engines/{}",
+            self.location.endpoint(),
+            request.project_id,
+            location,
+            self.collection(&request.collections),
+            request.engine_id
+        );
+        let response = self
+            .client
+            .api_delete(&self.client.scopes(), &url, None)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Create Target Site
+    /// Adds a URL pattern to the sites indexed by an advanced site search data store, see
+    /// `CreateDataStoreRequest::create_advance_site_search`.
+    ///
+    /// # Parameters
+    /// - `request`: A `CreateTargetSiteRequest` containing the data store path and the
+    ///   `TargetSite` to include or exclude.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/siteSearchEngine/targetSites`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.targetSites.create`
+    pub async fn create_target_site(
+        &self,
+        request: CreateTargetSiteRequest,
+    ) -> Result<Operation, Error> {
+        let url = format!(
+            "{}/v1/{}/targetSites",
+            self.location.endpoint(),
+            site_search_engine(
+                self.location.as_str(),
+                &request.project_id,
+                self.collection(&request.collections),
+                &request.data_store_id
+            )
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request.target_site)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Batch Create Target Sites
+    /// Adds several URL patterns to an advanced site search data store in one operation.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/siteSearchEngine/targetSites:batchCreate`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `parent` resource:
+    /// - `discoveryengine.targetSites.batchCreate`
+    pub async fn batch_create_target_sites(
+        &self,
+        request: BatchCreateTargetSitesRequest,
+    ) -> Result<Operation, Error> {
+        let parent = site_search_engine(
+            self.location.as_str(),
+            &request.project_id,
+            self.collection(&request.collections),
+            &request.data_store_id,
+        );
+        let url = format!(
+            "{}/v1/{}/targetSites:batchCreate",
+            self.location.endpoint(),
+            parent
+        );
+        let body = BatchCreateTargetSitesBody::new(&parent, request.target_sites);
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, body)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Recrawl URIs
+    /// Requests an on-demand recrawl of pages of a site search data store.
+    ///
+    /// # Parameters
+    /// - `request`: A `RecrawlUrisRequest` containing the data store path, the `uris` to recrawl,
+    ///   at most `MAX_RECRAWL_URIS`, and an optional `site_credential` for sites behind a login.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful, `Error::TooManyUris` without calling the
+    /// API when the limit is exceeded, or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/siteSearchEngine:recrawlUris`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `siteSearchEngine` resource:
+    /// - `discoveryengine.siteSearchEngines.recrawlUris`
+    pub async fn recrawl_uris(&self, request: RecrawlUrisRequest) -> Result<Operation, Error> {
+        if request.body.uris.len() > MAX_RECRAWL_URIS {
+            return Err(Error::TooManyUris(
+                request.body.uris.len(),
+                MAX_RECRAWL_URIS,
+            ));
+        }
+        let url = format!(
+            "{}/v1/{}:recrawlUris",
+            self.location.endpoint(),
+            site_search_engine(
+                self.location.as_str(),
+                &request.project_id,
+                self.collection(&request.collections),
+                &request.data_store_id
+            )
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request.body)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Enable Advanced Site Search
+    /// Turns advanced site search on for an existing site search data store, the equivalent of
+    /// creating it with `CreateDataStoreRequest::create_advance_site_search`.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/siteSearchEngine:enableAdvancedSiteSearch`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `siteSearchEngine` resource:
+    /// - `discoveryengine.siteSearchEngines.enableAdvancedSiteSearch`
+    pub async fn enable_advanced_site_search(
+        &self,
+        request: AdvancedSiteSearchRequest,
+    ) -> Result<Operation, Error> {
+        self.set_advanced_site_search(&request, true).await
+    }
+
+    /// # Disable Advanced Site Search
+    /// Turns advanced site search off for an existing site search data store.
+    ///
+    /// # Returns
+    /// Returns a long running `Operation` if successful or an `Error` in case of an error.
+    ///
+    /// # HTTP Request
+    /// POST `https://discoveryengine.googleapis.com/v1/projects/{project}/locations/{location}/collections/{collection}/dataStores/{dataStore}/siteSearchEngine:disableAdvancedSiteSearch`
+    ///
+    /// # IAM Permissions
+    /// Requires the following IAM permission on the `siteSearchEngine` resource:
+    /// - `discoveryengine.siteSearchEngines.disableAdvancedSiteSearch`
+    pub async fn disable_advanced_site_search(
+        &self,
+        request: AdvancedSiteSearchRequest,
+    ) -> Result<Operation, Error> {
+        self.set_advanced_site_search(&request, false).await
+    }
+
+    async fn set_advanced_site_search(
+        &self,
+        request: &AdvancedSiteSearchRequest,
+        enabled: bool,
+    ) -> Result<Operation, Error> {
+        let url = advanced_site_search_url(
+            &self.location,
+            self.collection(&request.collections),
+            request,
+            enabled,
+        );
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, serde_json::json!({}))
+            .await?;
+        parse_json(response).await
+    }
+}
+
+const DEFAULT_APP_ID: &str = "moni-demo-final_1722720080773";
+
+/// Collection of the requests that leave `collections` empty, unless the client was built
+/// `with_collection`.
+pub const DEFAULT_COLLECTION: &str = "default_collection";
+
+/// Location of the resources a `DataStoreClient` calls, `Global` unless it was built
+/// `with_location`. The locations other than `Global` are served from their own endpoint, e.g.
+/// `https://us-discoveryengine.googleapis.com` for `Us`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Location {
+    #[default]
+    Global,
+    Us,
+    Eu,
+    /// A location the enum does not list yet, e.g. a region added to the API.
+    Custom(String),
+}
+
+impl Location {
+    /// The location as written in resource names, e.g. `global` in
+    /// `projects/{project}/locations/global/collections/{collection}`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Location::Global => "global",
+            Location::Us => "us",
+            Location::Eu => "eu",
+            Location::Custom(location) => location,
+        }
+    }
+
+    /// Base URL of the API serving the location.
+    pub fn endpoint(&self) -> String {
+        match self {
+            Location::Global => "https://discoveryengine.googleapis.com".to_string(),
+            location => format!(
+                "https://{}-discoveryengine.googleapis.com",
+                location.as_str()
+            ),
+        }
+    }
+}
+
+impl From<&str> for Location {
+    fn from(location: &str) -> Self {
+        match location {
+            "global" => Location::Global,
+            "us" => Location::Us,
+            "eu" => Location::Eu,
+            location => Location::Custom(location.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Serving config `search` and `answer` use when the request does not name one.
+pub const DEFAULT_SERVING_CONFIG: &str = "default_serving_config";
+
+/// Maximum number of URIs accepted by a single `recrawl_uris` call.
+pub const MAX_RECRAWL_URIS: usize = 10_000;
+
+/// Largest `page_size` accepted by `search` and `search_stream`.
+pub const MAX_SEARCH_PAGE_SIZE: u32 = 100;
+
+/// Largest `page_size` accepted by `search_chunks`.
+pub const MAX_SEARCH_CHUNKS_PAGE_SIZE: i32 = 100;
+
+/// Largest `page_size` accepted by `list_chunks`.
+pub const MAX_LIST_CHUNKS_PAGE_SIZE: i32 = 1000;
+
+// check_page_size fails before the request is sent, the API would reject it anyway
+fn check_page_size<T: Into<i64>>(page_size: T, max: T) -> Result<(), Error> {
+    let (page_size, max) = (page_size.into(), max.into());
+    if page_size > max {
+        return Err(Error::PageSizeTooLarge(page_size, max));
+    }
+    Ok(())
+}
+
+// ErrorResponse is the body Google APIs send back with a non-2xx status
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: Status,
+}
+
+// parse_json deserializes a successful response into `T`. A failed request becomes
+// Error::ApiError with the status sent by the API, or with the raw body when it is not a Google
+// error, e.g. a 502 from a proxy. An exhausted quota becomes Error::QuotaExceeded instead.
+async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, Error> {
+    let status = response.status();
+    let retry_after = retry_after_header(&response);
+    let body = response
+        .text()
+        .await
+        .map_err(Error::ResponseTextRetrieval)?;
+    if !status.is_success() {
+        let error = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(response) => response.error,
+            Err(_) => Status {
+                code: i32::from(status.as_u16()),
+                message: body,
+                status: String::new(),
+                details: Vec::new(),
+            },
+        };
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || error.status == "RESOURCE_EXHAUSTED"
+        {
+            return Err(Error::QuotaExceeded {
+                retry_after: retry_after.or_else(|| error.retry_delay()),
+            });
+        }
+        return Err(Error::ApiError(error));
+    }
+    serde_json::from_str(&body).map_err(|source| Error::InvalidResponse { source, body })
+}
+
+// retry_after_header reads a Retry-After header in seconds, the HTTP date form is not sent by
+// the API
+fn retry_after_header(response: &reqwest::Response) -> Option<Duration> {
+    let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)?;
+    let seconds = retry_after.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+// validate_document checks the struct data of `document` against the JSON schema of the data
+// store, every violation is reported with the path of the offending field. A document without
+// struct data or a schema without a JSON schema has nothing to check.
+#[cfg(feature = "schema-validation")]
+fn validate_document(schema: &Schema, document: &Document) -> Result<(), Error> {
+    let json_schema = match (&schema.struct_schema, &schema.json_schema) {
+        (Some(struct_schema), _) => struct_schema.clone(),
+        (None, Some(json_schema)) => {
+            serde_json::from_str(json_schema).map_err(|e| Error::InvalidSchema(e.to_string()))?
+        }
+        (None, None) => return Ok(()),
+    };
+    let data = match &document.data {
+        Some(DocumentData::StructData { struct_data }) => struct_data.clone(),
+        Some(DocumentData::JsonData { json_data }) => serde_json::from_str(json_data)
+            .map_err(|e| Error::SchemaViolation(vec![format!("jsonData is not JSON: {}", e)]))?,
+        None => return Ok(()),
+    };
+    let validator =
+        jsonschema::validator_for(&json_schema).map_err(|e| Error::InvalidSchema(e.to_string()))?;
+    let violations: Vec<String> = validator
+        .iter_errors(&data)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{}: {}", path, error)
+            }
+        })
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::SchemaViolation(violations))
+    }
+}
+
+fn document_url(location: &Location, collection: &str, request: &GetDocumentRequest) -> String {
+    format!(
+        "{}/v1/projects/{}/locations/{}/collections/{}/dataStores/{}/branches/{}/documents/{}",
+        location.endpoint(),
+        request.project_id,
+        location.as_str(),
+        collection,
+        request.data_store_id,
+        request.branch,
+        request.document_id
+    )
+}
+
+fn site_search_engine(
+    location: &str,
+    project_id: &str,
+    collections: &str,
+    data_store_id: &str,
+) -> String {
+    format!(
+        "projects/{}/locations/{}/collections/{}/dataStores/{}/siteSearchEngine",
+        project_id, location, collections, data_store_id
+    )
+}
+
+fn advanced_site_search_url(
+    location: &Location,
+    collection: &str,
+    request: &AdvancedSiteSearchRequest,
+    enabled: bool,
+) -> String {
+    let method = if enabled {
+        "enableAdvancedSiteSearch"
+    } else {
+        "disableAdvancedSiteSearch"
+    };
+    format!(
+        "{}/v1/{}:{}",
+        location.endpoint(),
+        site_search_engine(
+            location.as_str(),
+            &request.project_id,
+            collection,
+            &request.data_store_id
+        ),
+        method
+    )
+}
+
+fn resolve_collection<'a>(requested: &'a str, client_default: Option<&'a str>) -> &'a str {
+    if !requested.is_empty() {
+        return requested;
+    }
+    client_default
+        .filter(|collection| !collection.is_empty())
+        .unwrap_or(DEFAULT_COLLECTION)
+}
+
+/// Resource name of a data store, as expected by `DiscoveryEngineSearchRequest::add_data_store`.
+/// An empty `collection` is DEFAULT_COLLECTION.
+pub fn data_store_name(
+    location: &Location,
+    project_id: &str,
+    collection: &str,
+    data_store_id: &str,
+) -> String {
+    format!(
+        "projects/{}/locations/{}/collections/{}/dataStores/{}",
+        project_id,
+        location.as_str(),
+        resolve_collection(collection, None),
+        data_store_id
+    )
+}
+
+fn serving_config(location: &str, project_id: &str, collection: &str, engine_id: &str) -> String {
+    serving_config_with_id(
+        location,
+        project_id,
+        collection,
+        engine_id,
+        DEFAULT_SERVING_CONFIG,
+    )
+}
+
+fn serving_config_with_id(
+    location: &str,
+    project_id: &str,
+    collection: &str,
+    engine_id: &str,
+    serving_config_id: &str,
+) -> String {
+    format!(
+        "projects/{}/locations/{}/collections/{}/engines/{}/servingConfigs/{}",
+        project_id, location, collection, engine_id, serving_config_id
+    )
+}
+
+// request_serving_config is the serving config named by a request, DEFAULT_SERVING_CONFIG when
+// it names none. A blank name is rejected rather than sent as an empty path segment.
+fn request_serving_config(
+    location: &str,
+    project_id: &str,
+    collection: &str,
+    serving_config_id: Option<&str>,
+) -> Result<String, Error> {
+    let serving_config_id = serving_config_id.unwrap_or(DEFAULT_SERVING_CONFIG);
+    if serving_config_id.trim().is_empty() {
+        return Err(Error::MissingField("serving_config"));
+    }
+    Ok(serving_config_with_id(
+        location,
+        project_id,
+        collection,
+        DEFAULT_APP_ID,
+        serving_config_id,
+    ))
+}
+
+fn serving_config_url(location: &Location, serving_config: &str, method: &str) -> String {
+    format!(
+        "{}/v1beta/{}:{}",
+        location.endpoint(),
+        serving_config,
+        method
+    )
+}
+
+// RawSearcher is the call search_raw is built on.
+pub(crate) trait RawSearcher {
+    async fn search_serving_config_raw(
+        &self,
+        serving_config: &str,
+        body: Value,
+    ) -> Result<Value, Error>;
+}
+
+impl RawSearcher for DataStoreClient {
+    async fn search_serving_config_raw(
+        &self,
+        serving_config: &str,
+        body: Value,
+    ) -> Result<Value, Error> {
+        DataStoreClient::search_serving_config_raw(self, serving_config, body).await
+    }
+}
+
+async fn search_raw<T: RawSearcher>(
+    client: &T,
+    serving_config: &str,
+    body: Value,
+) -> Result<Value, Error> {
+    if !body.is_object() {
+        return Err(Error::InvalidRequestBody(body.to_string()));
+    }
+    client.search_serving_config_raw(serving_config, body).await
+}
+
+// AnswerOperations are the calls grounded_answer is built on.
+pub(crate) trait AnswerOperations {
+    async fn search_serving_config(
+        &self,
+        serving_config: &str,
+        request: &DiscoveryEngineSearchRequest,
+    ) -> Result<SearchResponse, Error>;
+    async fn answer_serving_config(
+        &self,
+        serving_config: &str,
+        request: DiscoveryEngineAnswerRequest,
+    ) -> Result<FeedbackAnswerQueryResponse, Error>;
+}
+
+impl AnswerOperations for DataStoreClient {
+    async fn search_serving_config(
+        &self,
+        serving_config: &str,
+        request: &DiscoveryEngineSearchRequest,
+    ) -> Result<SearchResponse, Error> {
+        DataStoreClient::search_serving_config(self, serving_config, request).await
+    }
+
+    async fn answer_serving_config(
+        &self,
+        serving_config: &str,
+        request: DiscoveryEngineAnswerRequest,
+    ) -> Result<FeedbackAnswerQueryResponse, Error> {
+        DataStoreClient::answer_serving_config(self, serving_config, request).await
+    }
+}
+
+async fn grounded_answer<T: AnswerOperations>(
+    client: &T,
+    serving_config: &str,
+    query: &str,
+    safe_search: bool,
+) -> Result<Answer, Error> {
+    let (answer, _) = answer_in_session(client, serving_config, None, query, safe_search).await?;
+    Ok(answer)
+}
+
+// answer_in_session searches and answers `query` within `session`, a new session is opened when
+// it is None. Returns the answer and the name of the session it was given in.
+async fn answer_in_session<T: AnswerOperations>(
+    client: &T,
+    serving_config: &str,
+    session: Option<&str>,
+    query: &str,
+    safe_search: bool,
+) -> Result<(Answer, String), Error> {
+    let engine = serving_config
+        .split("/servingConfigs/")
+        .next()
+        .unwrap_or(serving_config);
+    let session = match session {
+        Some(session) => session.to_string(),
+        None => format!("{}/sessions/-", engine),
+    };
+    let search = client
+        .search_serving_config(
+            serving_config,
+            &DiscoveryEngineSearchRequest {
+                query: query.to_string(),
+                page_size: 10,
+                session,
+                safe_search,
+                ..Default::default()
+            },
+        )
+        .await?;
+    let session_info = search.session_info.ok_or(Error::MissingSessionInfo)?;
+
+    let response = client
+        .answer_serving_config(
+            serving_config,
+            DiscoveryEngineAnswerRequest {
+                query: Query {
+                    query_id: session_info.query_id,
+                    text: query.to_string(),
+                },
+                session: session_info.name.clone(),
+                safety_spec: SafetySpec {
+                    enable: safe_search,
+                },
+                related_questions_spec: RelatedQuestionsSpec { enable: true },
+                answer_generation_spec: AnswerGenerationSpec {
+                    include_citations: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok((response.answer, session_info.name))
+}
+
+/// # Conversation
+/// Asks questions to an engine within one session, so follow-up questions like "and in Bogotá?"
+/// are answered in the context of the previous ones. The session is opened by the first `ask`.
+pub struct Conversation {
+    client: DataStoreClient,
+    serving_config: String,
+    safe_search: bool,
+    session: Option<String>,
+}
+
+impl Conversation {
+    pub fn new(client: DataStoreClient, project_id: &str, engine: &str, safe_search: bool) -> Self {
+        Conversation {
+            serving_config: serving_config(
+                client.location.as_str(),
+                project_id,
+                client.collection(""),
+                engine,
+            ),
+            client,
+            safe_search,
+            session: None,
+        }
+    }
+
+    /// Name of the current session, `None` until the first question is asked.
+    pub fn session(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    /// Answers `query` like `DataStoreClient::grounded_answer`, in the session of the previous
+    /// questions.
+    pub async fn ask(&mut self, query: &str) -> Result<Answer, Error> {
+        let client = self.client.clone();
+        self.ask_with(&client, query).await
+    }
+
+    /// Forgets the session, the next question starts a new one.
+    pub fn reset(&mut self) {
+        self.session = None;
+    }
+
+    async fn ask_with<T: AnswerOperations>(
+        &mut self,
+        client: &T,
+        query: &str,
+    ) -> Result<Answer, Error> {
+        let (answer, session) = answer_in_session(
+            client,
+            &self.serving_config,
+            self.session.as_deref(),
+            query,
+            self.safe_search,
+        )
+        .await?;
+        self.session = Some(session);
+        Ok(answer)
+    }
+}
+
+fn search_stream<T: AnswerOperations>(
+    client: &T,
+    serving_config: String,
+    request: DiscoveryEngineSearchRequest,
+) -> impl Stream<Item = Result<SearchResult, Error>> + '_ {
+    // the state is the request for the next page, None once the last page was fetched
+    stream::try_unfold(Some(request), move |request| {
+        let serving_config = serving_config.clone();
+        async move {
+            let Some(mut request) = request else {
+                return Ok(None);
+            };
+            check_page_size(request.page_size, MAX_SEARCH_PAGE_SIZE)?;
+            let response = client
+                .search_serving_config(&serving_config, &request)
+                .await?;
+            let results = response.results.unwrap_or_default();
+            let next = match response.next_page_token {
+                Some(token) if !token.is_empty() => {
+                    request.page_token = token;
+                    Some(request)
+                }
+                _ => None,
+            };
+            Ok::<_, Error>(Some((stream::iter(results.into_iter().map(Ok)), next)))
+        }
+    })
+    .try_flatten()
+}
+
+// OperationReader is the call poll_until_done is built on.
+pub(crate) trait OperationReader {
+    async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error>;
+}
+
+impl OperationReader for DataStoreClient {
+    async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error> {
+        DataStoreClient::get_operation(self, operation_name).await
+    }
+}
+
+// OperationLister is the call list_operations pages through.
+pub(crate) trait OperationLister {
+    async fn list_operations_page(
+        &self,
+        parent: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListOperationsResponse, Error>;
+}
+
+impl OperationLister for DataStoreClient {
+    async fn list_operations_page(
+        &self,
+        parent: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListOperationsResponse, Error> {
+        DataStoreClient::list_operations_page(self, parent, page_token).await
+    }
+}
+
+// DocumentOperations are the calls refresh_from_gcs is built on, kept behind a trait so the
+// sequencing can be tested without reaching the API.
+pub(crate) trait DocumentOperations: OperationReader {
+    async fn purge_documents(&self, request: PurgeDocumentsRequest) -> Result<Operation, Error>;
+    async fn import_documents(&self, request: ImportDocumentsRequest) -> Result<Operation, Error>;
+}
+
+impl DocumentOperations for DataStoreClient {
+    async fn purge_documents(&self, request: PurgeDocumentsRequest) -> Result<Operation, Error> {
+        DataStoreClient::purge_documents(self, request).await
+    }
+
+    async fn import_documents(&self, request: ImportDocumentsRequest) -> Result<Operation, Error> {
+        DataStoreClient::import_documents(self, request).await
+    }
+}
+
+// DataStoreOperations are the calls create_data_store_and_wait is built on.
+pub(crate) trait DataStoreOperations: OperationReader {
+    async fn create_data_store(&self, request: CreateDataStoreRequest) -> Result<Operation, Error>;
+    async fn get_data_store(&self, request: GetDataStoreRequest) -> Result<DataStore, Error>;
+}
+
+impl DataStoreOperations for DataStoreClient {
+    async fn create_data_store(&self, request: CreateDataStoreRequest) -> Result<Operation, Error> {
+        DataStoreClient::create_data_store(self, request).await
+    }
+
+    async fn get_data_store(&self, request: GetDataStoreRequest) -> Result<DataStore, Error> {
+        DataStoreClient::get_data_store(self, request).await
+    }
+}
+
+async fn create_data_store_and_wait<T: DataStoreOperations>(
+    client: &T,
+    request: CreateDataStoreRequest,
+    config: &PollConfig,
+) -> Result<DataStore, Error> {
+    let get_request = GetDataStoreRequest {
+        collections: request.collections.clone(),
+        project_id: request.project_id.clone(),
+        data_store_id: request.data_store_id.clone(),
+    };
+    let mut operation = client.create_data_store(request).await?;
+    if !operation.done {
+        operation = poll_until_done(client, &operation.name, config).await?;
+    }
+    // the creation can also fail right away, without being polled
+    if let Some(status) = operation.error {
+        return Err(Error::OperationFailed(operation.name, status.message));
+    }
+    client.get_data_store(get_request).await
+}
+
+async fn data_store_exists<T: DataStoreOperations>(
+    client: &T,
+    request: GetDataStoreRequest,
+) -> Result<bool, Error> {
+    match client.get_data_store(request).await {
+        Ok(_) => Ok(true),
+        Err(Error::ApiError(status)) if status.code == 404 => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+async fn create_data_store_if_absent<T: DataStoreOperations>(
+    client: &T,
+    request: CreateDataStoreRequest,
+) -> Result<Option<Operation>, Error> {
+    let get_request = GetDataStoreRequest {
+        collections: request.collections.clone(),
+        project_id: request.project_id.clone(),
+        data_store_id: request.data_store_id.clone(),
+    };
+    if data_store_exists(client, get_request).await? {
+        return Ok(None);
+    }
+    client.create_data_store(request).await.map(Some)
+}
+
+// DocumentReader is the call batch_get_documents is built on.
+pub(crate) trait DocumentReader {
+    async fn get_document(&self, request: GetDocumentRequest) -> Result<Document, Error>;
+}
+
+impl DocumentReader for DataStoreClient {
+    async fn get_document(&self, request: GetDocumentRequest) -> Result<Document, Error> {
+        DataStoreClient::get_document(self, request).await
+    }
+}
+
+pub const BATCH_GET_DOCUMENTS_CONCURRENCY: usize = 8;
+
+async fn batch_get_documents<T: DocumentReader>(
+    client: &T,
+    request: BatchGetDocumentsRequest,
+) -> BatchGetDocumentsResponse {
+    let results: Vec<(String, Result<Document, Error>)> = stream::iter(request.document_ids)
+        .map(|document_id| {
+            let get = client.get_document(GetDocumentRequest {
+                project_id: request.project_id.clone(),
+                collections: request.collections.clone(),
+                data_store_id: request.data_store_id.clone(),
+                branch: request.branch.clone(),
+                document_id: document_id.clone(),
+            });
+            async move { (document_id, get.await) }
+        })
+        .buffered(BATCH_GET_DOCUMENTS_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut response = BatchGetDocumentsResponse {
+        documents: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (document_id, result) in results {
+        match result {
+            Ok(document) => response.documents.push(document),
+            Err(error) => response.errors.push(DocumentError { document_id, error }),
+        }
+    }
+    response
+}
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_POLL_ATTEMPTS: u32 = 60;
+
+/// Backoff used while polling long running operations: the first wait is `initial_delay`, each
+/// following one is multiplied by `multiplier` up to `max_delay`, and polling stops once
+/// `deadline` has elapsed, or after `max_attempts` polls when it is set.
+#[derive(Debug, Clone)]
+pub struct PollConfig {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub deadline: Duration,
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        PollConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            deadline: Duration::from_secs(10 * 60),
+            max_attempts: None,
+        }
+    }
+}
+
+impl PollConfig {
+    // fixed waits the same interval between attempts and polls at most `attempts` times, the
+    // waits alone take `interval * (attempts - 1)` but every poll takes some time too, so the
+    // deadline is only a backstop
+    pub fn fixed(interval: Duration, attempts: u32) -> Self {
+        PollConfig {
+            initial_delay: interval,
+            max_delay: interval,
+            multiplier: 1.0,
+            deadline: PollConfig::default().deadline.max(interval * attempts),
+            max_attempts: Some(attempts),
+        }
+    }
+
+    fn next_delay(&self, delay: Duration) -> Duration {
+        delay.mul_f64(self.multiplier.max(1.0)).min(self.max_delay)
+    }
+}
+
+async fn poll_until_done<T: OperationReader>(
+    client: &T,
+    operation_name: &str,
+    config: &PollConfig,
+) -> Result<Operation, Error> {
+    let deadline = tokio::time::Instant::now() + config.deadline;
+    let mut delay = config.initial_delay.min(config.max_delay);
+    let mut attempts = 0;
+    loop {
+        let operation = client.get_operation(operation_name).await?;
+        attempts += 1;
+        if operation.done {
+            return match operation.error {
+                Some(status) => Err(Error::OperationFailed(operation.name, status.message)),
+                None => Ok(operation),
+            };
+        }
+        let last_attempt = config.max_attempts.is_some_and(|max| attempts >= max);
+        if last_attempt || tokio::time::Instant::now() + delay > deadline {
+            return Err(Error::OperationTimeout(operation_name.to_string()));
+        }
+        tokio::time::sleep(delay).await;
+        delay = config.next_delay(delay);
+    }
+}
+
+// parse_cancel_response reads the empty body of a cancelled operation, the API refuses to cancel
+// an operation that is already done with FAILED_PRECONDITION
+async fn parse_cancel_response(
+    operation_name: &str,
+    response: reqwest::Response,
+) -> Result<(), Error> {
+    match parse_json::<Value>(response).await {
+        Ok(_) => Ok(()),
+        Err(Error::ApiError(status)) if status.status == "FAILED_PRECONDITION" => Err(
+            Error::OperationNotCancellable(operation_name.to_string(), status.message),
+        ),
+        Err(e) => Err(e),
+    }
+}
+
+async fn list_operations<T: OperationLister>(
+    client: &T,
+    parent: &str,
+    name_prefix: Option<&str>,
+) -> Result<Vec<Operation>, Error> {
+    let mut operations = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let page = client
+            .list_operations_page(parent, page_token.as_deref())
+            .await?;
+        operations.extend(page.operations.into_iter().filter(|operation| {
+            name_prefix.is_none_or(|prefix| operation.id().starts_with(prefix))
+        }));
+        match page.next_page_token {
+            Some(token) if !token.is_empty() => page_token = Some(token),
+            _ => return Ok(operations),
+        }
+    }
+}
+
+async fn refresh_from_gcs<T: DocumentOperations>(
+    client: &T,
+    request: RefreshRequest,
+) -> Result<Operation, Error> {
+    let purge = client
+        .purge_documents(PurgeDocumentsRequest {
+            project_id: request.project_id.clone(),
+            collections: request.collections.clone(),
+            data_store_id: request.data_store_id.clone(),
+            branch: request.branch.clone(),
+            body: PurgeDocumentsBody {
+                filter: request.filter.unwrap_or_else(|| "*".to_string()),
+                force: true,
+            },
+        })
+        .await?;
+    if !purge.done {
+        let config = request.poll_config.unwrap_or_default();
+        poll_until_done(client, &purge.name, &config).await?;
+    }
+
+    client
+        .import_documents(ImportDocumentsRequest {
+            project_id: request.project_id,
+            collections: request.collections,
+            data_store_id: request.data_store_id,
+            branch: request.branch,
+            body: ImportDocumentsBody {
+                gcs_source: Some(GcsSource {
+                    input_uris: request.gcs_input_uris,
+                    data_schema: request.data_schema,
+                }),
+                reconciliation_mode: Some(ReconciliationMode::Incremental),
+            },
+        })
+        .await
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackAnswerQueryResponse {
+    #[serde(default)]
+    pub answer: Answer,
+    #[serde(default)]
+    pub session: Session,
+    #[serde(default)]
+    pub answer_query_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub state: SessionState,
+    #[serde(default)]
+    pub user_pseudo_id: String,
+    #[serde(default)]
+    pub turns: Vec<Turn>,
+    #[serde(default)]
+    pub start_time: String,
+    #[serde(default)]
+    pub end_time: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Turn {
+    #[serde(default)]
+    pub query: Query,
+    #[serde(default)]
+    pub answer: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SessionState {
+    InProgress,
+    #[serde(rename = "STATE_UNSPECIFIED")]
+    #[default]
+    #[serde(other)]
+    SateUnspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Answer {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub state: State,
+    #[serde(default)]
+    pub answer_text: String,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    #[serde(default)]
+    pub references: Vec<AnswerReference>,
+    #[serde(default)]
+    pub related_questions: Vec<String>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    #[serde(default)]
+    pub query_understanding_info: QueryUnderstandingInfo,
+    #[serde(default)]
+    pub answer_skipped_reasons: Vec<AnswerSkippedReason>,
+    #[serde(default)]
+    pub create_time: String,
+    #[serde(default)]
+    pub complete_time: String,
+}
+
+impl Answer {
+    /// The answer is complete once it succeeded or failed, `Unspecified` and `InProgress`
+    /// answers may still change.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.state, State::Succeeded | State::Failed)
+    }
+
+    /// The step to show as the progress of the answer: the first one that did not succeed,
+    /// i.e. the step in progress or the one that failed, or the last step once all succeeded.
+    pub fn current_step(&self) -> Option<&Step> {
+        self.steps
+            .iter()
+            .find(|step| step.state != State::Succeeded)
+            .or(self.steps.last())
+    }
+
+    /// Titles of the documents cited in the answer, in citation order and without duplicates.
+    pub fn cited_titles(&self) -> Vec<String> {
+        let mut titles: Vec<String> = Vec::new();
+        for citation in self.resolved_citations() {
+            if !titles.contains(&citation.title) {
+                titles.push(citation.title);
+            }
+        }
+        titles
+    }
+
+    /// Joins every citation source with the `AnswerReference` its `reference_index` points at.
+    /// A citation with several sources yields one `ResolvedCitation` per source, sources whose
+    /// index is not a number or is out of range are skipped.
+    pub fn resolved_citations(&self) -> Vec<ResolvedCitation> {
+        let mut resolved = Vec::new();
+        for citation in &self.citations {
+            for source in citation.sources.iter().flatten() {
+                let Ok(reference_index) = source.reference_index.parse::<usize>() else {
+                    continue;
+                };
+                let Some(reference) = self.references.get(reference_index) else {
+                    continue;
+                };
+                let info = &reference.unstructured_document_info;
+                resolved.push(ResolvedCitation {
+                    start_index: citation.start_index.parse().unwrap_or_default(),
+                    end_index: citation.end_index.parse().unwrap_or_default(),
+                    reference_index,
+                    title: info.tittle.clone(),
+                    uri: info.uri.clone(),
+                    snippet: reference.snippet(),
+                });
+            }
+        }
+        resolved
+    }
+}
+
+/// A span of `Answer::answer_text`, `start_index..end_index`, with the document it cites.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedCitation {
+    pub start_index: usize,
+    pub end_index: usize,
+    pub reference_index: usize,
+    pub title: String,
+    pub uri: String,
+    pub snippet: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AnswerSkippedReason {
+    AdversarialQueryIgnored,      // The adversarial query ignored case.
+    NonAnswerSeekingQueryIgnored, // The non-answer seeking query ignored case.
+    OutOfDomainQueryIgnored,      // The out-of-domain query ignored case.
+    PotentialPolicyViolation,     // The potential policy violation case.
+    NoRelevantContent,            // The no relevant content case.
+    JailBreakingQueryIgnored,     // The jail-breaking query ignored case.
+    #[serde(other)]
+    AnswerSkippedReasonUnspecified, // Default value. The answer skipped reason is not specified.
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryUnderstandingInfo {
+    #[serde(default)]
+    pub query_classification_info: Vec<QueryClassificationInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryClassificationInfo {
+    #[serde(default)]
+    #[serde(rename = "type")]
+    pub query_classification_info_type: QueryClasificationInfoType,
+    #[serde(default)]
+    pub positive: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueryClasificationInfoType {
+    AdversarialQuery,      // Adversarial query classification type.
+    NonAnswerSeekingQuery, // Non-answer-seeking query classification type.
+    JailBreakingQuery,     // Jail-breaking query classification type.
+    #[default]
+    #[serde(other)]
+    TypeUnspecified, // Unspecified query classification type.
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    #[serde(default)]
+    pub state: State,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub thought: String,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+/// What a step of the answer did: searched the data stores or only reasoned about the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Thought,
+    Search,
+}
+
+impl Step {
+    pub fn kind(&self) -> StepKind {
+        if self.search_queries().is_empty() {
+            StepKind::Thought
+        } else {
+            StepKind::Search
+        }
+    }
+
+    /// Queries the step ran against the data stores, in order.
+    pub fn search_queries(&self) -> Vec<&str> {
+        self.actions
+            .iter()
+            .filter_map(|action| action.search_action.as_ref())
+            .map(|search| search.query.as_str())
+            .collect()
+    }
+
+    /// A step is finished once it succeeded or failed.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Succeeded | State::Failed)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Action {
+    pub search_action: Option<SearchAction>,
+    pub observation: Option<Observation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Observation {
+    #[serde(default)]
+    pub search_results: Vec<SearchResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchAction {
+    pub query: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservationSearchResult {
+    #[serde(default)]
+    pub document: String,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub snippet_info: SnipetInfo,
+    #[serde(default)]
+    pub chunk_info: ObservationSearchResultChunkInfo,
+    #[serde(default)]
+    pub struct_data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SnipetInfo {
+    #[serde(default)]
+    pub snippet: String,
+    #[serde(default)]
+    pub snippet_status: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservationSearchResultChunkInfo {
+    #[serde(default)]
+    pub chunk: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub relevance_score: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerReference {
+    #[serde(default)]
+    pub unstructured_document_info: UnstructureDocumentInfo,
+    #[serde(default)]
+    pub chunk_info: ChunkInfo,
+    #[serde(default)]
+    pub structured_document_info: StructuredDocumentInfo,
+}
+
+impl AnswerReference {
+    // snippet prefers the cited chunk and falls back to the extracted segments of the document
+    fn snippet(&self) -> Option<String> {
+        let info = &self.unstructured_document_info;
+        [
+            Some(&self.chunk_info.content),
+            info.extractive_segments.first().map(|s| &s.content),
+            info.extractive_answer.first().map(|a| &a.content),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|content| !content.is_empty())
+        .cloned()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StructuredDocumentInfo {
+    #[serde(default)]
+    pub document: String,
+    #[serde(default)]
+    pub struct_data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerChunkInfo {
+    #[serde(default)]
+    pub chunk: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub document_metadata: AnswerDocumentMetadata,
+    #[serde(default)]
+    pub relevance_score: f64, // Using f64 to r
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerDocumentMetadata {
+    #[serde(default)]
+    pub document: String,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub page_identifier: String,
+    #[serde(default)]
+    pub struct_data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerUnstructureDocumentInfo {
+    #[serde(default)]
+    pub document: String,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub chunk_contents: Vec<AnswerChunkContent>,
+    #[serde(default)]
+    pub struct_data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerChunkContent {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub page_identifier: String,
+    #[serde(default)]
+    pub relevance_score: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum State {
+    InProgress,
+    Failed,
+    Succeeded,
+    // the API sends STATE_UNSPECIFIED. The unspecified variants are last in the enums of the
+    // responses, serde(other) reads the values added to the API since as unspecified
+    #[serde(rename = "STATE_UNSPECIFIED", alias = "UNSPECIFIED")]
+    #[default]
+    #[serde(other)]
+    Unspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnswerRequest {
+    pub project_id: String,
+    /// Serving config of the engine to answer with, `DEFAULT_SERVING_CONFIG` when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serving_config: Option<String>,
+    pub discovery_engine_answer_request: DiscoveryEngineAnswerRequest,
+}
+
+impl AnswerRequest {
+    fn serving_config_name(&self, location: &str, collection: &str) -> Result<String, Error> {
+        request_serving_config(
+            location,
+            &self.project_id,
+            collection,
+            self.serving_config.as_deref(),
+        )
+    }
+
+    /// Request answering `query` from `results`, the answer API does not run a search for it.
+    pub fn with_search_results(
+        project_id: &str,
+        query: &str,
+        results: Vec<AnswerSearchResult>,
+    ) -> Self {
+        AnswerRequest {
+            project_id: project_id.to_string(),
+            serving_config: None,
+            discovery_engine_answer_request: DiscoveryEngineAnswerRequest {
+                query: Query {
+                    query_id: String::new(),
+                    text: query.to_string(),
+                },
+                search_spec: SearchSpec {
+                    search_params: None,
+                    search_result_list: Some(SearchResultList {
+                        search_results: results,
+                    }),
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryEngineAnswerRequest {
+    pub query: Query,
+    pub session: String,
+    pub safety_spec: SafetySpec,
+    pub related_questions_spec: RelatedQuestionsSpec,
+    pub answer_generation_spec: AnswerGenerationSpec,
+    pub search_spec: SearchSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSpec {
+    // at most one of them is sent, the answer API searches with search_params, or answers from
+    // search_result_list without searching
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_params: Option<SearchParams>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_result_list: Option<SearchResultList>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResultList {
+    pub search_results: Vec<AnswerSearchResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerSearchResult {
+    pub unstructured_document_info: UnstructureDocumentInfo,
+    pub chunk_info: ChunkInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkInfo {
+    #[serde(default)]
+    pub chunk: String,
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnstructureDocumentInfo {
+    #[serde(default)]
+    pub document: String,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    #[serde(rename = "title")]
+    pub tittle: String,
+    #[serde(default)]
+    pub document_context: Vec<DocumentContext>,
+    #[serde(default)]
+    pub extractive_segments: Vec<ExtractiveSegments>,
+    #[serde(default)]
+    pub extractive_answer: Vec<ExtractiveAnswer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractiveSegments {
+    #[serde(default)]
+    pub page_identifier: String,
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentContext {
+    #[serde(default)]
+    pub page_identifier: String,
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchParams {
+    pub max_return_results: i32,
+    pub filter: String,
+    pub boost_spec: BoostSpec,
+    pub order_by: String,
+    pub search_result_mode: SearchResultMode,
+    pub data_store_spec: Vec<DataStoreSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BoostControlSpec {
+    pub field_name: String,
+    pub attribute_type: AttributeType,
+    pub interpolation_type: InterpolationType,
+    pub control_points: Vec<ControlPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerGenerationSpec {
+    pub model_spec: ModelSpec,
+    pub prompt_spec: ModelPromptSpec,
+    pub include_citations: bool,
+    pub answer_language_code: String,
+    pub ignore_adversarial_query: bool,
+    pub ignore_non_answer_seeking_query: bool,
+    pub ignore_low_relevant_content: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RelatedQuestionsSpec {
+    pub enable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetySpec {
+    pub enable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Query {
+    pub query_id: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListDocumentsResponse {
+    #[serde(default)]
+    documents: Vec<Document>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub id: String,
+    pub content: Option<Content>,
+    pub parent_document_id: Option<String>,
+    pub derived_struct_data: Option<DerivedStructData>,
+    pub acl_info: Option<AclInfo>,
+    pub index_time: Option<String>,
+    #[serde(flatten)]
+    pub data: Option<DocumentData>,
+}
+
+impl Document {
+    /// The field called `key` of the document, looked up in its `structData` first, then in the
+    /// fields the engine derived, e.g. `title` or `link`. The snippets and extractive answers
+    /// are not fields, see `DerivedStructData`.
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        let struct_data = match &self.data {
+            Some(DocumentData::StructData { struct_data }) => struct_data.get(key),
+            _ => None,
+        };
+        struct_data.or_else(|| self.derived_struct_data.as_ref()?.get(key))
+    }
+
+    /// The field called `key` when it is a string.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.field(key)?.as_str()
+    }
+
+    /// The field called `key` when it is a number, numbers sent as strings included.
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.field(key)? {
+            Value::String(number) => number.parse().ok(),
+            value => value.as_f64(),
+        }
+    }
+
+    /// The title of the document, from its struct data or the title the engine derived from an
+    /// unstructured document. An empty title is no title.
+    pub fn title(&self) -> Option<&str> {
+        self.get_str("title").filter(|title| !title.is_empty())
+    }
+}
+
+/// Data the engine derived from an unstructured document: the snippets and extractive answers
+/// the search asked for, next to fields like `title` and `link`.
+// the keys of derivedStructData are snake_case
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DerivedStructData {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snippets: Vec<Snippet>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extractive_answers: Vec<ExtractiveAnswer>,
+    #[serde(flatten)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl DerivedStructData {
+    /// The derived field called `key`, e.g. `title` or `link`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(flatten)]
+    pub content: Option<ContentData>,
+}
+
+impl Content {
+    /// Builds an inline `Content`, encoding `bytes` as the base64 string expected by `rawBytes`.
+    pub fn from_raw_bytes(mime_type: &str, bytes: &[u8]) -> Self {
+        Content {
+            mime_type: mime_type.to_string(),
+            content: Some(ContentData::RawBytes {
+                raw_bytes: BASE64_STANDARD.encode(bytes),
+            }),
+        }
+    }
+
+    /// Decodes the inline `rawBytes` content, returns `None` when the content is not inline.
+    pub fn raw_bytes(&self) -> Option<Result<Vec<u8>, Error>> {
+        match &self.content {
+            Some(ContentData::RawBytes { raw_bytes }) => Some(
+                BASE64_STANDARD
+                    .decode(raw_bytes)
+                    .map_err(Error::ContentDecoding),
+            ),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ContentData {
+    RawBytes {
+        #[serde(rename = "rawBytes")]
+        raw_bytes: String,
+    },
+    Uri {
+        uri: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AclInfo {
+    readers: Option<Vec<AccessRestriction>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessRestriction {
+    pub principals: Option<Vec<Principal>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Principal {
+    #[serde(flatten)]
+    pub principal: Option<PrincipalType>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PrincipalType {
+    UserId { user_id: String },
+    GroupId { group_id: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum DocumentData {
+    StructData { struct_data: serde_json::Value },
+    JsonData { json_data: String },
+}
+pub struct SearchRequest {
+    pub project_id: String,
+    /// Serving config of the engine to search with, `DEFAULT_SERVING_CONFIG` when None.
+    pub serving_config: Option<String>,
+    pub discovery_engine_search_request: DiscoveryEngineSearchRequest,
+}
+
+impl SearchRequest {
+    fn serving_config_name(&self, location: &str, collection: &str) -> Result<String, Error> {
+        request_serving_config(
+            location,
+            &self.project_id,
+            collection,
+            self.serving_config.as_deref(),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    pub results: Option<Vec<SearchResult>>,
+    pub facets: Option<Vec<Facet>>,
+    pub guided_search_result: Option<GuidedSearchResult>,
+    pub total_size: Option<i32>,
+    pub attribution_token: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub next_page_token: Option<String>,
+    pub corrected_query: Option<String>,
+    pub summary: Option<Summary>,
+    pub applied_controls: Option<Vec<String>>,
+    pub geo_search_debug_info: Option<Vec<GeoSearchDebugInfo>>,
+    pub query_expansion_info: Option<QueryExpansionInfo>,
+    pub natural_language_query_understanding_info: Option<NaturalLanguageQueryUnderstandingInfo>,
+    pub session_info: Option<SessionInfo>,
+}
+
+impl SearchResponse {
+    /// Token identifying this search, to be sent with the user events it leads to, see
+    /// `UserEvent::attributed_to`.
+    pub fn attribution(&self) -> Option<&str> {
+        self.attribution_token
+            .as_deref()
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Whether the engine expanded the query, the results then include documents matching only
+    /// part of it.
+    pub fn was_query_expanded(&self) -> bool {
+        self.query_expansion_info
+            .as_ref()
+            .is_some_and(|info| info.expanded_query)
+    }
+
+    /// The facet returned for `key`, if the request asked for it.
+    pub fn facet(&self, key: &str) -> Option<&Facet> {
+        self.facets.as_ref()?.iter().find(|facet| facet.key == key)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NaturalLanguageQueryUnderstandingInfo {
+    pub extracted_filters: Option<String>,
+    pub rewritten_query: Option<String>,
+    pub structured_extracted_filter: Option<StructuredExtractedFilter>,
+}
+
+impl NaturalLanguageQueryUnderstandingInfo {
+    /// The structured extracted filter rendered as a search filter, e.g.
+    /// `year >= 2020 AND (country: ANY("Colombia") OR country: ANY("Peru"))`, if one was
+    /// extracted.
+    pub fn to_filter_string(&self) -> Option<String> {
+        let expression = self
+            .structured_extracted_filter
+            .as_ref()?
+            .expression
+            .as_ref()?;
+        Some(expression.to_filter_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StructuredExtractedFilter {
+    pub expression: Option<Expression>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all_fields = "camelCase")]
+#[serde(untagged)]
+pub enum Expression {
+    StringConstraint {
+        string_constraint: StringConstraint,
+    },
+    NumberConstraint {
+        number_constraint: NumberConstraint,
+    },
+    GeolocationConstraint {
+        geolocation_constraint: GeolocationConstraint,
+    },
+    AndExpr {
+        and_expr: AndExpression,
+    },
+    OrExpr {
+        or_expr: OrExpression,
+    },
+}
+
+impl Expression {
+    /// Renders the expression with the filter syntax of `DiscoveryEngineSearchRequest::filter`,
+    /// nested AND and OR expressions are parenthesized.
+    pub fn to_filter_string(&self) -> String {
+        match self {
+            Expression::StringConstraint { string_constraint } => {
+                let values: Vec<String> = string_constraint
+                    .values
+                    .iter()
+                    .map(|value| quote_filter_value(value))
+                    .collect();
+                format!(
+                    "{}: ANY({})",
+                    string_constraint.field_name,
+                    values.join(", ")
+                )
+            }
+            Expression::NumberConstraint { number_constraint } => {
+                let operator = match number_constraint.comparison {
+                    Comparison::ComparisonUnspecified | Comparison::Equals => "=",
+                    Comparison::LessThanEquals => "<=",
+                    Comparison::LessThan => "<",
+                    Comparison::GreaterThanEquals => ">=",
+                    Comparison::GreaterThan => ">",
+                };
+                format!(
+                    "{} {} {}",
+                    number_constraint.field_name, operator, number_constraint.value
+                )
+            }
+            Expression::GeolocationConstraint {
+                geolocation_constraint,
+            } => format!(
+                "GEO_DISTANCE({}, {}, {})",
+                geolocation_constraint.field_name,
+                quote_filter_value(&geolocation_constraint.address),
+                geolocation_constraint.radius_in_meters
+            ),
+            Expression::AndExpr { and_expr } => join_expressions(&and_expr.expressions, " AND "),
+            Expression::OrExpr { or_expr } => join_expressions(&or_expr.expressions, " OR "),
+        }
+    }
+}
+
+fn join_expressions(expressions: &[Expression], operator: &str) -> String {
+    expressions
+        .iter()
+        .map(|expression| match expression {
+            Expression::AndExpr { .. } | Expression::OrExpr { .. } => {
+                format!("({})", expression.to_filter_string())
+            }
+            _ => expression.to_filter_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(operator)
+}
+
+fn quote_filter_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StringConstraint {
+    pub field_name: String,
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberConstraint {
+    pub field_name: String,
+    #[serde(default)]
+    pub comparison: Comparison,
+    #[serde(default)]
+    pub value: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Comparison {
+    Equals,
+    LessThanEquals,
+    LessThan,
+    GreaterThanEquals,
+    GreaterThan,
+    #[default]
+    #[serde(other)]
+    ComparisonUnspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeolocationConstraint {
+    pub field_name: String,
+    pub address: String,
+    #[serde(default)]
+    pub radius_in_meters: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AndExpression {
+    #[serde(default)]
+    pub expressions: Vec<Expression>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrExpression {
+    #[serde(default)]
+    pub expressions: Vec<Expression>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryExpansionInfo {
+    #[serde(default)]
+    pub expanded_query: bool,
+    pub pinned_result_count: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoSearchDebugInfo {
+    #[serde(default)]
+    pub original_address_query: String,
+    #[serde(default)]
+    pub error_message: String,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub summary_text: Option<String>,
+    pub summary_skipped_reasons: Option<Vec<SummarySkippedReason>>,
+    pub safety_attributes: Option<SafetyAttributes>,
+    pub summary_with_metadata: Option<SummaryWithMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[allow(clippy::enum_variant_names)]
+pub enum SummarySkippedReason {
+    AdversarialQueryIgnored,
+    NonSummarySeekingQueryIgnored,
+    OutOfDomainQueryIgnored,
+    PotentialPolicyViolation,
+    LlmAddonNotEnabled,
+    #[default]
+    #[serde(other)]
+    SummarySkippedReasonUnspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyAttributes {
+    pub categories: Option<Vec<String>>,
+    pub scores: Option<Vec<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryWithMetadata {
+    #[serde(default)]
+    pub summary: String,
+    pub citation_metadata: Option<CitationMetadata>,
+    pub references: Option<Vec<Reference>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CitationMetadata {
+    pub citations: Option<Vec<Citation>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Citation {
+    #[serde(default)]
+    pub start_index: String,
+    #[serde(default)]
+    pub end_index: String,
+    pub sources: Option<Vec<CitationSource>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CitationSource {
+    #[serde(default)]
+    pub reference_index: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub document: String,
+    pub uri: Option<String>,
+    pub chunk_contents: Option<Vec<ChunkContent>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkContent {
+    #[serde(default)]
+    pub content: String,
+    pub page_identifier: Option<String>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GuidedSearchResult {
+    pub refinement_attributes: Option<Vec<RefinementAttribute>>,
+    pub follow_up_questions: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RefinementAttribute {
+    #[serde(default)]
+    pub attribute_key: String,
+    #[serde(default)]
+    pub attribute_value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Facet {
+    pub key: String,
+    #[serde(default)]
+    pub values: Vec<FacetValue>,
+    #[serde(default)]
+    pub dynamic_facet: bool,
+}
+
+impl Facet {
+    /// Builds the filter clause selecting the values of this facet the user picked, matched by
+    /// `FacetValue::label`. Text values become `key: ANY("a", "b")`, intervals are OR-ed
+    /// `key: IN(min, max)` ranges. Returns an empty string when nothing is selected.
+    pub fn to_filter(&self, selected_values: &[&str]) -> String {
+        let selected = self
+            .values
+            .iter()
+            .filter(|value| selected_values.contains(&value.label().as_str()));
+        let mut values = Vec::new();
+        let mut intervals = Vec::new();
+        for value in selected {
+            match &value.facet_value {
+                FacetValueType::Value { value } => values.push(format!(
+                    "\"{}\"",
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                )),
+                FacetValueType::Interval { interval } => {
+                    intervals.push(format!("{}: {}", self.key, interval.to_filter()))
+                }
+            }
+        }
+
+        let mut clauses = Vec::new();
+        if !values.is_empty() {
+            clauses.push(format!("{}: ANY({})", self.key, values.join(", ")));
+        }
+        match intervals.len() {
+            0 => {}
+            1 => clauses.append(&mut intervals),
+            _ => clauses.push(format!("({})", intervals.join(" OR "))),
+        }
+        clauses.join(" AND ")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FacetValue {
+    #[serde(default)]
+    pub count: String,
+    #[serde(flatten)]
+    pub facet_value: FacetValueType,
+}
+
+impl FacetValue {
+    /// Text shown for the value in a filter UI, the value itself or the interval as `min-max`
+    /// with `*` for an open bound.
+    pub fn label(&self) -> String {
+        match &self.facet_value {
+            FacetValueType::Value { value } => value.clone(),
+            FacetValueType::Interval { interval } => {
+                let bound = |inclusive: Option<f64>, exclusive: Option<f64>| {
+                    inclusive
+                        .or(exclusive)
+                        .map_or("*".to_string(), |bound| bound.to_string())
+                };
+                format!(
+                    "{}-{}",
+                    bound(interval.minimum, interval.exclusive_minimum),
+                    bound(interval.maximum, interval.exclusive_maximum)
+                )
+            }
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum FacetValueType {
+    Value { value: String },
+    Interval { interval: Interval },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub id: Option<String>,
+    pub document: Option<Document>,
+    pub chunk: Option<Chunk>,
+    // BTreeMaps keep the fields of results in the same order every time they are iterated
+    pub model_scores: Option<BTreeMap<String, DoubleList>>,
+}
+
+impl SearchResult {
+    /// First value of the model score called `name`, e.g. `relevance`, if the API returned it.
+    pub fn score(&self, name: &str) -> Option<f64> {
+        self.model_scores
+            .as_ref()?
+            .get(name)?
+            .values
+            .as_ref()?
+            .first()
+            .copied()
+    }
+
+    pub fn relevance(&self) -> Option<f64> {
+        self.score("relevance")
+    }
+
+    /// Extractive answers of the result document, present when the search request asked for
+    /// them in `ExtractiveContentSpec`.
+    pub fn extractive_answers(&self) -> Vec<&ExtractiveAnswer> {
+        self.derived_struct_data()
+            .map(|derived| derived.extractive_answers.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// The text to show for the result: its first generated snippet, or its first extractive
+    /// answer when no snippet is available.
+    pub fn best_snippet(&self) -> Option<&str> {
+        let derived = self.derived_struct_data()?;
+        derived
+            .snippets
+            .iter()
+            .find(|snippet| snippet.is_success())
+            .map(|snippet| snippet.snippet.as_str())
+            .or_else(|| {
+                derived
+                    .extractive_answers
+                    .first()
+                    .map(|answer| answer.content.as_str())
+            })
+    }
+
+    fn derived_struct_data(&self) -> Option<&DerivedStructData> {
+        self.document.as_ref()?.derived_struct_data.as_ref()
+    }
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DoubleList {
+    pub values: Option<Vec<f64>>,
+}
+// snippet_status of the snippets that were generated, NO_SNIPPET_AVAILABLE otherwise
+const SNIPPET_STATUS_SUCCESS: &str = "SUCCESS";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    // derivedStructData sends snippet_status
+    #[serde(default)]
+    #[serde(alias = "snippet_status")]
+    pub snippet_status: String,
+    #[serde(default)]
+    pub snippet: String,
+}
+
+/// Text shown in place of a snippet the API could not generate.
+pub const NO_SNIPPET_TEXT: &str = "No preview is available for this result.";
+
+impl Snippet {
+    /// A generated snippet of `text`, e.g. the best snippet of a search result.
+    pub fn new(text: &str) -> Self {
+        Snippet {
+            snippet_status: SNIPPET_STATUS_SUCCESS.to_string(),
+            snippet: text.to_string(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.snippet_status == SNIPPET_STATUS_SUCCESS && !self.snippet.trim().is_empty()
+    }
+
+    /// The snippet without its `<b>` highlighting and with HTML entities decoded, or
+    /// `NO_SNIPPET_TEXT` when no snippet was generated.
+    pub fn plain_text(&self) -> String {
+        if !self.is_success() {
+            return NO_SNIPPET_TEXT.to_string();
+        }
+        render_snippet(&self.snippet, false)
+    }
+
+    /// The snippet as HTML that can be rendered unescaped: the `<b>` highlighting is kept and
+    /// any other markup is escaped. `NO_SNIPPET_TEXT` when no snippet was generated.
+    pub fn html(&self) -> String {
+        if !self.is_success() {
+            return NO_SNIPPET_TEXT.to_string();
+        }
+        render_snippet(&self.snippet, true)
+    }
+}
+
+// render_snippet drops the tags of a snippet, keeping the <b> highlighting when `html` is set, in
+// which case the text is escaped again once its entities are decoded
+fn render_snippet(snippet: &str, html: bool) -> String {
+    let mut rendered = String::with_capacity(snippet.len());
+    let mut highlighted = false;
+    let mut rest = snippet;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        push_snippet_text(&mut rendered, &rest[..start], html);
+        let tag = rest[start + 1..end].trim().to_ascii_lowercase();
+        if html && tag == "b" && !highlighted {
+            rendered.push_str("<b>");
+            highlighted = true;
+        } else if html && tag == "/b" && highlighted {
+            rendered.push_str("</b>");
+            highlighted = false;
+        }
+        rest = &rest[end + 1..];
+    }
+    push_snippet_text(&mut rendered, rest, html);
+    if highlighted {
+        rendered.push_str("</b>");
+    }
+    rendered
+}
+
+fn push_snippet_text(rendered: &mut String, text: &str, html: bool) {
+    let text = decode_entities(text);
+    if !html {
+        rendered.push_str(&text);
+        return;
+    }
+    for c in text.chars() {
+        match c {
+            '&' => rendered.push_str("&amp;"),
+            '<' => rendered.push_str("&lt;"),
+            '>' => rendered.push_str("&gt;"),
+            '"' => rendered.push_str("&quot;"),
+            '\'' => rendered.push_str("&#39;"),
+            c => rendered.push(c),
+        }
+    }
+}
+
+// decode_entities decodes the named entities the API escapes snippets with and numeric ones,
+// anything else is kept as is
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[1..end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractiveAnswer {
+    #[serde(default)]
+    pub page_number: String,
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub query_id: String,
+}
+
+pub struct WriteUserEventRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub user_event: UserEvent,
+}
+
+/// The user event types the engine understands.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserEventType {
+    Search,
+    /// A document was viewed, e.g. after a click on a search result.
+    ViewItem,
+    ViewItemList,
+    ViewHomePage,
+    ViewCategoryPage,
+    AddToCart,
+    Purchase,
+    MediaPlay,
+    MediaComplete,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEvent {
+    pub event_type: UserEventType,
+    pub user_pseudo_id: String,
+    /// RFC 3339 time of the event, the time it was received when `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_info: Option<UserInfo>,
+    /// `SearchResponse::attribution_token` of the search the event comes from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub documents: Vec<DocumentInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_info: Option<SearchInfo>,
+}
+
+impl UserEvent {
+    pub fn new(event_type: UserEventType, user_pseudo_id: &str) -> Self {
+        UserEvent {
+            event_type,
+            user_pseudo_id: user_pseudo_id.to_string(),
+            event_time: None,
+            user_info: None,
+            attribution_token: None,
+            documents: Vec::new(),
+            search_info: None,
+        }
+    }
+
+    /// Credits the event to the search that returned `response`.
+    pub fn attributed_to(mut self, response: &SearchResponse) -> Self {
+        self.attribution_token = response.attribution().map(String::from);
+        self
+    }
+
+    pub fn with_document(mut self, document_id: &str) -> Self {
+        self.documents.push(DocumentInfo {
+            id: Some(document_id.to_string()),
+            ..Default::default()
+        });
+        self
+    }
+}
+
+/// A document of a user event, identified by one of its `id`, `name` or `uri`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchInfo {
+    pub search_query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryEngineSearchRequest {
+    pub branch: String,
+    pub query: String,
+    pub image_query: ImageQuery,
+    pub page_size: u32,
+    pub page_token: String,
+    pub offset: u32,
+    pub data_store_specs: Vec<DataStoreSpec>,
+    pub filter: String,
+    pub canonical_filter: String,
+    pub order_by: String,
+    pub user_info: UserInfo,
+    pub language_code: String,
+    pub facet_specs: Vec<FacetSpec>,
+    pub boost_spec: BoostSpec,
+    pub params: HashMap<String, Value>,
+    pub query_expansion_spec: QueryExpansionSpec,
+    pub spell_correction_spec: SpellCorrectionSpec,
+    pub user_pseudo_id: String,
+    pub content_search_spec: ContentSearchSpec,
+    pub safe_search: bool,
+    pub user_labels: HashMap<String, Value>,
+    pub search_as_you_type_spec: SearchAsYouTypeSpec,
+    pub session: String,
+    pub session_spec: SessionSpec,
+}
+
+impl DiscoveryEngineSearchRequest {
+    /// Also searches `data_store`, the resource name of a data store of the engine, see
+    /// `data_store_name`. The results of every data store added are blended in a single list,
+    /// without any the engine searches all of its data stores.
+    pub fn add_data_store(mut self, data_store: impl Into<String>) -> Self {
+        self.data_store_specs.push(DataStoreSpec {
+            data_store: data_store.into(),
+        });
+        self
+    }
+}
+
+/// Builds the `order_by` of search and list requests, e.g. `update_time desc,title`, checking
+/// every field against the fields the caller knows to be orderable so a typo fails before the
+/// request is sent. Ascending is the API default, so ascending fields are written without a
+/// suffix.
+#[derive(Debug, Clone)]
+pub struct OrderBy<'a> {
+    orderable_fields: &'a [&'a str],
+    clauses: Vec<(String, bool)>,
+}
+
+impl<'a> OrderBy<'a> {
+    pub fn new(orderable_fields: &'a [&'a str]) -> Self {
+        Self {
+            orderable_fields,
+            clauses: Vec::new(),
+        }
+    }
+
+    pub fn asc(mut self, field: impl Into<String>) -> Self {
+        self.clauses.push((field.into(), false));
+        self
+    }
+
+    pub fn desc(mut self, field: impl Into<String>) -> Self {
+        self.clauses.push((field.into(), true));
+        self
+    }
+
+    /// Returns the comma separated clauses, or `Error::UnknownOrderByField` for the first
+    /// field that is not orderable.
+    pub fn build(&self) -> Result<String, Error> {
+        let mut clauses = Vec::with_capacity(self.clauses.len());
+        for (field, descending) in &self.clauses {
+            if !self.orderable_fields.contains(&field.as_str()) {
+                return Err(Error::UnknownOrderByField(field.clone()));
+            }
+            if *descending {
+                clauses.push(format!("{} desc", field));
+            } else {
+                clauses.push(field.clone());
+            }
+        }
+        Ok(clauses.join(","))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSpec {
+    pub query_id: String,
+    pub search_result_persistence_count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchAsYouTypeSpec {
+    pub condition: SearchAsYouTypeCondition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentSearchSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet_spec: Option<SnippetSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_spec: Option<SummarySpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_spec: Option<ChunkSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extractive_content_spec: Option<ExtractiveContentSpec>,
+    pub search_result_mode: SearchResultMode,
+}
+
+impl ContentSearchSpec {
+    /// Document results with one snippet each.
+    pub fn snippets_only() -> Self {
+        ContentSearchSpec {
+            search_result_mode: SearchResultMode::Documents,
+            ..Default::default()
+        }
+        .with_snippets()
+    }
+
+    /// Chunk results, each with `previous` and `next` of the chunks around it.
+    pub fn chunks(previous: i32, next: i32) -> Self {
+        ContentSearchSpec {
+            chunk_spec: Some(ChunkSpec {
+                num_previous_chunks: Some(previous),
+                num_next_chunks: Some(next),
+            }),
+            search_result_mode: SearchResultMode::Chunks,
+            ..Default::default()
+        }
+    }
+
+    /// Also returns one snippet per result.
+    pub fn with_snippets(mut self) -> Self {
+        self.snippet_spec = Some(SnippetSpec {
+            max_snippet_count: 1,
+            reference_only: false,
+            return_snippet: true,
+        });
+        self
+    }
+
+    /// Also summarizes the top `count` results with citations, adversarial and non summary
+    /// seeking queries are not summarized.
+    pub fn with_summary(mut self, count: u32) -> Self {
+        self.summary_spec = Some(SummarySpec {
+            summary_result_count: count,
+            include_citations: true,
+            ignore_adversarial_query: true,
+            ignore_non_summary_seeking_query: true,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Also returns up to `segments` extractive segments per result.
+    pub fn extractive(mut self, segments: i32) -> Self {
+        self.extractive_content_spec = Some(ExtractiveContentSpec {
+            max_extractive_segment_count: Some(segments),
+            ..Default::default()
+        });
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SearchResultMode {
+    #[default]
+    SearchResultModeUnspecified,
+    Documents,
+    Chunks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SummarySpec {
+    pub summary_result_count: u32,
+    pub include_citations: bool,
+    pub ignore_adversarial_query: bool,
+    pub ignore_non_summary_seeking_query: bool,
+    pub model_prompt_spec: ModelPromptSpec,
+    pub language_mode: String,
+    pub model_spec: ModelSpec,
+    pub use_semantic_chunks: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPromptSpec {
+    pub preamble: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelSpec {
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractiveContentSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_extractive_answer_count: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_extractive_segment_count: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_extractive_segment_score: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_previus_segments: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_next_segments: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetSpec {
+    pub max_snippet_count: i32,
+    pub reference_only: bool,
+    pub return_snippet: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellCorrectionSpec {
+    pub mode: Mode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Mode {
+    ModeUnspecified,
+    #[serde(rename = "SUGGESTION_ONLY")]
+    SuggestionOnly,
+    #[default]
+    Auto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BoostSpec {
+    pub condition_boost_specs: Vec<ConditionBoostSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionBoostSpec {
+    pub condition: String,
+    pub boost: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlPoint {
+    pub attribute_value: String,
+    pub boost_amount: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AttributeType {
+    #[default]
+    AttributeTypeUnspecified,
+    Numerical,
+    Freshness,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InterpolationType {
+    #[default]
+    InterpolationTypeUnspecified,
+    Linear,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageQuery {
+    pub image_bytes: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataStoreSpec {
+    pub data_store: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    pub user_id: String,
+    pub user_agent: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FacetSpec {
+    pub facet_key: FacetKey,
+    pub limit: i32,
+    pub excluded_filter_keys: Vec<String>,
+    pub enable_dynamic_position: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FacetKey {
+    pub key: String,
+    pub interval: Vec<Interval>,
+    pub restricted_values: Vec<String>,
+    pub prefixes: Vec<String>,
+    pub contains: Vec<String>,
+    pub case_insensitve: bool,
+    pub order_by: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Interval {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusive_minimum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusive_maximum: Option<f64>,
+}
+
+impl Interval {
+    // to_filter formats the interval as IN(lower, upper), `*` is an open bound and an `e` suffix
+    // marks an exclusive one
+    fn to_filter(&self) -> String {
+        let bound = |inclusive: Option<f64>, exclusive: Option<f64>| match (inclusive, exclusive) {
+            (Some(bound), _) => bound.to_string(),
+            (None, Some(bound)) => format!("{}e", bound),
+            (None, None) => "*".to_string(),
+        };
+        format!(
+            "IN({}, {})",
+            bound(self.minimum, self.exclusive_minimum),
+            bound(self.maximum, self.exclusive_maximum)
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryExpansionSpec {
+    pub condition: Condition,
+    pub pin_unexpanded_results: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SearchAsYouTypeCondition {
+    ConditionUnspecified,
+    #[default]
+    Disabled,
+    Enabled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Condition {
+    ConditionUnspecified,
+    Disabled,
+    #[default]
+    Auto,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetupDataConnectorResponse {
+    pub name: String,
+    pub response: ResponseDataConnector,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseDataConnector {
+    #[serde(rename = "@type")]
+    pub type_url: String,
+    pub name: String,
+    pub state: String,
+    pub data_source: String,
+    pub params: Params,
+    pub refresh_interval: GoogleDuration,
+    pub entities: Vec<ResponseEntity>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseEntity {
+    pub entity_name: String,
+    pub data_store: String,
+    pub params: EntityParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetupDataConnectorRequest {
+    pub project_id: String,
+    pub collection_id: String,
+    pub collection_display_name: String,
+    pub data_connector: DataConnector,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DataConnector {
+    pub data_source: String,
+    pub params: Params,
+    pub refresh_interval: GoogleDuration,
+    pub entities: Vec<Entity>,
+    pub sync_mode: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Params {
+    pub instance_uris: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entity {
+    pub entity_name: String,
+    pub params: EntityParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EntityParams {
+    pub data_schema: String,
+    pub content_config: String,
+    pub industry_vertical: String,
+    pub auto_generate_ids: bool,
+}
+
+// sync modes of DataConnector::sync_mode
+pub const SYNC_MODES: [&str; 2] = ["PERIODIC", "STREAMING"];
+// range of DataConnector::refresh_interval accepted by the API, 0 syncs in real time
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+pub const MAX_REFRESH_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Builds a `SetupDataConnectorRequest`, checking the connector before anything is sent to the
+/// API: the refresh interval must be 0 or between `MIN_REFRESH_INTERVAL` and
+/// `MAX_REFRESH_INTERVAL`, the sync mode one of `SYNC_MODES` and at least one entity must be
+/// synced. Unless set, the connector syncs
+/// periodically and the collection is displayed with its id.
+pub struct DataConnectorBuilder {
+    project_id: String,
+    collection_id: String,
+    collection_display_name: Option<String>,
+    data_source: String,
+    instance_uris: Vec<String>,
+    refresh_interval: Option<Duration>,
+    sync_mode: String,
+    entities: Vec<Entity>,
+}
+
+impl DataConnectorBuilder {
+    pub fn new(
+        project_id: impl Into<String>,
+        collection_id: impl Into<String>,
+        data_source: impl Into<String>,
+    ) -> Self {
+        DataConnectorBuilder {
+            project_id: project_id.into(),
+            collection_id: collection_id.into(),
+            collection_display_name: None,
+            data_source: data_source.into(),
+            instance_uris: Vec::new(),
+            refresh_interval: None,
+            sync_mode: SYNC_MODES[0].to_string(),
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn collection_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.collection_display_name = Some(display_name.into());
+        self
+    }
+
+    pub fn instance_uris(mut self, instance_uris: Vec<String>) -> Self {
+        self.instance_uris = instance_uris;
+        self
+    }
+
+    pub fn refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = Some(refresh_interval);
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: impl Into<String>) -> Self {
+        self.sync_mode = sync_mode.into();
+        self
+    }
+
+    pub fn entity(mut self, entity: Entity) -> Self {
+        self.entities.push(entity);
+        self
+    }
+
+    // build validates the builder and returns the request for
+    // DataStoreClient::setup_data_connector
+    pub fn build(self) -> Result<SetupDataConnectorRequest, Error> {
+        let refresh_interval = self
+            .refresh_interval
+            .ok_or(Error::MissingField("refresh_interval"))?;
+        if !refresh_interval.is_zero()
+            && !(MIN_REFRESH_INTERVAL..=MAX_REFRESH_INTERVAL).contains(&refresh_interval)
+        {
+            return Err(Error::InvalidRefreshInterval(refresh_interval));
+        }
+        if !SYNC_MODES.contains(&self.sync_mode.as_str()) {
+            return Err(Error::InvalidSyncMode(self.sync_mode));
+        }
+        if self.entities.is_empty() {
+            return Err(Error::MissingField("entities"));
+        }
+        Ok(SetupDataConnectorRequest {
+            collection_display_name: self
+                .collection_display_name
+                .unwrap_or_else(|| self.collection_id.clone()),
+            project_id: self.project_id,
+            collection_id: self.collection_id,
+            data_connector: DataConnector {
+                data_source: self.data_source,
+                params: Params {
+                    instance_uris: self.instance_uris,
+                },
+                refresh_interval: GoogleDuration(refresh_interval),
+                entities: self.entities,
+                sync_mode: self.sync_mode,
+            },
+        })
+    }
+}
+
+/// A `Duration` in the JSON form of the API durations: seconds with an optional fraction
+/// followed by "s", e.g. `"86400s"` or `"1.5s"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GoogleDuration(pub Duration);
+
+impl From<Duration> for GoogleDuration {
+    fn from(duration: Duration) -> Self {
+        GoogleDuration(duration)
+    }
+}
+
+impl Serialize for GoogleDuration {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let secs = self.0.as_secs();
+        let nanos = self.0.subsec_nanos();
+        if nanos == 0 {
+            return serializer.serialize_str(&format!("{}s", secs));
+        }
+        let fraction = format!("{:09}", nanos);
+        serializer.serialize_str(&format!("{}.{}s", secs, fraction.trim_end_matches('0')))
+    }
+}
+
+impl<'de> Deserialize<'de> for GoogleDuration {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let duration = String::deserialize(deserializer)?;
+        parse_duration(&duration)
+            .map(GoogleDuration)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid duration {:?}", duration)))
+    }
+}
+
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let seconds = duration.strip_suffix('s')?;
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(fraction) || fraction.len() > 9 {
+        return None;
+    }
+    let nanos = format!("{:0<9}", fraction).parse::<u32>().ok()?;
+    Some(Duration::new(whole.parse().ok()?, nanos))
+}
+
+pub struct CompleteQueryRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub query: String,
+    pub query_model: Option<String>,
+    pub include_tail_suggestions: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteQueryResponse {
+    #[serde(default)]
+    pub query_suggestions: Vec<QuerySuggestion>,
+    // true when the suggestions complete only the last words of the query
+    #[serde(default)]
+    pub tail_match_triggered: bool,
+}
+
+impl CompleteQueryResponse {
+    /// The suggested queries, most relevant first.
+    pub fn suggestions(&self) -> Vec<&str> {
+        self.query_suggestions
+            .iter()
+            .map(|suggestion| suggestion.suggestion.as_str())
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySuggestion {
+    pub suggestion: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completable_field_paths: Vec<String>,
+}
+
+pub struct ListChunksRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub document_id: String,
+    pub page_size: Option<i32>,
+    pub page_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListChunksResponse {
+    #[serde(default)]
+    pub chunks: Vec<Chunk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkSpec {
+    pub num_previous_chunks: Option<i32>,
+    pub num_next_chunks: Option<i32>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchChunksRequest {
+    // the serving config the request is sent to, not part of the body
+    #[serde(skip_serializing)]
+    pub project_id: String,
+    #[serde(skip_serializing)]
+    pub collections: String,
+    #[serde(skip_serializing)]
+    pub data_store_id: String,
+    #[serde(skip_serializing)]
+    pub serving_config: String,
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_by: Option<String>,
+    pub content_search_spec: ContentSearchSpec,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchChunksResponse {
+    #[serde(default)]
+    pub chunks: Vec<Chunk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_metadata: Option<DocumentMetadata>,
+    #[serde(default)]
+    pub derived_struct_data: BTreeMap<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_span: Option<PageSpan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_metadata: Option<ChunkMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relevance_score: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub struct_data: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSpan {
+    #[serde(default)]
+    pub page_start: i32,
+    #[serde(default)]
+    pub page_end: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkMetadata {
+    #[serde(default)]
+    pub previous_chunks: Vec<Chunk>,
+    #[serde(default)]
+    pub next_chunks: Vec<Chunk>,
+}
+
+pub struct GetDataStoreRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub data_store_id: String,
+}
+
+pub struct DeleteDataStoreRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub data_store_id: String,
+}
+
+pub struct CreateEngineRequest {
+    pub engine: Engine,
+    pub project_id: String,
+    pub collections: String,
+    pub engine_id: String,
+}
+
+pub struct GetEngineRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub engine_id: String,
+}
+
+pub struct DeleteEngineRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub engine_id: String,
+}
+
+#[derive(Default)]
+pub struct ListEnginesRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub page_size: Option<i32>,
+    pub page_token: Option<String>,
+    pub filter: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ListEnginesResponse {
+    #[serde(default)]
+    pub engines: Vec<Engine>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Engine {
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub data_store_ids: Vec<String>,
+    #[serde(default)]
+    pub solution_type: SolutionType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub industry_vertical: Option<IndustryVertical>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_engine_config: Option<SearchEngineConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchEngineConfig {
+    #[serde(default)]
+    pub search_tier: SearchTier,
+    #[serde(default)]
+    pub search_add_ons: Vec<SearchAddOn>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SearchTier {
+    #[default]
+    SearchTierStandard,
+    SearchTierEnterprise,
+    #[serde(other)]
+    SearchTierUnspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SearchAddOn {
+    SearchAddOnLlm,
+    #[serde(other)]
+    SearchAddOnUnspecified,
+}
+
+pub struct CreateDataStoreRequest {
+    pub data_store: DataStore,
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub create_advance_site_search: Option<bool>,
+}
+
+pub const MAX_DATA_STORE_ID_LEN: usize = 63;
+// range of LayoutBasedChunkingConfig::chunk_size accepted by the API, in tokens
+pub const MIN_LAYOUT_CHUNK_SIZE: i32 = 100;
+pub const MAX_LAYOUT_CHUNK_SIZE: i32 = 500;
+
+/// Builds a `CreateDataStoreRequest`, checking the data store id and the required fields before
+/// anything is sent to the API.
+///
+/// The data store id must conform to RFC-1034: at most `MAX_DATA_STORE_ID_LEN` characters, only
+/// lowercase letters, digits and hyphens, starting with a letter and not ending with a hyphen.
+/// Unless set, the data store goes into the collection of the client as a generic data store
+/// without content.
+pub struct DataStoreBuilder {
+    project_id: String,
+    data_store_id: String,
+    collections: String,
+    display_name: Option<String>,
+    industry_vertical: IndustryVertical,
+    solution_types: Vec<SolutionType>,
+    content_config: ContentConfig,
+    default_schema_id: Option<String>,
+    language_info: Option<LanguageInfo>,
+    document_processing_config: Option<DocumentProcessingConfig>,
+    starting_schema: Option<Schema>,
+    ocr: Option<OcrParsingConfig>,
+    layout_chunking: Option<LayoutBasedChunkingConfig>,
+    create_advance_site_search: Option<bool>,
+}
+
+impl DataStoreBuilder {
+    pub fn new(project_id: impl Into<String>, data_store_id: impl Into<String>) -> Self {
+        DataStoreBuilder {
+            project_id: project_id.into(),
+            data_store_id: data_store_id.into(),
+            collections: String::new(),
+            display_name: None,
+            industry_vertical: IndustryVertical::Generic,
+            solution_types: Vec::new(),
+            content_config: ContentConfig::NoContent,
+            default_schema_id: None,
+            language_info: None,
+            document_processing_config: None,
+            starting_schema: None,
+            ocr: None,
+            layout_chunking: None,
+            create_advance_site_search: None,
+        }
+    }
+
+    pub fn collections(mut self, collections: impl Into<String>) -> Self {
+        self.collections = collections.into();
+        self
+    }
+
+    pub fn display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    pub fn industry_vertical(mut self, industry_vertical: IndustryVertical) -> Self {
+        self.industry_vertical = industry_vertical;
+        self
+    }
+
+    pub fn solution_types(mut self, solution_types: Vec<SolutionType>) -> Self {
+        self.solution_types = solution_types;
+        self
+    }
+
+    pub fn content_config(mut self, content_config: ContentConfig) -> Self {
+        self.content_config = content_config;
+        self
+    }
+
+    pub fn default_schema_id(mut self, default_schema_id: impl Into<String>) -> Self {
+        self.default_schema_id = Some(default_schema_id.into());
+        self
+    }
+
+    pub fn language_info(mut self, language_info: LanguageInfo) -> Self {
+        self.language_info = Some(language_info);
+        self
+    }
+
+    pub fn document_processing_config(mut self, config: DocumentProcessingConfig) -> Self {
+        self.document_processing_config = Some(config);
+        self
+    }
+
+    pub fn starting_schema(mut self, starting_schema: Schema) -> Self {
+        self.starting_schema = Some(starting_schema);
+        self
+    }
+
+    // with_ocr parses every document with OCR by default, for data stores of scanned PDFs.
+    // `enhanced_elements` are the extra elements to detect, e.g. "table". Setting it together with
+    // a default digital or layout parser fails on build.
+    pub fn with_ocr(mut self, use_native_text: bool, enhanced_elements: Vec<String>) -> Self {
+        self.ocr = Some(OcrParsingConfig {
+            enhanced_document_elements: Some(enhanced_elements),
+            use_native_text: Some(use_native_text),
+        });
+        self
+    }
+
+    // with_layout_chunking splits documents into chunks of at most `chunk_size` tokens following
+    // their layout, the size must be between MIN_LAYOUT_CHUNK_SIZE and MAX_LAYOUT_CHUNK_SIZE
+    pub fn with_layout_chunking(
+        mut self,
+        chunk_size: i32,
+        include_ancestor_headings: bool,
+    ) -> Self {
+        self.layout_chunking = Some(LayoutBasedChunkingConfig {
+            chunk_size: Some(chunk_size),
+            include_ancestor_headings: Some(include_ancestor_headings),
+        });
+        self
+    }
+
+    pub fn create_advance_site_search(mut self, enabled: bool) -> Self {
+        self.create_advance_site_search = Some(enabled);
+        self
+    }
+
+    // build validates the builder and returns the request for DataStoreClient::create_data_store
+    pub fn build(self) -> Result<CreateDataStoreRequest, Error> {
+        validate_data_store_id(&self.data_store_id)?;
+        let display_name = match self.display_name {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => return Err(Error::MissingField("display_name")),
+        };
+        let mut document_processing_config = self.document_processing_config;
+        if let Some(layout) = self.layout_chunking {
+            or_default_processing(&mut document_processing_config).chunking_config =
+                Some(ChunkingConfig {
+                    layout_based_chunking_config: Some(layout),
+                });
+        }
+        if let Some(ocr) = self.ocr {
+            let config = or_default_processing(&mut document_processing_config);
+            let parsing = config.default_parsing_config.get_or_insert(ParsingConfig {
+                digital_parsing_config: None,
+                ocr_parsing_config: None,
+                layout_parsing_config: None,
+            });
+            parsing.ocr_parsing_config = Some(ocr);
+        }
+        if let Some(config) = &document_processing_config {
+            validate_parsing_configs(config)?;
+            validate_chunking_config(config)?;
+        }
+
+        Ok(CreateDataStoreRequest {
+            data_store: DataStore {
+                name: self.data_store_id.clone(),
+                display_name,
+                industry_vertical: self.industry_vertical,
+                solution_types: self.solution_types,
+                default_schema_id: self.default_schema_id,
+                content_config: self.content_config,
+                create_time: None,
+                language_info: self.language_info,
+                document_processing_config,
+                starting_schema: self.starting_schema,
+            },
+            project_id: self.project_id,
+            collections: self.collections,
+            data_store_id: self.data_store_id,
+            create_advance_site_search: self.create_advance_site_search,
+        })
+    }
+}
+
+fn or_default_processing(
+    config: &mut Option<DocumentProcessingConfig>,
+) -> &mut DocumentProcessingConfig {
+    config.get_or_insert_with(|| DocumentProcessingConfig {
+        name: String::new(),
+        chunking_config: None,
+        default_parsing_config: None,
+        parsing_config_overrides: None,
+    })
+}
+
+// a parsing config is either digital, OCR or layout parsing, the API rejects more than one
+fn validate_parsing_configs(config: &DocumentProcessingConfig) -> Result<(), Error> {
+    let overrides = config.parsing_config_overrides.iter().flatten();
+    let scopes = config
+        .default_parsing_config
+        .iter()
+        .map(|parsing| ("default", parsing))
+        .chain(overrides.map(|(file_type, parsing)| (file_type.as_str(), parsing)));
+    for (scope, parsing) in scopes {
+        let parsers = [
+            parsing.digital_parsing_config.is_some(),
+            parsing.ocr_parsing_config.is_some(),
+            parsing.layout_parsing_config.is_some(),
+        ];
+        if parsers.iter().filter(|set| **set).count() > 1 {
+            return Err(Error::ConflictingParsingConfig(scope.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn validate_chunking_config(config: &DocumentProcessingConfig) -> Result<(), Error> {
+    let chunk_size = config
+        .chunking_config
+        .as_ref()
+        .and_then(|chunking| chunking.layout_based_chunking_config.as_ref())
+        .and_then(|layout| layout.chunk_size);
+    match chunk_size {
+        Some(size) if !(MIN_LAYOUT_CHUNK_SIZE..=MAX_LAYOUT_CHUNK_SIZE).contains(&size) => {
+            Err(Error::InvalidChunkSize(size))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_data_store_id(id: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| {
+        Err(Error::InvalidDataStoreId(
+            id.to_string(),
+            reason.to_string(),
+        ))
+    };
+
+    if id.is_empty() {
+        return invalid("it is empty");
+    }
+    if id.len() > MAX_DATA_STORE_ID_LEN {
+        return invalid("it is longer than 63 characters");
+    }
+    if !id.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return invalid("it must start with a lowercase letter");
+    }
+    if id.ends_with('-') {
+        return invalid("it must not end with a hyphen");
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-'))
+    {
+        return invalid(&format!(
+            "{:?} is not allowed, use lowercase letters, digits and hyphens",
+            c
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetOperationStatusRequest {
+    pub operation_name: String,
+    pub project_id: String,
+    pub collection: String,
+    pub data_store_id: String,
+    pub branch: String,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PollOperationRequest {
+    pub operation_name: String,
+    pub project_id: String,
+    pub collection: String,
+    pub data_store_id: String,
+    pub branch: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Operation {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<HashMap<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Status>,
+}
+
+impl Operation {
+    /// The id of the operation, the last segment of its name, e.g. `import-documents-123`.
+    pub fn id(&self) -> &str {
+        self.name
+            .rsplit_once("/operations/")
+            .map_or(self.name.as_str(), |(_, id)| id)
+    }
+
+    /// The metadata typed by its `@type`, the raw map for the types that aren't known here.
+    pub fn typed_metadata(&self) -> Option<OperationMetadata> {
+        self.metadata.as_ref().map(Metadata::typed)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct ObservationSearchResult {
-    pub document: String,
-    pub uri: String,
-    pub title: String,
-    pub snippet_info: SnipetInfo,
-    pub chunk_info: ObservationSearchResultChunkInfo,
-    pub struct_data: Value,
+pub struct ListOperationsResponse {
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+pub struct CreateTargetSiteRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub target_site: TargetSite,
+}
+
+pub struct AdvancedSiteSearchRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+}
+
+pub struct BatchCreateTargetSitesRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub target_sites: Vec<TargetSite>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct SnipetInfo {
-    pub snippet: String,
-    pub snippet_status: String,
+pub struct BatchCreateTargetSitesBody {
+    pub requests: Vec<CreateTargetSiteBody>,
+}
+
+impl BatchCreateTargetSitesBody {
+    // every nested request repeats the site search engine as its parent
+    fn new(parent: &str, target_sites: Vec<TargetSite>) -> Self {
+        Self {
+            requests: target_sites
+                .into_iter()
+                .map(|target_site| CreateTargetSiteBody {
+                    parent: parent.to_string(),
+                    target_site,
+                })
+                .collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct ObservationSearchResultChunkInfo {
-    pub chunk: String,
-    pub content: String,
-    pub relevance_score: i32,
+pub struct CreateTargetSiteBody {
+    pub parent: String,
+    pub target_site: TargetSite,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetSite {
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub name: String,
+    #[serde(default)]
+    pub provided_uri_pattern: String,
+    #[serde(default)]
+    #[serde(rename = "type")]
+    pub site_type: TargetSiteType,
+    #[serde(default)]
+    pub exact_match: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_uri_pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TargetSiteType {
+    #[default]
+    Include,
+    Exclude,
+    #[serde(other)]
+    TypeUnspecified,
+}
+
+pub struct RecrawlUrisRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub body: RecrawlUrisBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RecrawlUrisBody {
+    pub uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_credential: Option<String>,
+}
+
+pub struct GetDocumentRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub document_id: String,
+}
+
+/// Id of the schema every data store is created with.
+pub const DEFAULT_SCHEMA_ID: &str = "default_schema";
+
+/// Whether `create_document` validates the document against the data store schema before
+/// sending it. Only `Off` is available without the `schema-validation` feature.
+#[derive(Debug, Default)]
+pub enum SchemaValidation {
+    /// The document is sent as is, the API reports mismatches.
+    #[default]
+    Off,
+    /// The `DEFAULT_SCHEMA_ID` schema of the data store is fetched and validated against.
+    #[cfg(feature = "schema-validation")]
+    DataStoreSchema,
+    /// The document is validated against this schema, e.g. one fetched once for a batch.
+    #[cfg(feature = "schema-validation")]
+    Schema(Schema),
+}
+
+pub struct CreateDocumentRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub document_id: String,
+    pub document: Document,
+    pub validation: SchemaValidation,
+}
+
+pub struct BatchGetDocumentsRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub document_ids: Vec<String>,
+}
+
+/// Documents retrieved by `batch_get_documents`, `documents` keeps the order of the ids.
+#[derive(Debug)]
+pub struct BatchGetDocumentsResponse {
+    pub documents: Vec<Document>,
+    pub errors: Vec<DocumentError>,
+}
+
+/// A document `batch_get_documents` could not retrieve.
+#[derive(Debug)]
+pub struct DocumentError {
+    pub document_id: String,
+    pub error: Error,
+}
+
+pub struct PurgeDocumentsRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub body: PurgeDocumentsBody,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgeDocumentsBody {
+    pub filter: String,
+    pub force: bool,
+}
+
+pub struct ImportDocumentsRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub body: ImportDocumentsBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDocumentsBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gcs_source: Option<GcsSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconciliation_mode: Option<ReconciliationMode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GcsSource {
+    pub input_uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_schema: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReconciliationMode {
+    ReconciliationModeUnspecified,
+    Incremental,
+    Full,
+}
+
+pub struct RefreshRequest {
+    pub project_id: String,
+    pub collections: String,
+    pub data_store_id: String,
+    pub branch: String,
+    pub filter: Option<String>,
+    pub gcs_input_uris: Vec<String>,
+    pub data_schema: Option<String>,
+    pub poll_config: Option<PollConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OperationError {
+    #[serde(default)]
+    pub code: i32,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub details: Vec<HashMap<String, serde_json::Value>>, // Adjust as needed
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(rename = "@type")]
+    pub at_type: String,
+    #[serde(flatten)]
+    pub additional: HashMap<String, Value>,
+}
+
+/// The metadata of an operation, typed for the operations this crate starts, see
+/// [`Operation::typed_metadata`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperationMetadata {
+    ImportDocuments(ImportDocumentsMetadata),
+    CreateDataStore(CreateDataStoreMetadata),
+    /// Any other `@type`, or a known one that no longer matches its struct, as sent by the API.
+    Other(Metadata),
+}
+
+/// Progress of an import documents operation. The API sends the int64 counts as strings.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDocumentsMetadata {
+    pub create_time: Option<String>,
+    pub update_time: Option<String>,
+    #[serde(default, deserialize_with = "int64_string")]
+    pub success_count: i64,
+    #[serde(default, deserialize_with = "int64_string")]
+    pub failure_count: i64,
+    #[serde(default, deserialize_with = "int64_string")]
+    pub total_count: i64,
+}
+
+impl ImportDocumentsMetadata {
+    /// The documents processed so far, successfully or not.
+    pub fn processed_count(&self) -> i64 {
+        self.success_count + self.failure_count
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateDataStoreMetadata {
+    pub create_time: Option<String>,
+    pub update_time: Option<String>,
+}
+
+// int64_string reads a proto3 int64, a JSON string, also taking a number
+fn int64_string<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Int64 {
+        String(String),
+        Number(i64),
+    }
+    match Int64::deserialize(deserializer)? {
+        Int64::String(value) => value.parse().map_err(serde::de::Error::custom),
+        Int64::Number(value) => Ok(value),
+    }
+}
+
+impl Metadata {
+    // the @type is a type URL, type.googleapis.com/google.cloud.discoveryengine.v1.X, matched on
+    // X so the v1beta and v1alpha metadata are typed as well
+    fn typed(&self) -> OperationMetadata {
+        fn parse<T: DeserializeOwned>(metadata: &Metadata) -> Option<T> {
+            let fields = metadata.additional.clone().into_iter().collect();
+            serde_json::from_value(Value::Object(fields)).ok()
+        }
+
+        let type_name = self
+            .at_type
+            .rsplit_once('.')
+            .map_or(self.at_type.as_str(), |(_, name)| name);
+        let typed = match type_name {
+            "ImportDocumentsMetadata" => parse(self).map(OperationMetadata::ImportDocuments),
+            "CreateDataStoreMetadata" => parse(self).map(OperationMetadata::CreateDataStore),
+            _ => None,
+        };
+        typed.unwrap_or_else(|| OperationMetadata::Other(self.clone()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(rename = "@type")]
+    pub at_type: String,
+    #[serde(flatten)]
+    pub additional: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    #[serde(default)]
+    pub code: i32,
+    #[serde(default)]
+    pub message: String,
+    // canonical code of API errors, e.g. FAILED_PRECONDITION, empty in operation errors
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+    #[serde(default)]
+    pub details: Vec<Detail>,
+}
+
+impl Status {
+    /// The delay of the `google.rpc.RetryInfo` detail, how long to wait before retrying.
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.details
+            .iter()
+            .find(|detail| detail.at_type.ends_with("google.rpc.RetryInfo"))?
+            .additional
+            .get("retryDelay")?
+            .as_str()
+            .and_then(parse_duration)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Detail {
+    #[serde(rename = "@type")]
+    pub at_type: String,
+    #[serde(flatten)]
+    pub additional: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum OperationResult {
+    Error { error: Status },
+    Response { response: Response },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataStore {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub industry_vertical: IndustryVertical,
+    #[serde(default)]
+    pub solution_types: Vec<SolutionType>,
+    pub default_schema_id: Option<String>,
+    #[serde(default)]
+    pub content_config: ContentConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_info: Option<LanguageInfo>,
+    pub document_processing_config: Option<DocumentProcessingConfig>,
+    pub starting_schema: Option<Schema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IndustryVertical {
+    Media,
+    SiteSearch,
+    Generic,
+    #[serde(rename = "INDUSTRY_VERTICAL_UNSPECIFIED")]
+    #[default]
+    #[serde(other)]
+    Unspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SolutionType {
+    #[serde(rename = "SOLUTION_TYPE_RECOMMENDATION")]
+    Recommendation,
+    #[serde(rename = "SOLUTION_TYPE_SEARCH")]
+    Search,
+    #[serde(rename = "SOLUTION_TYPE_CHAT")]
+    Chat,
+    #[serde(rename = "SOLUTION_TYPE_GENERATIVE_CHAT")]
+    GenerativeChat,
+    #[serde(rename = "SOLUTION_TYPE_UNSPECIFIED")]
+    #[default]
+    #[serde(other)]
+    Unspecified,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ContentConfig {
+    NoContent,
+    ContentRequired,
+    PublicWebsite,
+    #[serde(rename = "CONTENT_CONFIG_UNSPECIFIED")]
+    #[default]
+    #[serde(other)]
+    Unspecified,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct AnswerReference {
-    pub unstructured_document_info: UnstructureDocumentInfo,
-    pub chunk_info: ChunkInfo,
-    pub structured_document_info: StructuredDocumentInfo,
+pub struct LanguageInfo {
+    #[serde(default)]
+    pub language_code: String,
+    pub normalized_language_code: Option<String>,
+    pub language: Option<String>,
+    pub region: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct StructuredDocumentInfo {
-    pub document: String,
-    pub struct_data: Value,
+pub struct DocumentProcessingConfig {
+    #[serde(default)]
+    pub name: String,
+    pub chunking_config: Option<ChunkingConfig>,
+    pub default_parsing_config: Option<ParsingConfig>,
+    pub parsing_config_overrides: Option<HashMap<String, ParsingConfig>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct AnswerChunkInfo {
-    pub chunk: String,
-    pub content: String,
-    pub document_metadata: AnswerDocumentMetadata,
-    pub relevance_score: f64, // Using f64 to r
+pub struct ChunkingConfig {
+    pub layout_based_chunking_config: Option<LayoutBasedChunkingConfig>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct AnswerDocumentMetadata {
-    pub document: String,
-    pub uri: String,
-    pub title: String,
-    pub page_identifier: String,
-    pub struct_data: Value,
+pub struct LayoutBasedChunkingConfig {
+    pub chunk_size: Option<i32>,
+    pub include_ancestor_headings: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct AnswerUnstructureDocumentInfo {
-    pub document: String,
-    pub uri: String,
-    pub title: String,
-    pub chunk_contents: Vec<AnswerChunkContent>,
-    pub struct_data: Value,
+pub struct ParsingConfig {
+    pub digital_parsing_config: Option<DigitalParsingConfig>,
+    pub ocr_parsing_config: Option<OcrParsingConfig>,
+    pub layout_parsing_config: Option<LayoutParsingConfig>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct AnswerChunkContent {
-    pub content: String,
-    pub page_identifier: String,
-    pub relevance_score: f64,
-}
+pub struct DigitalParsingConfig {}
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum State {
-    Unspecified,
-    InProgress,
-    Failed,
-    Succeeded,
+#[serde(rename_all = "camelCase")]
+pub struct OcrParsingConfig {
+    pub enhanced_document_elements: Option<Vec<String>>,
+    pub use_native_text: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct AnswerRequest {
-    pub project_id: String,
-    pub discovery_engine_answer_request: DiscoveryEngineAnswerRequest,
-}
+pub struct LayoutParsingConfig {}
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
-pub struct DiscoveryEngineAnswerRequest {
-    pub query: Query,
-    pub session: String,
-    pub safety_spec: SafetySpec,
-    pub related_questions_spec: RelatedQuestionsSpec,
-    pub answer_generation_spec: AnswerGenerationSpec,
-    pub search_spec: SearchSpec,
+pub struct Schema {
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub struct_schema: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchSpec {
-    pub search_params: SearchParams,
-    pub search_result_list: SearchResultList,
+pub struct GetSchemaRequest {
+    pub collections: String,
+    pub project_id: String,
+    pub data_store_id: String,
+    pub schema_id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchResultList {
-    pub search_results: Vec<AnswerSearchResult>,
+pub struct UpdateSchemaRequest {
+    pub schema: Schema,
+    pub collections: String,
+    pub project_id: String,
+    pub data_store_id: String,
+    pub schema_id: String,
+    pub allow_missing: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct AnswerSearchResult {
-    pub unstructured_document_info: UnstructureDocumentInfo,
-    pub chunk_info: ChunkInfo,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn operation(name: &str, done: bool) -> Operation {
+        Operation {
+            name: name.to_string(),
+            metadata: None,
+            done,
+            response: None,
+            error: None,
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ChunkInfo {
-    pub chunk: String,
-    pub content: String,
-}
+    // RecordingClient records every call and reports the purge as done after two polls.
+    #[derive(Default)]
+    struct RecordingClient {
+        calls: Mutex<Vec<String>>,
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct UnstructureDocumentInfo {
-    pub document: String,
-    pub uri: String,
-    pub tittle: String,
-    pub document_context: Vec<DocumentContext>,
-    pub extractive_segments: Vec<ExtractiveSegments>,
-    pub extractive_answer: Vec<ExtractiveAnswer>,
-}
+    impl RecordingClient {
+        fn record(&self, call: String) -> usize {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(call);
+            calls.len()
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ExtractiveSegments {
-    pub page_identifier: String,
-    pub content: String,
-}
+    impl DocumentOperations for RecordingClient {
+        async fn purge_documents(
+            &self,
+            request: PurgeDocumentsRequest,
+        ) -> Result<Operation, Error> {
+            self.record(format!("purge {}", request.body.filter));
+            Ok(operation("purge-operation", false))
+        }
+
+        async fn import_documents(
+            &self,
+            request: ImportDocumentsRequest,
+        ) -> Result<Operation, Error> {
+            let uris = request.body.gcs_source.unwrap().input_uris.join(",");
+            self.record(format!("import {}", uris));
+            Ok(operation("import-operation", false))
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct DocumentContext {
-    pub page_identifier: String,
-    pub content: String,
-}
+    impl OperationReader for RecordingClient {
+        async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error> {
+            let call = self.record(format!("get {}", operation_name));
+            Ok(operation(operation_name, call >= 3))
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchParams {
-    pub max_return_results: i32,
-    pub filter: String,
-    pub boost_spec: BoostSpec,
-    pub order_by: String,
-    pub search_result_mode: SearchResultMode,
-    pub data_store_spec: Vec<DataStoreSpec>,
-}
+    #[test]
+    fn test_deserialize_data_store_resource() {
+        let data_store: DataStore =
+            serde_json::from_str(include_str!("../../testdata/data_store.json")).unwrap();
+
+        assert!(data_store
+            .name
+            .ends_with("/dataStores/moni-demo_1722720098936"));
+        assert_eq!(data_store.display_name, "moni-demo");
+        assert!(matches!(
+            data_store.industry_vertical,
+            IndustryVertical::Generic
+        ));
+        assert!(matches!(
+            data_store.solution_types[..],
+            [SolutionType::Search]
+        ));
+        assert!(matches!(
+            data_store.content_config,
+            ContentConfig::ContentRequired
+        ));
+        assert_eq!(
+            data_store.default_schema_id.as_deref(),
+            Some("default_schema")
+        );
+        assert!(data_store
+            .document_processing_config
+            .unwrap()
+            .default_parsing_config
+            .unwrap()
+            .digital_parsing_config
+            .is_some());
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct BoostControlSpec {
-    pub field_name: String,
-    pub attribute_type: AttributeType,
-    pub interpolation_type: InterpolationType,
-    pub control_points: Vec<ControlPoint>,
-}
+    #[test]
+    fn test_content_raw_bytes_round_trip() {
+        let bytes = b"%PDF-1.4\n\x00\xff binary";
+        let content = Content::from_raw_bytes("application/pdf", bytes);
+
+        let json = serde_json::to_value(&content).unwrap();
+        assert_eq!(json["mimeType"], "application/pdf");
+        assert_eq!(json["rawBytes"], BASE64_STANDARD.encode(bytes));
+
+        let content: Content = serde_json::from_value(json).unwrap();
+        assert_eq!(content.raw_bytes().unwrap().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_parse_list_chunks_response() {
+        let response: ListChunksResponse = serde_json::from_value(serde_json::json!({
+            "chunks": [
+                {
+                    "name": "projects/1/locations/global/collections/default_collection/dataStores/moni-docs/branches/0/documents/7/chunks/c1",
+                    "id": "c1",
+                    "content": "La adaptación al cambio climático",
+                    "derivedStructData": {"title": "Plan nacional de adaptación"},
+                    "pageSpan": {"pageStart": 1, "pageEnd": 2}
+                },
+                {
+                    "name": "projects/1/locations/global/collections/default_collection/dataStores/moni-docs/branches/0/documents/7/chunks/c2",
+                    "id": "c2",
+                    "content": "Medidas de mitigación",
+                    "documentMetadata": {"uri": "gs://moni/plan.pdf", "title": "plan.pdf"}
+                }
+            ],
+            "nextPageToken": "page-2"
+        }))
+        .unwrap();
+
+        let ids: Vec<&str> = response
+            .chunks
+            .iter()
+            .map(|chunk| chunk.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["c1", "c2"]);
+        let page_span = response.chunks[0].page_span.as_ref().unwrap();
+        assert_eq!((page_span.page_start, page_span.page_end), (1, 2));
+        assert_eq!(
+            response.chunks[0].derived_struct_data["title"],
+            "Plan nacional de adaptación"
+        );
+        assert_eq!(
+            response.chunks[1].document_metadata.as_ref().unwrap().uri,
+            "gs://moni/plan.pdf"
+        );
+        assert_eq!(response.next_page_token.as_deref(), Some("page-2"));
+
+        let last_page: ListChunksResponse = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(last_page.chunks.is_empty());
+        assert!(last_page.next_page_token.is_none());
+    }
+
+    fn advanced_site_search_request() -> AdvancedSiteSearchRequest {
+        AdvancedSiteSearchRequest {
+            project_id: "moni-429523".to_string(),
+            collections: "default_collection".to_string(),
+            data_store_id: "moni-sites".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_enable_advanced_site_search_endpoint() {
+        assert_eq!(
+            advanced_site_search_url(&Location::Global, "default_collection", &advanced_site_search_request(), true),
+            "https://discoveryengine.googleapis.com/v1/projects/moni-429523/locations/global/collections/default_collection/dataStores/moni-sites/siteSearchEngine:enableAdvancedSiteSearch"
+        );
+    }
+
+    #[test]
+    fn test_disable_advanced_site_search_endpoint() {
+        assert_eq!(
+            advanced_site_search_url(&Location::Global, "default_collection", &advanced_site_search_request(), false),
+            "https://discoveryengine.googleapis.com/v1/projects/moni-429523/locations/global/collections/default_collection/dataStores/moni-sites/siteSearchEngine:disableAdvancedSiteSearch"
+        );
+    }
+
+    #[test]
+    fn test_serialize_batch_create_target_sites_body() {
+        let parent =
+            site_search_engine("global", "moni-429523", "default_collection", "moni-sites");
+        let body = BatchCreateTargetSitesBody::new(
+            &parent,
+            vec![
+                TargetSite {
+                    provided_uri_pattern: "www.minambiente.gov.co/*".to_string(),
+                    ..Default::default()
+                },
+                TargetSite {
+                    provided_uri_pattern: "www.minambiente.gov.co/wp-admin/*".to_string(),
+                    site_type: TargetSiteType::Exclude,
+                    exact_match: false,
+                    ..Default::default()
+                },
+            ],
+        );
+
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            serde_json::json!({
+                "requests": [
+                    {
+                        "parent": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni-sites/siteSearchEngine",
+                        "targetSite": {
+                            "providedUriPattern": "www.minambiente.gov.co/*",
+                            "type": "INCLUDE",
+                            "exactMatch": false
+                        }
+                    },
+                    {
+                        "parent": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni-sites/siteSearchEngine",
+                        "targetSite": {
+                            "providedUriPattern": "www.minambiente.gov.co/wp-admin/*",
+                            "type": "EXCLUDE",
+                            "exactMatch": false
+                        }
+                    }
+                ]
+            })
+        );
+    }
+
+    fn recrawl_request(uris: Vec<String>) -> RecrawlUrisRequest {
+        RecrawlUrisRequest {
+            project_id: "moni-429523".to_string(),
+            collections: "default_collection".to_string(),
+            data_store_id: "moni-sites".to_string(),
+            body: RecrawlUrisBody {
+                uris,
+                site_credential: None,
+            },
+        }
+    }
+
+    #[cfg(feature = "schema-validation")]
+    fn policy_schema() -> Schema {
+        Schema {
+            struct_schema: Some(serde_json::json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "title": {"type": "string", "retrievable": true},
+                    "year": {"type": "integer", "indexable": true}
+                },
+                "required": ["title", "year"]
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn structured_document(struct_data: Value) -> Document {
+        Document {
+            name: String::new(),
+            id: "climate-action-plan".to_string(),
+            content: None,
+            parent_document_id: None,
+            derived_struct_data: None,
+            acl_info: None,
+            index_time: None,
+            data: Some(DocumentData::StructData { struct_data }),
+        }
+    }
+
+    #[cfg(feature = "schema-validation")]
+    #[test]
+    fn test_validate_document_lists_offending_fields() {
+        let valid = structured_document(serde_json::json!({
+            "title": "Climate Action Plan 2024",
+            "year": 2024
+        }));
+        assert!(validate_document(&policy_schema(), &valid).is_ok());
+
+        let invalid = structured_document(serde_json::json!({"title": 2024}));
+        let Err(Error::SchemaViolation(violations)) = validate_document(&policy_schema(), &invalid)
+        else {
+            panic!("the document should not match the schema");
+        };
+        assert_eq!(violations.len(), 2);
+        assert!(violations
+            .iter()
+            .any(|violation| violation.contains(r#""year" is a required property"#)));
+        assert!(violations
+            .iter()
+            .any(|violation| violation.starts_with("/title:")));
+
+        // the schema can also come as a JSON string
+        let json_schema = Schema {
+            json_schema: Some(policy_schema().struct_schema.unwrap().to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_document(&json_schema, &invalid),
+            Err(Error::SchemaViolation(violations)) if violations.len() == 2
+        ));
+    }
+
+    #[cfg(feature = "schema-validation")]
+    #[tokio::test]
+    async fn test_create_document_validates_before_sending() {
+        let client = DataStoreClient::with_client(Client::new().await.unwrap());
+
+        let result = client
+            .create_document(CreateDocumentRequest {
+                project_id: "moni-429523".to_string(),
+                collections: String::new(),
+                data_store_id: "moni".to_string(),
+                branch: "0".to_string(),
+                document_id: "climate-action-plan".to_string(),
+                document: structured_document(serde_json::json!({"title": "Climate Action Plan"})),
+                validation: SchemaValidation::Schema(policy_schema()),
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::SchemaViolation(violations))
+                if violations == vec![r#""year" is a required property"#.to_string()]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_collection_precedence() {
+        let client = DataStoreClient::with_client(Client::new().await.unwrap());
+        assert_eq!(client.collection(""), DEFAULT_COLLECTION);
+        assert_eq!(client.collection("reports"), "reports");
+
+        let client = client.with_collection("policies");
+        assert_eq!(client.collection(""), "policies");
+        assert_eq!(client.collection("reports"), "reports");
+
+        let request = GetDocumentRequest {
+            project_id: "moni-429523".to_string(),
+            collections: String::new(),
+            data_store_id: "moni".to_string(),
+            branch: "0".to_string(),
+            document_id: "1".to_string(),
+        };
+        assert_eq!(
+            document_url(&client.location, client.collection(&request.collections), &request),
+            "https://discoveryengine.googleapis.com/v1/projects/moni-429523/locations/global/collections/policies/dataStores/moni/branches/0/documents/1"
+        );
+
+        // the serving configs of searches and answers are in the client collection too
+        let conversation = Conversation::new(client.clone(), "moni-429523", "moni", true);
+        assert_eq!(
+            conversation.serving_config,
+            "projects/moni-429523/locations/global/collections/policies/engines/moni/servingConfigs/default_serving_config"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_location_strings() {
+        for (location, name, endpoint) in [
+            (
+                Location::Global,
+                "global",
+                "https://discoveryengine.googleapis.com",
+            ),
+            (
+                Location::Us,
+                "us",
+                "https://us-discoveryengine.googleapis.com",
+            ),
+            (
+                Location::Eu,
+                "eu",
+                "https://eu-discoveryengine.googleapis.com",
+            ),
+            (
+                Location::Custom("asia-northeast1".to_string()),
+                "asia-northeast1",
+                "https://asia-northeast1-discoveryengine.googleapis.com",
+            ),
+        ] {
+            assert_eq!(location.as_str(), name);
+            assert_eq!(location.to_string(), name);
+            assert_eq!(location.endpoint(), endpoint);
+            assert_eq!(Location::from(name), location);
+        }
+        assert_eq!(Location::default(), Location::Global);
+
+        let client =
+            DataStoreClient::with_client(Client::new().await.unwrap()).with_location(Location::Eu);
+        let request = GetDocumentRequest {
+            project_id: "moni-429523".to_string(),
+            collections: String::new(),
+            data_store_id: "moni".to_string(),
+            branch: "0".to_string(),
+            document_id: "1".to_string(),
+        };
+        assert_eq!(
+            document_url(&client.location, client.collection(&request.collections), &request),
+            "https://eu-discoveryengine.googleapis.com/v1/projects/moni-429523/locations/eu/collections/default_collection/dataStores/moni/branches/0/documents/1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recrawl_uris_rejects_too_many_uris() {
+        let client = DataStoreClient::with_client(Client::new().await.unwrap());
+        let uris = (0..=MAX_RECRAWL_URIS)
+            .map(|i| format!("https://www.minambiente.gov.co/page-{}", i))
+            .collect();
+
+        let result = client.recrawl_uris(recrawl_request(uris)).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::TooManyUris(10_001, MAX_RECRAWL_URIS))
+        ));
+    }
+
+    #[test]
+    fn test_data_store_builder_accepts_valid_ids() {
+        let id = format!("a{}", "b".repeat(MAX_DATA_STORE_ID_LEN - 1));
+        for data_store_id in ["moni-documents", "docs2024", "a", id.as_str()] {
+            let request = DataStoreBuilder::new("moni-429523", data_store_id)
+                .display_name("Moni documents")
+                .build()
+                .unwrap();
+            assert_eq!(request.data_store_id, data_store_id);
+            // sent to the collection of the client
+            assert_eq!(request.collections, "");
+            assert_eq!(request.data_store.display_name, "Moni documents");
+        }
+    }
+
+    #[test]
+    fn test_data_store_builder_rejects_invalid_ids() {
+        let too_long = "a".repeat(MAX_DATA_STORE_ID_LEN + 1);
+        for data_store_id in [
+            "",
+            too_long.as_str(),
+            "1documents",
+            "-documents",
+            "documents-",
+            "Moni-documents",
+            "moni_documents",
+            "moni documents",
+        ] {
+            let result = DataStoreBuilder::new("moni-429523", data_store_id)
+                .display_name("Moni documents")
+                .build();
+            assert!(
+                matches!(&result, Err(Error::InvalidDataStoreId(id, _)) if id == data_store_id),
+                "{:?} should be rejected",
+                data_store_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_data_store_builder_requires_display_name() {
+        for builder in [
+            DataStoreBuilder::new("moni-429523", "moni-documents"),
+            DataStoreBuilder::new("moni-429523", "moni-documents").display_name("  "),
+        ] {
+            assert!(matches!(
+                builder.build(),
+                Err(Error::MissingField("display_name"))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_data_store_builder_with_ocr() {
+        let request = DataStoreBuilder::new("moni-429523", "moni-scans")
+            .display_name("Scanned reports")
+            .with_ocr(true, vec!["table".to_string()])
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&request.data_store).unwrap();
+        assert_eq!(
+            json["documentProcessingConfig"]["defaultParsingConfig"]["ocrParsingConfig"],
+            serde_json::json!({
+                "enhancedDocumentElements": ["table"],
+                "useNativeText": true
+            })
+        );
+        assert!(
+            json["documentProcessingConfig"]["defaultParsingConfig"]["digitalParsingConfig"]
+                .is_null()
+        );
+    }
+
+    #[test]
+    fn test_data_store_builder_rejects_ocr_with_digital_parsing() {
+        let digital = || DocumentProcessingConfig {
+            name: String::new(),
+            chunking_config: None,
+            default_parsing_config: Some(ParsingConfig {
+                digital_parsing_config: Some(DigitalParsingConfig {}),
+                ocr_parsing_config: None,
+                layout_parsing_config: None,
+            }),
+            parsing_config_overrides: None,
+        };
+
+        let result = DataStoreBuilder::new("moni-429523", "moni-scans")
+            .display_name("Scanned reports")
+            .document_processing_config(digital())
+            .with_ocr(false, vec![])
+            .build();
+        assert!(
+            matches!(result, Err(Error::ConflictingParsingConfig(scope)) if scope == "default")
+        );
+
+        // OCR for pdf files only does not conflict with the default digital parsing
+        let mut config = digital();
+        config.parsing_config_overrides = Some(HashMap::from([(
+            "pdf".to_string(),
+            ParsingConfig {
+                digital_parsing_config: None,
+                ocr_parsing_config: Some(OcrParsingConfig {
+                    enhanced_document_elements: None,
+                    use_native_text: Some(true),
+                }),
+                layout_parsing_config: None,
+            },
+        )]));
+        assert!(DataStoreBuilder::new("moni-429523", "moni-scans")
+            .display_name("Scanned reports")
+            .document_processing_config(config)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_data_store_builder_with_layout_chunking() {
+        let request = DataStoreBuilder::new("moni-429523", "moni-reports")
+            .display_name("Reports")
+            .with_layout_chunking(MAX_LAYOUT_CHUNK_SIZE, true)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(&request.data_store).unwrap();
+        assert_eq!(
+            json["documentProcessingConfig"]["chunkingConfig"]["layoutBasedChunkingConfig"],
+            serde_json::json!({"chunkSize": 500, "includeAncestorHeadings": true})
+        );
+
+        for chunk_size in [0, MIN_LAYOUT_CHUNK_SIZE - 1, MAX_LAYOUT_CHUNK_SIZE + 1] {
+            let result = DataStoreBuilder::new("moni-429523", "moni-reports")
+                .display_name("Reports")
+                .with_layout_chunking(chunk_size, false)
+                .build();
+            assert!(
+                matches!(result, Err(Error::InvalidChunkSize(size)) if size == chunk_size),
+                "chunk size {} was accepted",
+                chunk_size
+            );
+        }
+    }
+
+    fn gcs_entity() -> Entity {
+        Entity {
+            entity_name: "documents".to_string(),
+            params: EntityParams {
+                data_schema: "content".to_string(),
+                content_config: "CONTENT_REQUIRED".to_string(),
+                industry_vertical: "GENERIC".to_string(),
+                auto_generate_ids: true,
+            },
+        }
+    }
+
+    #[test]
+    fn test_data_connector_builder() {
+        let request = DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+            .instance_uris(vec!["gs://moni-demo-1".to_string()])
+            .refresh_interval(Duration::from_secs(86400))
+            .entity(gcs_entity())
+            .build()
+            .unwrap();
+
+        assert_eq!(request.collection_display_name, "moni-policies");
+        assert_eq!(request.data_connector.sync_mode, "PERIODIC");
+        let json = serde_json::to_value(&request.data_connector).unwrap();
+        assert_eq!(json["refresh_interval"], "86400s");
+    }
+
+    #[test]
+    fn test_google_duration_round_trip() {
+        for (duration, json) in [
+            (Duration::from_secs(86400), "\"86400s\""),
+            (Duration::ZERO, "\"0s\""),
+            (Duration::from_millis(1500), "\"1.5s\""),
+            (Duration::new(3, 1), "\"3.000000001s\""),
+        ] {
+            assert_eq!(
+                serde_json::to_string(&GoogleDuration(duration)).unwrap(),
+                json
+            );
+            let parsed: GoogleDuration = serde_json::from_str(json).unwrap();
+            assert_eq!(parsed.0, duration);
+        }
+
+        for invalid in [
+            "",
+            "86400",
+            "1d",
+            "-60s",
+            "s",
+            ".5s",
+            "1.5.0s",
+            "1e3s",
+            "1.0000000001s",
+        ] {
+            assert!(
+                serde_json::from_value::<GoogleDuration>(invalid.into()).is_err(),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_data_connector_builder_rejects_invalid_refresh_intervals() {
+        for interval in [
+            Duration::from_secs(60),
+            MIN_REFRESH_INTERVAL - Duration::from_secs(1),
+            MAX_REFRESH_INTERVAL + Duration::from_millis(1),
+        ] {
+            let result = DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+                .refresh_interval(interval)
+                .entity(gcs_entity())
+                .build();
+            assert!(
+                matches!(result, Err(Error::InvalidRefreshInterval(i)) if i == interval),
+                "{:?} was accepted",
+                interval
+            );
+        }
+        for interval in [Duration::ZERO, MIN_REFRESH_INTERVAL, MAX_REFRESH_INTERVAL] {
+            assert!(
+                DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+                    .refresh_interval(interval)
+                    .entity(gcs_entity())
+                    .build()
+                    .is_ok(),
+                "{:?} was rejected",
+                interval
+            );
+        }
+
+        let result = DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+            .entity(gcs_entity())
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::MissingField("refresh_interval"))
+        ));
+    }
+
+    #[test]
+    fn test_data_connector_builder_rejects_unknown_sync_modes() {
+        for sync_mode in ["periodic", "SYNC_MODE_UNSPECIFIED", ""] {
+            let result = DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+                .refresh_interval(Duration::from_secs(86400))
+                .sync_mode(sync_mode)
+                .entity(gcs_entity())
+                .build();
+            assert!(
+                matches!(&result, Err(Error::InvalidSyncMode(mode)) if mode == sync_mode),
+                "{:?} was accepted",
+                sync_mode
+            );
+        }
+    }
+
+    #[test]
+    fn test_data_connector_builder_requires_an_entity() {
+        let result = DataConnectorBuilder::new("moni-429523", "moni-policies", "gcs")
+            .refresh_interval(Duration::from_secs(86400))
+            .build();
+        assert!(matches!(result, Err(Error::MissingField("entities"))));
+    }
+
+    fn response(status: u16, body: &str) -> reqwest::Response {
+        http::Response::builder()
+            .status(status)
+            .body(body.to_string())
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_parse_json_success() {
+        let operation: Operation = parse_json(response(
+            200,
+            r#"{"name": "projects/moni/operations/import-1", "done": true}"#,
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(operation.name, "projects/moni/operations/import-1");
+        assert!(operation.done);
+    }
+
+    #[tokio::test]
+    async fn test_parse_json_api_error() {
+        let body = r#"{"error": {"code": 403, "message": "Permission 'discoveryengine.dataStores.get' denied", "status": "PERMISSION_DENIED"}}"#;
+
+        match parse_json::<DataStore>(response(403, body)).await {
+            Err(Error::ApiError(status)) => {
+                assert_eq!(status.code, 403);
+                assert_eq!(status.status, "PERMISSION_DENIED");
+                assert_eq!(
+                    status.message,
+                    "Permission 'discoveryengine.dataStores.get' denied"
+                );
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // bodies that are not a Google error are kept as the message
+        match parse_json::<DataStore>(response(502, "<html>Bad Gateway</html>")).await {
+            Err(Error::ApiError(status)) => {
+                assert_eq!(status.code, 502);
+                assert_eq!(status.message, "<html>Bad Gateway</html>");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct AnswerGenerationSpec {
-    pub model_spec: ModelSpec,
-    pub prompt_spec: ModelPromptSpec,
-    pub include_citations: bool,
-    pub answer_language_code: String,
-    pub ignore_adversarial_query: bool,
-    pub ignore_non_answer_seeking_query: bool,
-    pub ignore_low_relevant_content: bool,
-}
+    #[tokio::test]
+    async fn test_parse_json_quota_exceeded() {
+        let body = r#"{"error": {"code": 429, "message": "Quota exceeded for quota metric 'Search requests' and limit 'Search requests per minute' of service 'discoveryengine.googleapis.com' for consumer 'project_number:123456789'.", "status": "RESOURCE_EXHAUSTED", "details": [{"@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "RATE_LIMIT_EXCEEDED", "domain": "googleapis.com"}, {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "42s"}]}}"#;
+
+        let with_header: reqwest::Response = http::Response::builder()
+            .status(429)
+            .header("retry-after", "30")
+            .body(body.to_string())
+            .unwrap()
+            .into();
+        match parse_json::<DataStore>(with_header).await {
+            Err(Error::QuotaExceeded { retry_after }) => {
+                assert_eq!(retry_after, Some(Duration::from_secs(30)))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // without the header the delay of the RetryInfo detail is used
+        match parse_json::<DataStore>(response(429, body)).await {
+            Err(Error::QuotaExceeded { retry_after }) => {
+                assert_eq!(retry_after, Some(Duration::from_secs(42)))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match parse_json::<DataStore>(response(429, "Too Many Requests")).await {
+            Err(Error::QuotaExceeded { retry_after }) => assert_eq!(retry_after, None),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct RelatedQuestionsSpec {
-    pub enable: bool,
-}
+    #[tokio::test]
+    async fn test_cancel_operation() {
+        let name = "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/operations/import-documents-1";
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SafetySpec {
-    pub enable: bool,
-}
+        assert!(parse_cancel_response(name, response(200, "{}"))
+            .await
+            .is_ok());
+
+        let done = r#"{"error": {"code": 400, "message": "Operation is already done.", "status": "FAILED_PRECONDITION"}}"#;
+        match parse_cancel_response(name, response(400, done)).await {
+            Err(Error::OperationNotCancellable(operation, message)) => {
+                assert_eq!(operation, name);
+                assert_eq!(message, "Operation is already done.");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let missing =
+            r#"{"error": {"code": 404, "message": "Operation not found.", "status": "NOT_FOUND"}}"#;
+        assert!(matches!(
+            parse_cancel_response(name, response(404, missing)).await,
+            Err(Error::ApiError(status)) if status.code == 404
+        ));
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct Query {
-    pub query_id: String,
-    pub text: String,
-}
+    #[tokio::test]
+    async fn test_parse_json_malformed_body() {
+        match parse_json::<Operation>(response(200, r#"{"name": "operations/1", "done": "#)).await {
+            Err(error @ Error::InvalidResponse { .. }) => {
+                assert!(error
+                    .to_string()
+                    .contains(r#"{"name": "operations/1", "done": "#));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ListDocumentsResponse {
-    documents: Vec<Document>,
-    next_page_token: Option<String>,
-}
+    #[test]
+    fn test_answer_steps_progress() {
+        let mut answer: Answer = serde_json::from_value(serde_json::json!({
+            "name": "projects/moni/locations/global/collections/default_collection/engines/moni/sessions/123/answers/456",
+            "state": "IN_PROGRESS",
+            "answerText": "",
+            "citations": [],
+            "references": [],
+            "relatedQuestions": [],
+            "steps": [
+                {
+                    "state": "SUCCEEDED",
+                    "description": "Rephrase the query and search.",
+                    "thought": "I need the adaptation plans of Colombia.",
+                    "actions": [
+                        {"searchAction": {"query": "Colombia national adaptation plan"}},
+                        {"searchAction": {"query": "Colombia climate adaptation policy"}}
+                    ]
+                },
+                {
+                    "state": "IN_PROGRESS",
+                    "description": "Summarize the results."
+                },
+                {
+                    "state": "STATE_UNSPECIFIED"
+                }
+            ],
+            "queryUnderstandingInfo": {"queryClassificationInfo": []},
+            "answerSkippedReasons": [],
+            "createTime": "2024-08-01T10:00:00Z",
+            "completeTime": ""
+        }))
+        .unwrap();
+
+        assert!(!answer.is_complete());
+        assert_eq!(answer.steps[0].kind(), StepKind::Search);
+        assert_eq!(
+            answer.steps[0].search_queries(),
+            vec![
+                "Colombia national adaptation plan",
+                "Colombia climate adaptation policy"
+            ]
+        );
+        assert_eq!(answer.steps[1].kind(), StepKind::Thought);
+        assert!(answer.steps[0].is_finished());
+        assert!(!answer.steps[1].is_finished());
+        assert_eq!(
+            answer.current_step().unwrap().description,
+            "Summarize the results."
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Document {
-    pub name: String,
-    pub id: String,
-    pub content: Option<Content>,
-    pub parent_document_id: Option<String>,
-    pub derived_struct_data: Option<serde_json::Value>,
-    pub acl_info: Option<AclInfo>,
-    pub index_time: Option<String>,
-    #[serde(flatten)]
-    pub data: Option<DocumentData>,
-}
+        answer.steps[1].state = State::Failed;
+        answer.state = State::Failed;
+        assert!(answer.is_complete());
+        assert_eq!(answer.current_step().unwrap().state, State::Failed);
+
+        for step in &mut answer.steps {
+            step.state = State::Succeeded;
+        }
+        answer.state = State::Succeeded;
+        assert!(answer.is_complete());
+        assert!(std::ptr::eq(
+            answer.current_step().unwrap(),
+            answer.steps.last().unwrap()
+        ));
+        answer.steps.clear();
+        assert!(answer.current_step().is_none());
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Content {
-    pub mime_type: String,
-    #[serde(flatten)]
-    pub content: Option<ContentData>,
-}
+    #[test]
+    fn test_answer_with_search_results_omits_search_params() {
+        let request = AnswerRequest::with_search_results(
+            "moni-429523",
+            "How much can carbon credits offset?",
+            vec![AnswerSearchResult {
+                chunk_info: ChunkInfo {
+                    chunk: "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/1/chunks/c1".to_string(),
+                    content: "Carbon credits may offset at most 20% of the emissions.".to_string(),
+                },
+                ..Default::default()
+            }],
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum ContentData {
-    RawBytes { raw_bytes: String },
-    Uri { uri: String },
-}
+        let json = serde_json::to_value(&request.discovery_engine_answer_request).unwrap();
+
+        assert_eq!(json["query"]["text"], "How much can carbon credits offset?");
+        let search_spec = &json["searchSpec"];
+        assert!(search_spec.get("searchParams").is_none());
+        let results = search_spec["searchResultList"]["searchResults"]
+            .as_array()
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0]["chunkInfo"]["content"],
+            "Carbon credits may offset at most 20% of the emissions."
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct AclInfo {
-    readers: Option<Vec<AccessRestriction>>,
-}
+        let searched = serde_json::to_value(DiscoveryEngineAnswerRequest::default()).unwrap();
+        assert_eq!(searched["searchSpec"], serde_json::json!({}));
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct AccessRestriction {
-    pub principals: Option<Vec<Principal>>,
-}
+    #[test]
+    fn test_query_understanding_filter_string() {
+        let info: NaturalLanguageQueryUnderstandingInfo = serde_json::from_value(serde_json::json!({
+            "rewrittenQuery": "climate policies",
+            "structuredExtractedFilter": {
+                "expression": {
+                    "andExpr": {
+                        "expressions": [
+                            {"numberConstraint": {"fieldName": "year", "comparison": "GREATER_THAN_EQUALS", "value": 2020}},
+                            {"orExpr": {"expressions": [
+                                {"stringConstraint": {"fieldName": "country", "values": ["Colombia", "Perú"]}},
+                                {"andExpr": {"expressions": [
+                                    {"stringConstraint": {"fieldName": "author", "values": ["Ministerio \"MinAmbiente\""]}},
+                                    {"numberConstraint": {"fieldName": "pages", "comparison": "LESS_THAN", "value": 50.5}}
+                                ]}}
+                            ]}},
+                            {"geolocationConstraint": {"fieldName": "location", "address": "Bogotá", "radiusInMeters": 5000}}
+                        ]
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            info.to_filter_string().unwrap(),
+            r#"year >= 2020 AND (country: ANY("Colombia", "Perú") OR (author: ANY("Ministerio \"MinAmbiente\"") AND pages < 50.5)) AND GEO_DISTANCE(location, "Bogotá", 5000)"#
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Principal {
-    #[serde(flatten)]
-    pub principal: Option<PrincipalType>,
-}
+        let unfiltered: NaturalLanguageQueryUnderstandingInfo =
+            serde_json::from_value(serde_json::json!({"rewrittenQuery": "climate"})).unwrap();
+        assert_eq!(unfiltered.to_filter_string(), None);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum PrincipalType {
-    UserId { user_id: String },
-    GroupId { group_id: String },
-}
+    #[test]
+    fn test_order_by_clauses() {
+        let orderable = ["title", "update_time", "year"];
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum DocumentData {
-    StructData { struct_data: serde_json::Value },
-    JsonData { json_data: String },
-}
-pub struct SearchRequest {
-    pub project_id: String,
-    pub discovery_engine_search_request: DiscoveryEngineSearchRequest,
-}
+        assert_eq!(
+            OrderBy::new(&orderable).asc("title").build().unwrap(),
+            "title"
+        );
+        assert_eq!(
+            OrderBy::new(&orderable)
+                .desc("update_time")
+                .asc("title")
+                .desc("year")
+                .build()
+                .unwrap(),
+            "update_time desc,title,year desc"
+        );
+        assert_eq!(OrderBy::new(&orderable).build().unwrap(), "");
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchResponse {
-    pub results: Option<Vec<SearchResult>>,
-    pub facets: Option<Vec<Facet>>,
-    pub guided_search_result: Option<GuidedSearchResult>,
-    pub total_size: Option<i32>,
-    pub attribution_token: Option<String>,
-    pub redirect_uri: Option<String>,
-    pub next_page_token: Option<String>,
-    pub corrected_query: Option<String>,
-    pub summary: Option<Summary>,
-    pub applied_controls: Option<Vec<String>>,
-    pub geo_search_debug_info: Option<Vec<GeoSearchDebugInfo>>,
-    pub query_expansion_info: Option<QueryExpansionInfo>,
-    pub natural_language_query_understanding_info: Option<NaturalLanguageQueryUnderstandingInfo>,
-    pub session_info: Option<SessionInfo>,
-}
+    #[test]
+    fn test_order_by_rejects_unknown_fields() {
+        let orderable = ["title", "update_time"];
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct NaturalLanguageQueryUnderstandingInfo {
-    pub extracted_filters: Option<String>,
-    pub rewritten_query: Option<String>,
-    pub structured_extracted_filter: Option<StructuredExtractedFilter>,
-}
+        let result = OrderBy::new(&orderable)
+            .asc("title")
+            .desc("updated_time")
+            .build();
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct StructuredExtractedFilter {
-    pub expression: Option<Expression>,
-}
+        assert!(
+            matches!(result, Err(Error::UnknownOrderByField(field)) if field == "updated_time")
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-#[serde(untagged)]
-pub enum Expression {
-    StringConstraint {
-        string_constraint: StringConstraint,
-    },
-    NumberConstraint {
-        number_constraint: NumberConstraint,
-    },
-    GeolocationConstraint {
-        geolocation_constraint: GeolocationConstraint,
-    },
-    AndExpr {
-        and_expr: AndExpression,
-    },
-    OrExpr {
-        or_expr: OrExpression,
-    },
-}
+    // DocumentStore answers get_document from a fixed set of documents, the ids listed first
+    // are the slowest so the batch has to restore the order
+    struct DocumentStore {
+        ids: Vec<&'static str>,
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct StringConstraint {
-    pub field_name: String,
-    pub values: Vec<String>,
-}
+    impl DocumentReader for DocumentStore {
+        async fn get_document(&self, request: GetDocumentRequest) -> Result<Document, Error> {
+            let position = self.ids.iter().position(|id| *id == request.document_id);
+            let delay = 5 * (self.ids.len() - position.unwrap_or(0)) as u64;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            if position.is_none() {
+                return Err(Error::ApiError(Status {
+                    code: 404,
+                    message: format!("Document {} not found", request.document_id),
+                    status: "NOT_FOUND".to_string(),
+                    details: vec![],
+                }));
+            }
+            Ok(serde_json::from_value(serde_json::json!({
+                "name": format!("{}/documents/{}", request.branch, request.document_id),
+                "id": request.document_id,
+            }))
+            .unwrap())
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct NumberConstraint {
-    pub field_name: String,
-    pub comparison: Comparison,
-    pub value: f64,
-}
+    #[tokio::test]
+    async fn test_batch_get_documents_keeps_order_and_collects_errors() {
+        let store = DocumentStore {
+            ids: vec!["adaptation-plan", "ndc-2020", "ndc-2015"],
+        };
+        let ids = ["ndc-2015", "missing", "adaptation-plan", "ndc-2020"];
+
+        let response = batch_get_documents(
+            &store,
+            BatchGetDocumentsRequest {
+                project_id: "moni-429523".to_string(),
+                collections: "default_collection".to_string(),
+                data_store_id: "moni-reports".to_string(),
+                branch: "default_branch".to_string(),
+                document_ids: ids.iter().map(|id| id.to_string()).collect(),
+            },
+        )
+        .await;
+
+        let found: Vec<&str> = response
+            .documents
+            .iter()
+            .map(|document| document.id.as_str())
+            .collect();
+        assert_eq!(found, vec!["ndc-2015", "adaptation-plan", "ndc-2020"]);
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].document_id, "missing");
+        assert!(matches!(&response.errors[0].error, Error::ApiError(status) if status.code == 404));
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum Comparison {
-    ComparisonUnspecified,
-    Equals,
-    LessThanEquals,
-    LessThan,
-    GreaterThanEquals,
-    GreaterThan,
-}
+    #[test]
+    fn test_serialize_recrawl_uris_body() {
+        let mut request = recrawl_request(vec![
+            "https://www.minambiente.gov.co/politica-nacional-cambio-climatico".to_string(),
+        ]);
+        assert_eq!(
+            serde_json::to_value(&request.body).unwrap(),
+            serde_json::json!({
+                "uris": ["https://www.minambiente.gov.co/politica-nacional-cambio-climatico"]
+            })
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct GeolocationConstraint {
-    pub field_name: String,
-    pub address: String,
-    pub radius_in_meters: f64,
-}
+        request.body.site_credential = Some("intranet".to_string());
+        assert_eq!(
+            serde_json::to_value(&request.body).unwrap()["siteCredential"],
+            "intranet"
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct AndExpression {
-    pub expressions: Vec<Expression>,
-}
+    #[test]
+    fn test_spell_correction_mode_round_trip() {
+        for (mode, json) in [
+            (Mode::ModeUnspecified, "MODE_UNSPECIFIED"),
+            (Mode::SuggestionOnly, "SUGGESTION_ONLY"),
+            (Mode::Auto, "AUTO"),
+        ] {
+            let value = serde_json::to_value(&mode).unwrap();
+            assert_eq!(value, json);
+            let parsed: Mode = serde_json::from_value(value).unwrap();
+            assert_eq!(serde_json::to_value(parsed).unwrap(), json);
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct OrExpression {
-    pub expressions: Vec<Expression>,
-}
+    #[test]
+    fn test_serialize_create_engine_body() {
+        let engine = Engine {
+            name: String::new(),
+            display_name: "moni-demo-final".to_string(),
+            data_store_ids: vec!["moni-demo_1722720098936".to_string()],
+            solution_type: SolutionType::Search,
+            industry_vertical: Some(IndustryVertical::Generic),
+            search_engine_config: Some(SearchEngineConfig {
+                search_tier: SearchTier::SearchTierEnterprise,
+                search_add_ons: vec![SearchAddOn::SearchAddOnLlm],
+            }),
+            create_time: None,
+            update_time: None,
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct QueryExpansionInfo {
-    pub expanded_query: bool,
-    pub pinned_result_count: Option<String>,
-}
+        assert_eq!(
+            serde_json::to_value(&engine).unwrap(),
+            serde_json::json!({
+                "displayName": "moni-demo-final",
+                "dataStoreIds": ["moni-demo_1722720098936"],
+                "solutionType": "SOLUTION_TYPE_SEARCH",
+                "industryVertical": "GENERIC",
+                "searchEngineConfig": {
+                    "searchTier": "SEARCH_TIER_ENTERPRISE",
+                    "searchAddOns": ["SEARCH_ADD_ON_LLM"]
+                }
+            })
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct GeoSearchDebugInfo {
-    pub original_address_query: String,
-    pub error_message: String,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Summary {
-    pub summary_text: Option<String>,
-    pub summary_skipped_reasons: Option<Vec<SummarySkippedReason>>,
-    pub safety_attributes: Option<SafetyAttributes>,
-    pub summary_with_metadata: Option<SummaryWithMetadata>,
-}
+    #[test]
+    fn test_schema_round_trip() {
+        let schema: Schema =
+            serde_json::from_str(include_str!("../../testdata/schema.json")).unwrap();
+        assert!(schema.name.ends_with("/schemas/default_schema"));
+        let struct_schema = schema.struct_schema.unwrap();
+        assert_eq!(
+            struct_schema["properties"]["country"]["indexable"],
+            serde_json::json!(true)
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-#[allow(clippy::enum_variant_names)]
-pub enum SummarySkippedReason {
-    #[default]
-    SummarySkippedReasonUnspecified,
-    AdversarialQueryIgnored,
-    NonSummarySeekingQueryIgnored,
-    OutOfDomainQueryIgnored,
-    PotentialPolicyViolation,
-    LlmAddonNotEnabled,
-}
+        let update = Schema {
+            struct_schema: Some(serde_json::json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {"year": {"type": "number", "retrievable": true}}
+            })),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&update).unwrap();
+        assert!(json.get("name").is_none());
+        assert!(json.get("jsonSchema").is_none());
+        assert_eq!(json["structSchema"]["properties"]["year"]["type"], "number");
+    }
+
+    // RecordingAnswerClient opens a session on search and records the answer request.
+    #[derive(Default)]
+    struct RecordingAnswerClient {
+        search_requests: Mutex<Vec<Value>>,
+        answer_requests: Mutex<Vec<DiscoveryEngineAnswerRequest>>,
+    }
+
+    impl RecordingAnswerClient {
+        fn answer(&self, session: String) -> Answer {
+            Answer {
+                name: format!("{}/answers/456", session),
+                state: State::Succeeded,
+                answer_text: "Colombia adopted a national adaptation plan.".to_string(),
+                citations: vec![],
+                references: vec![],
+                related_questions: vec![],
+                steps: vec![],
+                query_understanding_info: QueryUnderstandingInfo {
+                    query_classification_info: vec![],
+                },
+                answer_skipped_reasons: vec![],
+                create_time: String::new(),
+                complete_time: String::new(),
+            }
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SafetyAttributes {
-    pub categories: Option<Vec<String>>,
-    pub scores: Option<Vec<f64>>,
-}
+    impl AnswerOperations for RecordingAnswerClient {
+        async fn search_serving_config(
+            &self,
+            serving_config: &str,
+            request: &DiscoveryEngineSearchRequest,
+        ) -> Result<SearchResponse, Error> {
+            assert_eq!(
+                request.session,
+                format!(
+                    "{}/sessions/-",
+                    serving_config.split("/servingConfigs/").next().unwrap()
+                )
+            );
+            self.search_requests
+                .lock()
+                .unwrap()
+                .push(serde_json::to_value(request).unwrap());
+            Ok(SearchResponse {
+                session_info: Some(SessionInfo {
+                    name: "projects/p/locations/global/collections/default_collection/engines/e/sessions/123".to_string(),
+                    query_id: "projects/p/locations/global/collections/default_collection/engines/e/sessions/123/answers/456".to_string(),
+                }),
+                ..Default::default()
+            })
+        }
+
+        async fn answer_serving_config(
+            &self,
+            _serving_config: &str,
+            request: DiscoveryEngineAnswerRequest,
+        ) -> Result<FeedbackAnswerQueryResponse, Error> {
+            let session = request.session.clone();
+            self.answer_requests.lock().unwrap().push(request);
+            Ok(FeedbackAnswerQueryResponse {
+                answer: self.answer(session.clone()),
+                session: Session {
+                    name: session,
+                    state: SessionState::InProgress,
+                    user_pseudo_id: String::new(),
+                    turns: vec![],
+                    start_time: String::new(),
+                    end_time: String::new(),
+                },
+                answer_query_token: String::new(),
+            })
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SummaryWithMetadata {
-    pub summary: String,
-    pub citation_metadata: Option<CitationMetadata>,
-    pub references: Option<Vec<Reference>>,
-}
+    // SessionClient opens session 1 when asked for a new one and records the session of every
+    // search and answer request.
+    #[derive(Default)]
+    struct SessionClient {
+        sessions: Mutex<Vec<String>>,
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct CitationMetadata {
-    pub citations: Option<Vec<Citation>>,
-}
+    impl AnswerOperations for SessionClient {
+        async fn search_serving_config(
+            &self,
+            _: &str,
+            request: &DiscoveryEngineSearchRequest,
+        ) -> Result<SearchResponse, Error> {
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.push(request.session.clone());
+            let name = request.session.replace("sessions/-", "sessions/1");
+            Ok(SearchResponse {
+                session_info: Some(SessionInfo {
+                    query_id: format!("{}/answers/{}", name, sessions.len()),
+                    name,
+                }),
+                ..Default::default()
+            })
+        }
+
+        async fn answer_serving_config(
+            &self,
+            _: &str,
+            request: DiscoveryEngineAnswerRequest,
+        ) -> Result<FeedbackAnswerQueryResponse, Error> {
+            self.sessions.lock().unwrap().push(request.session.clone());
+            let recording = RecordingAnswerClient::default();
+            recording.answer_serving_config("", request).await
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Citation {
-    pub start_index: String,
-    pub end_index: String,
-    pub sources: Option<Vec<CitationSource>>,
-}
+    #[tokio::test]
+    async fn test_conversation_reuses_the_session_of_the_first_question() {
+        let client = SessionClient::default();
+        let mut conversation = Conversation::new(
+            DataStoreClient::new().await.unwrap(),
+            "moni-429523",
+            "moni-engine",
+            true,
+        );
+        let engine = "projects/moni-429523/locations/global/collections/default_collection/engines/moni-engine";
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct CitationSource {
-    pub reference_index: String,
-}
+        conversation
+            .ask_with(&client, "What is the national adaptation plan?")
+            .await
+            .unwrap();
+        conversation
+            .ask_with(&client, "And in Bogotá?")
+            .await
+            .unwrap();
+
+        let session = format!("{}/sessions/1", engine);
+        assert_eq!(conversation.session(), Some(session.as_str()));
+        assert_eq!(
+            *client.sessions.lock().unwrap(),
+            vec![
+                format!("{}/sessions/-", engine),
+                session.clone(),
+                session.clone(),
+                session.clone(),
+            ]
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Reference {
-    pub title: Option<String>,
-    pub document: String,
-    pub uri: Option<String>,
-    pub chunk_contents: Option<Vec<ChunkContent>>,
-}
+        conversation.reset();
+        assert_eq!(conversation.session(), None);
+        conversation
+            .ask_with(&client, "What about mitigation?")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.sessions.lock().unwrap()[4],
+            format!("{}/sessions/-", engine)
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ChunkContent {
-    pub content: String,
-    pub page_identifier: Option<String>,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct GuidedSearchResult {
-    pub refinement_attributes: Option<Vec<RefinementAttribute>>,
-    pub follow_up_questions: Option<Vec<String>>,
-}
+    fn reference(title: &str, uri: &str, chunk: &str) -> AnswerReference {
+        AnswerReference {
+            unstructured_document_info: UnstructureDocumentInfo {
+                document: format!("documents/{}", title),
+                uri: uri.to_string(),
+                tittle: title.to_string(),
+                ..Default::default()
+            },
+            chunk_info: ChunkInfo {
+                chunk: String::new(),
+                content: chunk.to_string(),
+            },
+            structured_document_info: StructuredDocumentInfo {
+                document: String::new(),
+                struct_data: Value::Null,
+            },
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct RefinementAttribute {
-    pub attribute_key: String,
-    pub attribute_value: String,
-}
+    fn citation(start: &str, end: &str, reference_indexes: &[&str]) -> Citation {
+        Citation {
+            start_index: start.to_string(),
+            end_index: end.to_string(),
+            sources: Some(
+                reference_indexes
+                    .iter()
+                    .map(|index| CitationSource {
+                        reference_index: index.to_string(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Facet {
-    pub key: String,
-    pub values: Vec<FacetValue>,
-    pub dynamic_facet: bool,
-}
+    #[test]
+    fn test_resolved_citations_join_references() {
+        let mut answer = RecordingAnswerClient::default().answer("session".to_string());
+        answer.answer_text =
+            "Colombia adopted a national adaptation plan. Bogota expanded its bike lanes."
+                .to_string();
+        answer.references = vec![
+            reference(
+                "PNACC",
+                "gs://moni/pnacc.pdf",
+                "The national adaptation plan...",
+            ),
+            reference("Bogota mobility", "gs://moni/bogota.pdf", ""),
+        ];
+        answer.references[1]
+            .unstructured_document_info
+            .extractive_segments = vec![ExtractiveSegments {
+            page_identifier: "3".to_string(),
+            content: "Bike lanes grew by 20%".to_string(),
+        }];
+        answer.citations = vec![
+            citation("0", "44", &["0"]),
+            citation("45", "76", &["1", "7", "x"]),
+        ];
+
+        let resolved = answer.resolved_citations();
+
+        assert_eq!(
+            resolved,
+            vec![
+                ResolvedCitation {
+                    start_index: 0,
+                    end_index: 44,
+                    reference_index: 0,
+                    title: "PNACC".to_string(),
+                    uri: "gs://moni/pnacc.pdf".to_string(),
+                    snippet: Some("The national adaptation plan...".to_string()),
+                },
+                ResolvedCitation {
+                    start_index: 45,
+                    end_index: 76,
+                    reference_index: 1,
+                    title: "Bogota mobility".to_string(),
+                    uri: "gs://moni/bogota.pdf".to_string(),
+                    snippet: Some("Bike lanes grew by 20%".to_string()),
+                },
+            ]
+        );
+        assert_eq!(answer.cited_titles(), vec!["PNACC", "Bogota mobility"]);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct FacetValue {
-    pub count: String,
-    #[serde(flatten)]
-    pub facet_value: FacetValueType,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum FacetValueType {
-    Value { value: String },
-    Interval { interval: Interval },
-}
+    fn facets_response() -> SearchResponse {
+        serde_json::from_value(serde_json::json!({
+            "facets": [
+                {
+                    "key": "country",
+                    "values": [
+                        {"value": "Colombia", "count": "12"},
+                        {"value": "Peru", "count": "4"},
+                        {"value": "Costa \"Rica\"", "count": "1"}
+                    ]
+                },
+                {
+                    "key": "year",
+                    "values": [
+                        {"interval": {"minimum": 2000, "exclusiveMaximum": 2010}, "count": "3"},
+                        {"interval": {"minimum": 2010, "exclusiveMaximum": 2020}, "count": "8"},
+                        {"interval": {"minimum": 2020}, "count": "5"}
+                    ],
+                    "dynamicFacet": true
+                }
+            ]
+        }))
+        .unwrap()
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchResult {
-    pub id: Option<String>,
-    pub document: Option<Document>,
-    pub chunk: Option<Chunk>,
-    pub model_scores: Option<HashMap<String, DoubleList>>,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct DoubleList {
-    pub values: Option<Vec<f64>>,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Snippet {
-    pub snippet_status: String,
-    pub snippet: String,
-}
+    #[test]
+    fn test_value_facet_to_filter() {
+        let response = facets_response();
+        let country = response.facet("country").unwrap();
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ExtractiveAnswer {
-    pub page_number: String,
-    pub content: String,
-}
+        assert!(response.facet("language").is_none());
+        assert_eq!(country.to_filter(&[]), "");
+        assert_eq!(
+            country.to_filter(&["Colombia", "Costa \"Rica\"", "Chile"]),
+            r#"country: ANY("Colombia", "Costa \"Rica\"")"#
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SessionInfo {
-    pub name: String,
-    pub query_id: String,
-}
+    #[test]
+    fn test_interval_facet_to_filter() {
+        let response = facets_response();
+        let year = response.facet("year").unwrap();
+
+        let labels: Vec<String> = year.values.iter().map(FacetValue::label).collect();
+        assert_eq!(labels, vec!["2000-2010", "2010-2020", "2020-*"]);
+        assert_eq!(year.to_filter(&["2020-*"]), "year: IN(2020, *)");
+        assert_eq!(
+            year.to_filter(&["2000-2010", "2010-2020"]),
+            "(year: IN(2000, 2010e) OR year: IN(2010, 2020e))"
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct DiscoveryEngineSearchRequest {
-    pub branch: String,
-    pub query: String,
-    pub image_query: ImageQuery,
-    pub page_size: u32,
-    pub page_token: String,
-    pub offset: u32,
-    pub data_store_specs: Vec<DataStoreSpec>,
-    pub filter: String,
-    pub canonical_filter: String,
-    pub order_by: String,
-    pub user_info: UserInfo,
-    pub language_code: String,
-    pub facet_specs: Vec<FacetSpec>,
-    pub boost_spec: BoostSpec,
-    pub params: HashMap<String, Value>,
-    pub query_expansion_spec: QueryExpansionSpec,
-    pub spell_correction_spec: SpellCorrectionSpec,
-    pub user_pseudo_id: String,
-    pub content_search_spec: ContentSearchSpec,
-    pub safe_search: bool,
-    pub user_labels: HashMap<String, Value>,
-    pub search_as_you_type_spec: SearchAsYouTypeSpec,
-    pub session: String,
-    pub session_spec: SessionSpec,
-}
+    #[tokio::test]
+    async fn test_grounded_answer_reuses_search_session() {
+        let client = RecordingAnswerClient::default();
+
+        let answer = grounded_answer(
+            &client,
+            &serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            "colombian climate adaptation",
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert!(answer.name.starts_with(
+            "projects/p/locations/global/collections/default_collection/engines/e/sessions/123"
+        ));
+        let requests = client.answer_requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].session.ends_with("/sessions/123"));
+        assert!(requests[0].query.query_id.ends_with("/answers/456"));
+        assert_eq!(requests[0].query.text, "colombian climate adaptation");
+        assert!(requests[0].answer_generation_spec.include_citations);
+        assert!(requests[0].related_questions_spec.enable);
+        assert!(!requests[0].safety_spec.enable);
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SessionSpec {
-    pub query_id: String,
-    pub search_result_persistence_count: u32,
-}
+    #[tokio::test]
+    async fn test_grounded_answer_with_safe_search() {
+        let client = RecordingAnswerClient::default();
+
+        grounded_answer(
+            &client,
+            &serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            "bogota air quality",
+            true,
+        )
+        .await
+        .unwrap();
+
+        let searches = client.search_requests.lock().unwrap();
+        assert_eq!(searches[0]["safeSearch"], true);
+        let answers = client.answer_requests.lock().unwrap();
+        assert!(answers[0].safety_spec.enable);
+        assert_eq!(
+            serde_json::to_value(&answers[0]).unwrap()["safetySpec"]["enable"],
+            true
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchAsYouTypeSpec {
-    pub condition: SearchAsYouTypeCondition,
-}
+    #[test]
+    fn test_search_result_model_scores() {
+        let result: SearchResult = serde_json::from_value(serde_json::json!({
+            "id": "pnacc",
+            "modelScores": {
+                "relevance": {"values": [0.87, 0.5]},
+                "freshness": {"values": []}
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(result.relevance(), Some(0.87));
+        assert_eq!(result.score("freshness"), None);
+        assert_eq!(result.score("popularity"), None);
+
+        let unscored: SearchResult =
+            serde_json::from_value(serde_json::json!({"id": "cali"})).unwrap();
+        assert_eq!(unscored.relevance(), None);
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ContentSearchSpec {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub snippet_spec: Option<SnippetSpec>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub summary_spec: Option<SummarySpec>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub chunk_spec: Option<ChunkSpec>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub extractive_content_spec: Option<ExtractiveContentSpec>,
-    pub search_result_mode: SearchResultMode,
-}
+    #[test]
+    fn test_document_fields() {
+        let document: Document = serde_json::from_value(serde_json::json!({
+            "name": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/7f3c",
+            "id": "7f3c",
+            "content": {"mimeType": "application/pdf", "uri": "gs://moni-demo-1/ndc-colombia-2020.pdf"},
+            "derivedStructData": {
+                "title": "Actualización de la NDC de Colombia",
+                "link": "gs://moni-demo-1/ndc-colombia-2020.pdf",
+                "can_fetch_raw_content": "true",
+                "page_count": "112",
+                "relevance": 0.82,
+                "snippets": [
+                    {"snippet": "Colombia se compromete a reducir el <b>51%</b>", "snippet_status": "SUCCESS"}
+                ],
+                "extractive_answers": [
+                    {"pageNumber": "14", "content": "La meta de mitigación es emitir máximo 169 Mt CO2eq en 2030."}
+                ]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            document.title(),
+            Some("Actualización de la NDC de Colombia")
+        );
+        assert_eq!(
+            document.get_str("link"),
+            Some("gs://moni-demo-1/ndc-colombia-2020.pdf")
+        );
+        assert_eq!(document.get_f64("page_count"), Some(112.0));
+        assert_eq!(document.get_f64("relevance"), Some(0.82));
+        assert_eq!(document.get_f64("link"), None);
+        assert_eq!(document.get_str("relevance"), None);
+        assert_eq!(document.field("missing"), None);
+        assert_eq!(document.field("snippets"), None);
+
+        // the struct data of a structured document wins over the derived fields
+        let mut structured = structured_document(serde_json::json!({
+            "title": "Climate Action Plan 2024",
+            "year": 2024
+        }));
+        assert_eq!(structured.title(), Some("Climate Action Plan 2024"));
+        assert_eq!(structured.get_f64("year"), Some(2024.0));
+        structured.derived_struct_data = Some(DerivedStructData {
+            fields: BTreeMap::from([("title".to_string(), Value::from("derived"))]),
+            ..Default::default()
+        });
+        assert_eq!(structured.title(), Some("Climate Action Plan 2024"));
+
+        let untitled = structured_document(serde_json::json!({"title": ""}));
+        assert_eq!(untitled.title(), None);
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum SearchResultMode {
-    #[default]
-    SearchResultModeUnspecified,
-    Documents,
-    Chunks,
-}
+    #[test]
+    fn test_search_request_data_stores() {
+        let request = DiscoveryEngineSearchRequest {
+            query: "carbon credits".to_string(),
+            ..Default::default()
+        }
+        .add_data_store(data_store_name(
+            &Location::Global,
+            "moni-429523",
+            "",
+            "policies",
+        ))
+        .add_data_store(data_store_name(
+            &Location::Global,
+            "moni-429523",
+            "reports",
+            "news",
+        ));
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["dataStoreSpecs"],
+            serde_json::json!([
+                {"dataStore": "projects/moni-429523/locations/global/collections/default_collection/dataStores/policies"},
+                {"dataStore": "projects/moni-429523/locations/global/collections/reports/dataStores/news"}
+            ])
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SummarySpec {
-    pub summary_result_count: u32,
-    pub include_citations: bool,
-    pub ignore_adversarial_query: bool,
-    pub ignore_non_summary_seeking_query: bool,
-    pub model_prompt_spec: ModelPromptSpec,
-    pub language_mode: String,
-    pub model_spec: ModelSpec,
-    pub use_semantic_chunks: bool,
-}
+    #[test]
+    fn test_search_result_extractive_answers_and_snippets() {
+        let result: SearchResult = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "document": {
+                "name": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/1",
+                "id": "1",
+                "derivedStructData": {
+                    "title": "Climate Action Plan 2024",
+                    "link": "gs://moni-demo-1/climate-action-plan-2024.pdf",
+                    "snippets": [
+                        {"snippet": "No snippet is available for this page.", "snippet_status": "NO_SNIPPET_AVAILABLE"},
+                        {"snippet": "Colombia commits to a <b>51%</b> cut", "snippet_status": "SUCCESS"}
+                    ],
+                    "extractive_answers": [
+                        {"pageNumber": "3", "content": "The plan cuts emissions by 51% by 2030."},
+                        {"pageNumber": "7", "content": "Carbon credits are capped at 20%."}
+                    ]
+                }
+            }
+        }))
+        .unwrap();
+
+        let answers = result.extractive_answers();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[1].page_number, "7");
+        assert_eq!(
+            result.best_snippet(),
+            Some("Colombia commits to a <b>51%</b> cut")
+        );
+        let derived = result
+            .document
+            .as_ref()
+            .unwrap()
+            .derived_struct_data
+            .as_ref();
+        assert_eq!(
+            derived.unwrap().get("title").unwrap(),
+            "Climate Action Plan 2024"
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ModelPromptSpec {
-    pub preamble: String,
-}
+        let without_snippets: SearchResult = serde_json::from_value(serde_json::json!({
+            "document": {
+                "name": "documents/2",
+                "id": "2",
+                "derivedStructData": {
+                    "snippets": [{"snippet": "", "snippet_status": "NO_SNIPPET_AVAILABLE"}],
+                    "extractive_answers": [{"pageNumber": "1", "content": "Solar gets tax exemptions."}]
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            without_snippets.best_snippet(),
+            Some("Solar gets tax exemptions.")
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ModelSpec {
-    pub version: String,
-}
+        let chunk_result: SearchResult =
+            serde_json::from_value(serde_json::json!({"id": "c1"})).unwrap();
+        assert!(chunk_result.extractive_answers().is_empty());
+        assert_eq!(chunk_result.best_snippet(), None);
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ExtractiveContentSpec {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_extractive_answer_count: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_extractive_segment_count: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub return_extractive_segment_score: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub num_previus_segments: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub num_next_segments: Option<i32>,
-}
+    // EchoSearcher answers a raw search with the body it was sent, next to the serving config
+    #[derive(Default)]
+    struct EchoSearcher {
+        calls: Mutex<usize>,
+    }
+
+    impl RawSearcher for EchoSearcher {
+        async fn search_serving_config_raw(
+            &self,
+            serving_config: &str,
+            body: Value,
+        ) -> Result<Value, Error> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(serde_json::json!({"servingConfig": serving_config, "echo": body}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_raw_sends_the_body_verbatim() {
+        let client = EchoSearcher::default();
+        // fields the request structs do not model are sent as they are
+        let body = serde_json::json!({
+            "query": "carbon credits",
+            "pageSize": 5,
+            "relevanceThreshold": "HIGH",
+            "naturalLanguageQueryUnderstandingSpec": {"filterExtractionCondition": "ENABLED"},
+            "futureField": [1, {"nested": null}]
+        });
+
+        let response = search_raw(
+            &client,
+            &serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            body.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response["echo"], body);
+        assert_eq!(
+            response["servingConfig"],
+            "projects/p/locations/global/collections/default_collection/engines/e/servingConfigs/default_serving_config"
+        );
+
+        let result = search_raw(
+            &client,
+            &serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            serde_json::json!([1]),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::InvalidRequestBody(body)) if body == "[1]"));
+        assert_eq!(*client.calls.lock().unwrap(), 1);
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SnippetSpec {
-    pub max_snippet_count: i32,
-    pub reference_only: bool,
-    pub return_snippet: bool,
-}
+    #[test]
+    fn test_serving_config_is_threaded_into_the_url() {
+        let mut request = SearchRequest {
+            project_id: "moni-429523".to_string(),
+            serving_config: None,
+            discovery_engine_search_request: DiscoveryEngineSearchRequest::default(),
+        };
+        assert_eq!(
+            serving_config_url(&Location::Global, &request.serving_config_name("global", "policies").unwrap(), "search"),
+            "https://discoveryengine.googleapis.com/v1beta/projects/moni-429523/locations/global/collections/policies/engines/moni-demo-final_1722720080773/servingConfigs/default_serving_config:search"
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct SpellCorrectionSpec {
-    pub mode: Mode,
-}
+        request.serving_config = Some("policy_briefs".to_string());
+        assert_eq!(
+            serving_config_url(&Location::Global, &request.serving_config_name("global", "policies").unwrap(), "search"),
+            "https://discoveryengine.googleapis.com/v1beta/projects/moni-429523/locations/global/collections/policies/engines/moni-demo-final_1722720080773/servingConfigs/policy_briefs:search"
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum Mode {
-    ModeUnspecified,
-    SugestionOnly,
-    #[default]
-    Auto,
-}
+        let mut answer =
+            AnswerRequest::with_search_results("moni-429523", "carbon credits", vec![]);
+        answer.serving_config = Some("policy_briefs".to_string());
+        assert!(serving_config_url(
+            &Location::Global,
+            &answer.serving_config_name("global", "policies").unwrap(),
+            "answer"
+        )
+        .ends_with("/servingConfigs/policy_briefs:answer"));
+
+        request.serving_config = Some(" ".to_string());
+        assert!(matches!(
+            request.serving_config_name("global", "policies"),
+            Err(Error::MissingField("serving_config"))
+        ));
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct BoostSpec {
-    pub condition_boost_specs: Vec<ConditionBoostSpec>,
-}
+    #[test]
+    fn test_complete_query_response() {
+        let response: CompleteQueryResponse = serde_json::from_value(serde_json::json!({
+            "querySuggestions": [
+                {"suggestion": "climate adaptation"},
+                {"suggestion": "climate action plan 2024", "completableFieldPaths": ["title"]}
+            ],
+            "tailMatchTriggered": true
+        }))
+        .unwrap();
+
+        assert_eq!(
+            response.suggestions(),
+            vec!["climate adaptation", "climate action plan 2024"]
+        );
+        assert_eq!(
+            response.query_suggestions[1].completable_field_paths,
+            vec!["title"]
+        );
+        assert!(response.tail_match_triggered);
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ConditionBoostSpec {
-    pub condition: String,
-    pub boost: i32,
-}
+        // nothing to suggest comes back as an empty object
+        let empty: CompleteQueryResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.suggestions().is_empty());
+        assert!(!empty.tail_match_triggered);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ControlPoint {
-    pub attribute_value: String,
-    pub boost_amount: i32,
-}
+    #[test]
+    fn test_query_expansion_flag() {
+        let expanded: SearchResponse = serde_json::from_value(serde_json::json!({
+            "totalSize": 12,
+            "attributionToken": "token",
+            "queryExpansionInfo": {"expandedQuery": true, "pinnedResultCount": "2"}
+        }))
+        .unwrap();
+        assert!(expanded.was_query_expanded());
+
+        for response in [
+            serde_json::json!({"queryExpansionInfo": {"expandedQuery": false}}),
+            serde_json::json!({"queryExpansionInfo": {}}),
+            serde_json::json!({}),
+        ] {
+            let response: SearchResponse = serde_json::from_value(response.clone()).unwrap();
+            assert!(!response.was_query_expanded());
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum AttributeType {
-    #[default]
-    AttributeTypeUnspecified,
-    Numerical,
-    Freshness,
-}
+    #[test]
+    fn test_responses_tolerate_new_and_omitted_fields() {
+        // fields and enum values added to the API since, zero values left out as proto3 JSON does
+        let search: SearchResponse = serde_json::from_value(serde_json::json!({
+            "results": [{
+                "id": "doc-1",
+                "document": {
+                    "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/branches/0/documents/doc-1",
+                    "derivedStructData": {"snippets": [{"snippet": "Moni"}]},
+                    "rankSignals": {"semanticSimilarityScore": 0.8}
+                },
+                "modelScores": {"relevance": {"values": [0.9]}}
+            }],
+            "facets": [{"key": "year", "values": [{"value": "2024"}]}],
+            "summary": {
+                "summarySkippedReasons": ["CUSTOMER_POLICY_VIOLATION"],
+                "summaryWithMetadata": {
+                    "citationMetadata": {"citations": [{"endIndex": "12", "sources": [{}]}]},
+                    "references": [{"title": "Moni"}]
+                }
+            },
+            "searchLinkPromotions": [{"title": "Moni"}],
+            "semanticState": "ENABLED"
+        }))
+        .unwrap();
+        let results = search.results.as_ref().unwrap();
+        assert_eq!(results[0].document.as_ref().unwrap().id, "");
+        assert_eq!(results[0].relevance(), Some(0.9));
+        assert_eq!(search.facet("year").unwrap().values[0].count, "");
+        let summary = search.summary.as_ref().unwrap();
+        assert_eq!(
+            summary.summary_skipped_reasons,
+            Some(vec![SummarySkippedReason::SummarySkippedReasonUnspecified])
+        );
+        let citations = summary.summary_with_metadata.as_ref().unwrap();
+        let citation = &citations
+            .citation_metadata
+            .as_ref()
+            .unwrap()
+            .citations
+            .as_ref()
+            .unwrap()[0];
+        assert_eq!(citation.start_index, "");
+        assert_eq!(citation.sources.as_ref().unwrap()[0].reference_index, "");
+
+        let answer: FeedbackAnswerQueryResponse = serde_json::from_value(serde_json::json!({
+            "answer": {
+                "name": "projects/moni/locations/global/collections/default_collection/engines/moni/sessions/-/answers/1",
+                "state": "STREAMING",
+                "answerText": "Moni is a search demo.",
+                "steps": [{"state": "SUCCEEDED", "actions": [{"searchAction": {"query": "moni"}}]}],
+                "groundingScore": 0.7
+            },
+            "session": {"state": "CLOSED"}
+        }))
+        .unwrap();
+        assert_eq!(answer.answer.state, State::Unspecified);
+        assert!(answer.answer.citations.is_empty());
+        assert_eq!(answer.answer.steps[0].search_queries(), vec!["moni"]);
+        assert!(matches!(
+            answer.session.state,
+            SessionState::SateUnspecified
+        ));
+
+        let data_store: DataStore = serde_json::from_value(serde_json::json!({
+            "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo",
+            "solutionTypes": ["SOLUTION_TYPE_AGENT"],
+            "industryVertical": "HEALTHCARE_FHIR",
+            "servingConfigDataStore": {}
+        }))
+        .unwrap();
+        assert!(matches!(
+            data_store.industry_vertical,
+            IndustryVertical::Unspecified
+        ));
+        assert!(matches!(
+            data_store.solution_types[..],
+            [SolutionType::Unspecified]
+        ));
+        assert!(matches!(
+            data_store.content_config,
+            ContentConfig::Unspecified
+        ));
+        assert_eq!(data_store.display_name, "");
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum InterpolationType {
-    #[default]
-    InterpolationTypeUnspecified,
-    Linear,
-}
+    #[test]
+    fn test_content_search_spec_presets() {
+        let json = |spec: ContentSearchSpec| serde_json::to_value(spec).unwrap();
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct ImageQuery {
-    pub image_bytes: String,
-}
+        assert_eq!(
+            json(ContentSearchSpec::snippets_only()),
+            serde_json::json!({
+                "snippetSpec": {"maxSnippetCount": 1, "referenceOnly": false, "returnSnippet": true},
+                "searchResultMode": "DOCUMENTS"
+            })
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct DataStoreSpec {
-    pub data_store: String,
-}
+        assert_eq!(
+            json(ContentSearchSpec::chunks(1, 2)),
+            serde_json::json!({
+                "chunkSpec": {"numPreviousChunks": 1, "numNextChunks": 2},
+                "searchResultMode": "CHUNKS"
+            })
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct UserInfo {
-    pub user_id: String,
-    pub user_agent: String,
-}
+        let summarized = json(ContentSearchSpec::default().with_summary(5));
+        assert_eq!(summarized["summarySpec"]["summaryResultCount"], 5);
+        assert_eq!(summarized["summarySpec"]["includeCitations"], true);
+        assert_eq!(summarized["summarySpec"]["ignoreAdversarialQuery"], true);
+        for omitted in ["snippetSpec", "chunkSpec", "extractiveContentSpec"] {
+            assert!(summarized.get(omitted).is_none(), "{}", omitted);
+        }
+
+        assert_eq!(
+            json(ContentSearchSpec::snippets_only().extractive(3)),
+            serde_json::json!({
+                "snippetSpec": {"maxSnippetCount": 1, "referenceOnly": false, "returnSnippet": true},
+                "extractiveContentSpec": {"maxExtractiveSegmentCount": 3},
+                "searchResultMode": "DOCUMENTS"
+            })
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct FacetSpec {
-    pub facet_key: FacetKey,
-    pub limit: i32,
-    pub excluded_filter_keys: Vec<String>,
-    pub enable_dynamic_position: bool,
-}
+    fn snippet(status: &str, snippet: &str) -> Snippet {
+        Snippet {
+            snippet_status: status.to_string(),
+            snippet: snippet.to_string(),
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct FacetKey {
-    pub key: String,
-    pub interval: Vec<Interval>,
-    pub restricted_values: Vec<String>,
-    pub prefixes: Vec<String>,
-    pub contains: Vec<String>,
-    pub case_insensitve: bool,
-    pub order_by: String,
-}
+    #[test]
+    fn test_snippet_success() {
+        let snippet = snippet("SUCCESS", "Colombia commits to a <b>51%</b> cut");
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Interval {
-    pub minimum: i32,
-    pub exclusive_minimum: i32,
-    pub maximum: i32,
-    pub exclusive_maximum: i32,
-}
+        assert!(snippet.is_success());
+        assert_eq!(snippet.plain_text(), "Colombia commits to a 51% cut");
+        assert_eq!(snippet.html(), "Colombia commits to a <b>51%</b> cut");
+    }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct QueryExpansionSpec {
-    pub condition: Condition,
-    pub pin_unexpanded_results: bool,
-}
+    #[test]
+    fn test_snippet_markup_is_stripped_and_escaped() {
+        let snippet = snippet(
+            "SUCCESS",
+            "<b>Carbon</b> credits &amp; <i>forestry</i> &quot;offsets&quot; <script>alert(1)</script> a &lt; b &#39;&#x41;&bogus; &",
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum SearchAsYouTypeCondition {
-    ConditionUnspecified,
-    #[default]
-    Disabled,
-    Enabled,
-}
+        assert_eq!(
+            snippet.plain_text(),
+            "Carbon credits & forestry \"offsets\" alert(1) a < b 'A&bogus; &"
+        );
+        assert_eq!(
+            snippet.html(),
+            "<b>Carbon</b> credits &amp; forestry &quot;offsets&quot; alert(1) a &lt; b &#39;A&amp;bogus; &amp;"
+        );
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum Condition {
-    ConditionUnspecified,
-    Disabled,
-    #[default]
-    Auto,
-}
+        // highlighting left open is closed, a stray closing tag is dropped
+        assert_eq!(
+            self::snippet("SUCCESS", "</b>51% <B>cut").html(),
+            "51% <b>cut</b>"
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct SetupDataConnectorResponse {
-    pub name: String,
-    pub response: ResponseDataConnector,
-}
+    #[test]
+    fn test_no_snippet_status_renders_fallback_text() {
+        for snippet in [
+            snippet(
+                "NO_SNIPPET_AVAILABLE",
+                "No snippet is available for this page.",
+            ),
+            snippet("SUCCESS", "  "),
+        ] {
+            assert!(!snippet.is_success());
+            assert_eq!(snippet.plain_text(), NO_SNIPPET_TEXT);
+            assert_eq!(snippet.html(), NO_SNIPPET_TEXT);
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ResponseDataConnector {
-    #[serde(rename = "@type")]
-    pub type_url: String,
-    pub name: String,
-    pub state: String,
-    pub data_source: String,
-    pub params: Params,
-    pub refresh_interval: String,
-    pub entities: Vec<ResponseEntity>,
-}
+    #[test]
+    fn test_click_event_carries_the_search_attribution_token() {
+        let search: SearchResponse = serde_json::from_value(serde_json::json!({
+            "results": [{"id": "1"}],
+            "attributionToken": "CvgECgwI9ae0tQYQ_4DVkwESJDY2"
+        }))
+        .unwrap();
+
+        let click = UserEvent::new(UserEventType::ViewItem, "d1a7e8c0-4f0b")
+            .attributed_to(&search)
+            .with_document("1");
+
+        assert_eq!(
+            serde_json::to_value(&click).unwrap(),
+            serde_json::json!({
+                "eventType": "view-item",
+                "userPseudoId": "d1a7e8c0-4f0b",
+                "attributionToken": "CvgECgwI9ae0tQYQ_4DVkwESJDY2",
+                "documents": [{"id": "1"}]
+            })
+        );
+        let event_type: UserEventType = serde_json::from_str("\"media-complete\"").unwrap();
+        assert_eq!(event_type, UserEventType::MediaComplete);
+        assert!(serde_json::from_str::<UserEventType>("\"click\"").is_err());
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ResponseEntity {
-    pub entity_name: String,
-    pub data_store: String,
-    pub params: EntityParams,
-}
+    #[test]
+    fn test_search_response_attribution() {
+        let response: SearchResponse = serde_json::from_value(serde_json::json!({
+            "results": [{"id": "1"}],
+            "attributionToken": "CvgECgwI9ae0tQYQ_4DVkwESJDY2"
+        }))
+        .unwrap();
+        assert_eq!(response.attribution(), Some("CvgECgwI9ae0tQYQ_4DVkwESJDY2"));
+
+        let untracked: SearchResponse =
+            serde_json::from_value(serde_json::json!({"attributionToken": ""})).unwrap();
+        assert_eq!(untracked.attribution(), None);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct SetupDataConnectorRequest {
-    pub project_id: String,
-    pub collection_id: String,
-    pub collection_display_name: String,
-    pub data_connector: DataConnector,
-}
+    #[test]
+    fn test_result_maps_keep_a_stable_key_order() {
+        let body = r#"{
+            "modelScores": {"relevance": {"values": [0.8]}, "freshness": {"values": [0.1]}},
+            "chunk": {
+                "name": "c1",
+                "id": "c1",
+                "content": "Emissions are cut by 51% by 2030.",
+                "documentMetadata": {"structData": {"year": 2024, "country": "Colombia"}},
+                "derivedStructData": {"title": "Plan", "link": "gs://moni/plan.pdf", "author": "MinAmbiente"}
+            }
+        }"#;
+
+        let result: SearchResult = serde_json::from_str(body).unwrap();
+
+        let chunk = result.chunk.as_ref().unwrap();
+        let derived: Vec<&String> = chunk.derived_struct_data.keys().collect();
+        assert_eq!(derived, vec!["author", "link", "title"]);
+        let metadata = chunk.document_metadata.as_ref().unwrap();
+        let struct_data: Vec<&String> = metadata.struct_data.keys().collect();
+        assert_eq!(struct_data, vec!["country", "year"]);
+        let scores: Vec<&String> = result.model_scores.as_ref().unwrap().keys().collect();
+        assert_eq!(scores, vec!["freshness", "relevance"]);
+
+        let serialized = serde_json::to_string(&result).unwrap();
+        let reserialized =
+            serde_json::to_string(&serde_json::from_str::<SearchResult>(&serialized).unwrap())
+                .unwrap();
+        assert_eq!(serialized, reserialized);
+        assert!(serialized.find("\"freshness\"") < serialized.find("\"relevance\""));
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DataConnector {
-    pub data_source: String,
-    pub params: Params,
-    pub refresh_interval: String,
-    pub entities: Vec<Entity>,
-    pub sync_mode: String,
-}
+    // PagingClient serves three results over two pages and records the requested page tokens.
+    #[derive(Default)]
+    struct PagingClient {
+        page_tokens: Mutex<Vec<String>>,
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Params {
-    pub instance_uris: Vec<String>,
-}
+    impl AnswerOperations for PagingClient {
+        async fn search_serving_config(
+            &self,
+            _: &str,
+            request: &DiscoveryEngineSearchRequest,
+        ) -> Result<SearchResponse, Error> {
+            self.page_tokens
+                .lock()
+                .unwrap()
+                .push(request.page_token.clone());
+            let page = match request.page_token.as_str() {
+                "" => serde_json::json!({
+                    "results": [{"id": "pnacc"}, {"id": "bogota-mobility"}],
+                    "nextPageToken": "page-2"
+                }),
+                "page-2" => serde_json::json!({"results": [{"id": "cali-water"}]}),
+                token => panic!("unexpected page token {}", token),
+            };
+            Ok(serde_json::from_value(page).unwrap())
+        }
+
+        async fn answer_serving_config(
+            &self,
+            _: &str,
+            _: DiscoveryEngineAnswerRequest,
+        ) -> Result<FeedbackAnswerQueryResponse, Error> {
+            panic!("PagingClient only serves search pages")
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Entity {
-    pub entity_name: String,
-    pub params: EntityParams,
-}
+    #[tokio::test]
+    async fn test_search_stream_yields_every_page_in_order() {
+        let client = PagingClient::default();
+        let request = DiscoveryEngineSearchRequest {
+            query: "climate adaptation".to_string(),
+            page_size: 2,
+            ..Default::default()
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct EntityParams {
-    pub data_schema: String,
-    pub content_config: String,
-    pub industry_vertical: String,
-    pub auto_generate_ids: bool,
-}
+        let results: Vec<SearchResult> = search_stream(
+            &client,
+            serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            request,
+        )
+        .try_collect()
+        .await
+        .unwrap();
+
+        let ids: Vec<&str> = results.iter().filter_map(|r| r.id.as_deref()).collect();
+        assert_eq!(ids, vec!["pnacc", "bogota-mobility", "cali-water"]);
+        assert_eq!(*client.page_tokens.lock().unwrap(), vec!["", "page-2"]);
+    }
 
-pub struct ListChunksRequest {
-    pub project_id: String,
-    pub collections: String,
-    pub data_store_id: String,
-    pub branch: String,
-    pub documet_id: String,
-}
+    #[tokio::test]
+    async fn test_search_stream_rejects_oversized_page_size() {
+        let client = PagingClient::default();
+        let request = DiscoveryEngineSearchRequest {
+            query: "climate adaptation".to_string(),
+            page_size: MAX_SEARCH_PAGE_SIZE + 1,
+            ..Default::default()
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ListChunksResponse {
-    pub chunks: Vec<Chunk>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(rename = "nextPageToken")]
-    pub next_page_token: Option<String>,
-}
+        let result: Result<Vec<SearchResult>, Error> = search_stream(
+            &client,
+            serving_config("global", "p", DEFAULT_COLLECTION, "e"),
+            request,
+        )
+        .try_collect()
+        .await;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ChunkSpec {
-    pub num_previous_chunks: Option<i32>,
-    pub num_next_chunks: Option<i32>,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchChunksRequest {
-    pub project_id: String,
-    pub collections: String,
-    pub data_store_id: String,
-    pub serving_config: String,
-    pub query: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub page_size: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub page_token: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub offset: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub filter: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub order_by: Option<String>,
-    pub content_search_spec: ContentSearchSpec,
-}
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchChunksResponse {
-    pub chunks: Vec<Chunk>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub next_page_token: Option<String>,
-}
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Chunk {
-    pub name: String,
-    pub id: String,
-    pub content: String,
-    #[serde(rename = "documentMetadata")]
-    pub document_metadata: DocumentMetadata,
-    #[serde(rename = "deriveStructData")]
-    pub derive_struct_data: HashMap<String, Value>,
-    #[serde(rename = "pageSpan")]
-    pub page_span: PageSpan,
-    #[serde(rename = "chunkMetadata")]
-    pub chunk_metadata: ChunkMetadata,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(rename = "relevanceScore")]
-    relevance_score: Option<f32>,
-}
+        assert!(matches!(result, Err(Error::PageSizeTooLarge(101, 100))));
+        assert!(client.page_tokens.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_chunks_posts_the_page_size() {
+        let client = DataStoreClient::with_client(
+            crate::client::Client::new()
+                .await
+                .unwrap()
+                .with_fixture(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/testdata/fixtures/search_chunks.json"
+                ))
+                .unwrap(),
+        );
+        let request = |page_size| SearchChunksRequest {
+            project_id: "moni-429523".to_string(),
+            collections: String::new(),
+            data_store_id: "moni-demo_1722720098936".to_string(),
+            serving_config: String::new(),
+            query: "carbon credits".to_string(),
+            page_size: Some(page_size),
+            page_token: None,
+            offset: None,
+            filter: None,
+            order_by: None,
+            content_search_spec: ContentSearchSpec::chunks(0, 1),
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename = "camelCase")]
-pub struct DocumentMetadata {
-    pub uri: String,
-    pub title: String,
-    pub struct_data: HashMap<String, Value>,
-}
+        // the fixture only answers a POST whose body has the page size
+        let response = client.search_chunks(request(5)).await.unwrap();
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct PageSpan {
-    #[serde(rename = "pageStart")]
-    pub page_start: i32,
-    #[serde(rename = "pageEnd")]
-    pub page_end: i32,
-}
+        assert_eq!(response.chunks.len(), 1);
+        assert_eq!(response.next_page_token.as_deref(), Some("page-2"));
+        assert!(matches!(
+            client.search_chunks(request(101)).await,
+            Err(Error::PageSizeTooLarge(101, 100))
+        ));
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ChunkMetadata {
-    #[serde(rename = "previusChunks")]
-    pub previus_chunks: Vec<Chunk>,
-    #[serde(rename = "nextChunks")]
-    pub next_chunks: Vec<Chunk>,
-}
+    #[test]
+    fn test_check_page_size() {
+        assert!(check_page_size(MAX_SEARCH_PAGE_SIZE, MAX_SEARCH_PAGE_SIZE).is_ok());
+        assert!(check_page_size(1, MAX_SEARCH_CHUNKS_PAGE_SIZE).is_ok());
+        assert!(matches!(
+            check_page_size(1001, MAX_LIST_CHUNKS_PAGE_SIZE),
+            Err(Error::PageSizeTooLarge(1001, 1000))
+        ));
+    }
 
-pub struct GetDataStoreRequest {
-    pub collections: String,
-    pub project_id: String,
-    pub data_store_id: String,
-}
+    // FlippingClient reports the operation as done from the `done_after`-th poll on.
+    struct FlippingClient {
+        done_after: usize,
+        polls: Mutex<usize>,
+    }
 
-pub struct DeleteDataStoreRequest {
-    pub collections: String,
-    pub project_id: String,
-    pub data_store_id: String,
-}
+    impl OperationReader for FlippingClient {
+        async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error> {
+            let mut polls = self.polls.lock().unwrap();
+            *polls += 1;
+            Ok(operation(operation_name, *polls >= self.done_after))
+        }
+    }
 
-pub struct CreateDataStoreRequest {
-    pub data_store: DataStore,
-    pub project_id: String,
-    pub collections: String,
-    pub data_store_id: String,
-    pub create_advance_site_search: Option<bool>,
-}
+    fn test_poll_config() -> PollConfig {
+        PollConfig {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+            multiplier: 2.0,
+            deadline: Duration::from_millis(200),
+            max_attempts: None,
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GetOperationStatusRequest {
-    pub operation_name: String,
-    pub project_id: String,
-    pub collection: String,
-    pub data_store_id: String,
-    pub branch: String,
-}
-#[derive(Serialize, Deserialize, Debug)]
-pub struct PollOperationRequest {
-    pub operation_name: String,
-    pub project_id: String,
-    pub collection: String,
-    pub data_store_id: String,
-    pub branch: String,
-}
+    #[test]
+    fn test_poll_config_backoff_is_capped() {
+        let config = test_poll_config();
+        let mut delay = config.initial_delay;
+        let mut delays = vec![];
+        for _ in 0..5 {
+            delays.push(delay.as_millis());
+            delay = config.next_delay(delay);
+        }
+        assert_eq!(delays, vec![1, 2, 4, 4, 4]);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Operation {
-    pub name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<Metadata>,
-    pub done: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub response: Option<HashMap<String, String>>,
-}
+    #[tokio::test]
+    async fn test_poll_until_done_returns_finished_operation() {
+        let client = FlippingClient {
+            done_after: 4,
+            polls: Mutex::new(0),
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct OperationError {
-    pub code: i32,
-    pub message: String,
-    pub details: Vec<HashMap<String, serde_json::Value>>, // Adjust as needed
-}
+        let operation = poll_until_done(&client, "operations/import-1", &test_poll_config())
+            .await
+            .unwrap();
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Metadata {
-    #[serde(rename = "@type")]
-    pub at_type: String,
-    #[serde(flatten)]
-    pub additional: HashMap<String, Value>,
-}
+        assert!(operation.done);
+        assert_eq!(*client.polls.lock().unwrap(), 4);
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Response {
-    #[serde(rename = "@type")]
-    pub at_type: String,
-    #[serde(flatten)]
-    pub additional: HashMap<String, Value>,
-}
+    #[tokio::test]
+    async fn test_poll_until_done_times_out() {
+        let client = FlippingClient {
+            done_after: usize::MAX,
+            polls: Mutex::new(0),
+        };
+        let config = PollConfig {
+            deadline: Duration::from_millis(20),
+            ..test_poll_config()
+        };
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Status {
-    pub code: i32,
-    pub message: String,
-    pub details: Vec<Detail>,
-}
+        let result = poll_until_done(&client, "operations/import-1", &config).await;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Detail {
-    #[serde(rename = "@type")]
-    pub at_type: String,
-    #[serde(flatten)]
-    pub additional: HashMap<String, Value>,
-}
+        assert!(
+            matches!(result, Err(Error::OperationTimeout(name)) if name == "operations/import-1")
+        );
+        assert!(*client.polls.lock().unwrap() > 1);
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum OperationResult {
-    Error { error: Status },
-    Response { response: Response },
-}
+    #[tokio::test]
+    async fn test_fixed_poll_config_polls_at_most_the_given_attempts() {
+        let config = PollConfig::fixed(Duration::from_millis(1), 3);
+        let running = FlippingClient {
+            done_after: usize::MAX,
+            polls: Mutex::new(0),
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DataStore {
-    pub name: String,
-    pub display_name: String,
-    pub industry_vertical: IndustryVertical,
-    pub solution_types: Vec<SolutionType>,
-    pub default_schema_id: Option<String>,
-    pub content_config: ContentConfig,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub create_time: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub language_info: Option<LanguageInfo>,
-    pub document_processing_config: Option<DocumentProcessingConfig>,
-    pub starting_schema: Option<Schema>,
-}
+        let result = poll_until_done(&running, "operations/import-1", &config).await;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum IndustryVertical {
-    Unspecified,
-    Media,
-    SiteSearch,
-    Generic,
-}
+        assert!(matches!(result, Err(Error::OperationTimeout(_))));
+        assert_eq!(*running.polls.lock().unwrap(), 3);
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum SolutionType {
-    Unspecified,
-    Recommendation,
-    Search,
-}
+        // done on the last attempt
+        let finishing = FlippingClient {
+            done_after: 3,
+            polls: Mutex::new(0),
+        };
+        let operation = poll_until_done(&finishing, "operations/import-1", &config)
+            .await
+            .unwrap();
+        assert!(operation.done);
+        assert_eq!(*finishing.polls.lock().unwrap(), 3);
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum ContentConfig {
-    Unspecified,
-    NoContent,
-    ContentRequired,
-    PublicWebsite,
-}
+    #[tokio::test]
+    async fn test_refresh_from_gcs_waits_for_purge_before_import() {
+        let client = RecordingClient::default();
+        let request = RefreshRequest {
+            project_id: "project".to_string(),
+            collections: "default_collection".to_string(),
+            data_store_id: "data-store".to_string(),
+            branch: "default_branch".to_string(),
+            filter: None,
+            gcs_input_uris: vec!["gs://bucket/*.pdf".to_string()],
+            data_schema: None,
+            poll_config: Some(PollConfig::fixed(Duration::from_millis(1), 10)),
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct LanguageInfo {
-    pub language_code: String,
-    pub normalized_language_code: Option<String>,
-    pub language: Option<String>,
-    pub region: Option<String>,
-}
+        let operation = refresh_from_gcs(&client, request).await.unwrap();
+
+        assert_eq!(operation.name, "import-operation");
+        assert_eq!(
+            *client.calls.lock().unwrap(),
+            vec![
+                "purge *",
+                "get purge-operation",
+                "get purge-operation",
+                "import gs://bucket/*.pdf",
+            ]
+        );
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DocumentProcessingConfig {
-    pub name: String,
-    pub chunking_config: Option<ChunkingConfig>,
-    pub default_parsing_config: Option<ParsingConfig>,
-    pub parsing_config_overrides: Option<HashMap<String, ParsingConfig>>,
-}
+    // CreatingClient records every call, the creation operation is done on the second poll and
+    // fails with `error` when it is set. Getting the data store fails with an API error of code
+    // `get_error` when it is set.
+    #[derive(Default)]
+    struct CreatingClient {
+        calls: Mutex<Vec<String>>,
+        error: Option<&'static str>,
+        get_error: Option<i32>,
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ChunkingConfig {
-    pub layout_based_chunking_config: Option<LayoutBasedChunkingConfig>,
-}
+    impl OperationReader for CreatingClient {
+        async fn get_operation(&self, operation_name: &str) -> Result<Operation, Error> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(format!("get {}", operation_name));
+            let mut operation = operation(operation_name, calls.len() >= 3);
+            if operation.done {
+                operation.error = self.error.map(|message| Status {
+                    code: 9,
+                    message: message.to_string(),
+                    status: String::new(),
+                    details: Vec::new(),
+                });
+            }
+            Ok(operation)
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct LayoutBasedChunkingConfig {
-    pub chunk_size: Option<i32>,
-    pub include_ancestor_headings: Option<bool>,
-}
+    impl DataStoreOperations for CreatingClient {
+        async fn create_data_store(
+            &self,
+            request: CreateDataStoreRequest,
+        ) -> Result<Operation, Error> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("create {}", request.data_store_id));
+            Ok(operation("create-operation", false))
+        }
+
+        async fn get_data_store(&self, request: GetDataStoreRequest) -> Result<DataStore, Error> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("get data store {}", request.data_store_id));
+            if let Some(code) = self.get_error {
+                return Err(Error::ApiError(Status {
+                    code,
+                    message: "get data store failed".to_string(),
+                    status: String::new(),
+                    details: Vec::new(),
+                }));
+            }
+            Ok(serde_json::from_str(include_str!("../../testdata/data_store.json")).unwrap())
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ParsingConfig {
-    pub digital_parsing_config: Option<DigitalParsingConfig>,
-    pub ocr_parsing_config: Option<OcrParsingConfig>,
-    pub layout_parsing_config: Option<LayoutParsingConfig>,
-}
+    fn create_data_store_request() -> CreateDataStoreRequest {
+        CreateDataStoreRequest {
+            data_store: serde_json::from_str(include_str!("../../testdata/data_store.json"))
+                .unwrap(),
+            project_id: "project".to_string(),
+            collections: "default_collection".to_string(),
+            data_store_id: "moni-demo_1722720098936".to_string(),
+            create_advance_site_search: None,
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DigitalParsingConfig {}
+    #[tokio::test]
+    async fn test_create_data_store_and_wait() {
+        let client = CreatingClient::default();
+        let config = PollConfig::fixed(Duration::from_millis(1), 10);
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct OcrParsingConfig {
-    pub enhanced_document_elements: Option<Vec<String>>,
-    pub use_native_text: Option<bool>,
-}
+        let data_store = create_data_store_and_wait(&client, create_data_store_request(), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(data_store.display_name, "moni-demo");
+        assert_eq!(
+            *client.calls.lock().unwrap(),
+            vec![
+                "create moni-demo_1722720098936",
+                "get create-operation",
+                "get create-operation",
+                "get data store moni-demo_1722720098936",
+            ]
+        );
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct LayoutParsingConfig {}
+        let failing = CreatingClient {
+            error: Some("data store moni-demo_1722720098936 already exists"),
+            ..Default::default()
+        };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Schema {}
+        let result =
+            create_data_store_and_wait(&failing, create_data_store_request(), &config).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::OperationFailed(name, message))
+                if name == "create-operation" && message.contains("already exists")
+        ));
+        assert!(!failing
+            .calls
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|call| call.starts_with("get data store")));
+    }
+
+    fn get_data_store_request() -> GetDataStoreRequest {
+        GetDataStoreRequest {
+            collections: "default_collection".to_string(),
+            project_id: "project".to_string(),
+            data_store_id: "moni-demo_1722720098936".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_store_exists() {
+        let existing = CreatingClient::default();
+        assert!(data_store_exists(&existing, get_data_store_request())
+            .await
+            .unwrap());
+
+        let absent = CreatingClient {
+            get_error: Some(404),
+            ..Default::default()
+        };
+        assert!(!data_store_exists(&absent, get_data_store_request())
+            .await
+            .unwrap());
+
+        let forbidden = CreatingClient {
+            get_error: Some(403),
+            ..Default::default()
+        };
+        assert!(matches!(
+            data_store_exists(&forbidden, get_data_store_request()).await,
+            Err(Error::ApiError(status)) if status.code == 403
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_data_store_if_absent() {
+        let existing = CreatingClient::default();
+        let operation = create_data_store_if_absent(&existing, create_data_store_request())
+            .await
+            .unwrap();
+        assert!(operation.is_none());
+        assert_eq!(
+            *existing.calls.lock().unwrap(),
+            vec!["get data store moni-demo_1722720098936"]
+        );
+
+        let absent = CreatingClient {
+            get_error: Some(404),
+            ..Default::default()
+        };
+        let operation = create_data_store_if_absent(&absent, create_data_store_request())
+            .await
+            .unwrap();
+        assert_eq!(operation.unwrap().name, "create-operation");
+        assert_eq!(
+            *absent.calls.lock().unwrap(),
+            vec![
+                "get data store moni-demo_1722720098936",
+                "create moni-demo_1722720098936",
+            ]
+        );
+
+        let failing = CreatingClient {
+            get_error: Some(500),
+            ..Default::default()
+        };
+        assert!(matches!(
+            create_data_store_if_absent(&failing, create_data_store_request()).await,
+            Err(Error::ApiError(status)) if status.code == 500
+        ));
+        assert_eq!(failing.calls.lock().unwrap().len(), 1);
+    }
+
+    // PagedOperations serves the operations of a data store in two pages and records the page
+    // tokens it was asked for
+    #[derive(Default)]
+    struct PagedOperations {
+        page_tokens: Mutex<Vec<Option<String>>>,
+    }
+
+    impl OperationLister for PagedOperations {
+        async fn list_operations_page(
+            &self,
+            parent: &str,
+            page_token: Option<&str>,
+        ) -> Result<ListOperationsResponse, Error> {
+            assert!(parent.ends_with("/dataStores/moni-demo"));
+            self.page_tokens
+                .lock()
+                .unwrap()
+                .push(page_token.map(String::from));
+            let page = match page_token {
+                None => {
+                    r#"{
+                        "operations": [
+                            {
+                                "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/operations/import-documents-1",
+                                "metadata": {
+                                    "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.ImportDocumentsMetadata",
+                                    "createTime": "2024-08-03T20:42:12Z",
+                                    "successCount": "12"
+                                },
+                                "done": true,
+                                "response": {
+                                    "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.ImportDocumentsResponse"
+                                }
+                            },
+                            {
+                                "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/operations/purge-documents-2",
+                                "metadata": {
+                                    "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.PurgeDocumentsMetadata"
+                                }
+                            }
+                        ],
+                        "nextPageToken": "page-2"
+                    }"#
+                }
+                Some("page-2") => {
+                    r#"{
+                        "operations": [
+                            {
+                                "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/operations/import-documents-3",
+                                "metadata": {
+                                    "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.ImportDocumentsMetadata"
+                                }
+                            }
+                        ]
+                    }"#
+                }
+                Some(token) => panic!("unexpected page token {}", token),
+            };
+            Ok(serde_json::from_str(page).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_operations() {
+        let parent =
+            "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo";
+        let client = PagedOperations::default();
+
+        let operations = list_operations(&client, parent, None).await.unwrap();
+
+        let states: Vec<(&str, bool)> = operations
+            .iter()
+            .map(|operation| (operation.id(), operation.done))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("import-documents-1", true),
+                ("purge-documents-2", false),
+                ("import-documents-3", false),
+            ]
+        );
+        let metadata = operations[0].metadata.as_ref().unwrap();
+        assert!(metadata.at_type.ends_with("ImportDocumentsMetadata"));
+        assert_eq!(metadata.additional["successCount"], "12");
+        assert_eq!(
+            *client.page_tokens.lock().unwrap(),
+            vec![None, Some("page-2".to_string())]
+        );
+
+        let imports = list_operations(&client, parent, Some("import-documents"))
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = imports.iter().map(Operation::id).collect();
+        assert_eq!(ids, vec!["import-documents-1", "import-documents-3"]);
+    }
+
+    #[test]
+    fn test_typed_import_documents_metadata() {
+        let operation: Operation = serde_json::from_str(
+            r#"{
+                "name": "projects/moni/locations/global/collections/default_collection/dataStores/moni-demo/branches/0/operations/import-documents-1",
+                "metadata": {
+                    "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.ImportDocumentsMetadata",
+                    "createTime": "2024-08-03T20:42:12.123Z",
+                    "updateTime": "2024-08-03T20:43:01.456Z",
+                    "successCount": "12",
+                    "failureCount": "3",
+                    "totalCount": "40"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let Some(OperationMetadata::ImportDocuments(progress)) = operation.typed_metadata() else {
+            panic!("unexpected metadata {:?}", operation.typed_metadata());
+        };
+        assert_eq!(
+            progress,
+            ImportDocumentsMetadata {
+                create_time: Some("2024-08-03T20:42:12.123Z".to_string()),
+                update_time: Some("2024-08-03T20:43:01.456Z".to_string()),
+                success_count: 12,
+                failure_count: 3,
+                total_count: 40,
+            }
+        );
+        assert_eq!(progress.processed_count(), 15);
+
+        // the counts are left out until documents are processed
+        let started: Metadata = serde_json::from_value(serde_json::json!({
+            "@type": "type.googleapis.com/google.cloud.discoveryengine.v1beta.ImportDocumentsMetadata",
+            "createTime": "2024-08-03T20:42:12.123Z"
+        }))
+        .unwrap();
+        assert!(matches!(
+            started.typed(),
+            OperationMetadata::ImportDocuments(ImportDocumentsMetadata {
+                success_count: 0,
+                total_count: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_typed_metadata_falls_back_to_the_raw_map() {
+        let create: Metadata = serde_json::from_value(serde_json::json!({
+            "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.CreateDataStoreMetadata",
+            "createTime": "2024-08-03T20:42:12Z"
+        }))
+        .unwrap();
+        assert_eq!(
+            create.typed(),
+            OperationMetadata::CreateDataStore(CreateDataStoreMetadata {
+                create_time: Some("2024-08-03T20:42:12Z".to_string()),
+                update_time: None,
+            })
+        );
+
+        let purge: Metadata = serde_json::from_value(serde_json::json!({
+            "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.PurgeDocumentsMetadata",
+            "successCount": "5"
+        }))
+        .unwrap();
+        assert_eq!(purge.typed(), OperationMetadata::Other(purge.clone()));
+
+        // a count that isn't an int64 keeps the raw map instead of failing
+        let malformed: Metadata = serde_json::from_value(serde_json::json!({
+            "@type": "type.googleapis.com/google.cloud.discoveryengine.v1.ImportDocumentsMetadata",
+            "successCount": "twelve"
+        }))
+        .unwrap();
+        assert_eq!(
+            malformed.typed(),
+            OperationMetadata::Other(malformed.clone())
+        );
+
+        let pending: Operation = serde_json::from_str(r#"{"name": "operations/1"}"#).unwrap();
+        assert_eq!(pending.typed_metadata(), None);
+    }
+}
 
 // Test
 #[cfg(test)]
@@ -1593,7 +7821,7 @@ mod tests_integrations {
         // let operation_finished = client.poll_operation(operation_request, None, None).await;
         // assert!(operation_finished);
         // Now lets delete it
-        thread::sleep(::from_secs(5));
+        thread::sleep(Duration::from_secs(5));
         let delete_request = DeleteDataStoreRequest {
             project_id: project_id.to_string(),
             collections: collections.to_string(),
@@ -1606,6 +7834,8 @@ mod tests_integrations {
         println!("{:?}", delete_operation.unwrap());
     }
 
+    // replays testdata/fixtures/search_document.json, record it again against the API with
+    // VERTEX_AI_RECORD=1
     #[tokio::test]
     async fn test_search_document() {
         env::set_var(
@@ -1618,6 +7848,7 @@ mod tests_integrations {
 
         let request = SearchRequest {
             project_id: project_id.to_string(),
+            serving_config: None,
             discovery_engine_search_request: DiscoveryEngineSearchRequest {
                 session: "projects/875055333740/locations/global/collections/default_collection/engines/moni-demo-final_1722720080773/sessions/-".to_string(),
                 query: "Can you show all document that a relevant for Colombian Climate adaptation"
@@ -1629,19 +7860,7 @@ mod tests_integrations {
                     ..Default::default()
                 },
                 spell_correction_spec: SpellCorrectionSpec { mode: Mode::Auto },
-                content_search_spec: ContentSearchSpec {
-                    extractive_content_spec: Some(ExtractiveContentSpec {
-                        max_extractive_segment_count: Some(1),
-                        ..Default::default()
-                    }),
-                    snippet_spec: Some(SnippetSpec {
-                        max_snippet_count: 1,
-                        return_snippet: true,
-                        ..Default::default()
-                    }),
-                    chunk_spec: None,
-                    ..Default::default()
-                },
+                content_search_spec: ContentSearchSpec::snippets_only().extractive(1),
                 session_spec: SessionSpec {
                     search_result_persistence_count: 5,
                     ..Default::default()
@@ -1650,11 +7869,20 @@ mod tests_integrations {
             },
         };
 
-        let client = DataStoreClient::new().await.unwrap();
+        let client = client::Client::new()
+            .await
+            .unwrap()
+            .with_fixture(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/testdata/fixtures/search_document.json"
+            ))
+            .unwrap();
+        let client = DataStoreClient::with_client(client);
         let response = client.search(request).await;
         println!("{:?}", response);
         assert!(response.is_ok());
         let search_response = response.unwrap();
+        assert!(!search_response.results.unwrap_or_default().is_empty());
     }
 
     // Test create_data_store with a storage bucket.
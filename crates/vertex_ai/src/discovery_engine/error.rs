@@ -1,11 +1,27 @@
+use crate::discovery_engine::client::Status;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("client error")]
-    ClientError(crate::client::error::Error),
+    ClientError(#[from] crate::client::error::Error),
 
     #[error("HTTP status error")]
     HttpStatus(reqwest::Error),
 
+    #[error("API error {}: {}", .0.code, .0.message)]
+    ApiError(Status),
+
+    #[error("quota exceeded, retry after {retry_after:?}")]
+    QuotaExceeded {
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("unable to parse response: {source}, body: {body}")]
+    InvalidResponse {
+        source: serde_json::Error,
+        body: String,
+    },
+
     #[error("some random datastore error")]
     DataStoreError,
 
@@ -14,4 +30,55 @@ pub enum Error {
 
     #[error("Text response error")]
     ResponseTextRetrieval(reqwest::Error),
+
+    #[error("operation {0} failed: {1}")]
+    OperationFailed(String, String),
+
+    #[error("operation {0} did not finish in time")]
+    OperationTimeout(String),
+
+    #[error("operation {0} cannot be cancelled: {1}")]
+    OperationNotCancellable(String, String),
+
+    #[error("search response has no session info")]
+    MissingSessionInfo,
+
+    #[error("invalid base64 content")]
+    ContentDecoding(base64::DecodeError),
+
+    #[error("{0} uris exceed the limit of {1} per recrawl")]
+    TooManyUris(usize, usize),
+
+    #[error("invalid data store id {0:?}: {1}")]
+    InvalidDataStoreId(String, String),
+
+    #[error("{0} is required")]
+    MissingField(&'static str),
+
+    #[error("more than one parser configured for {0} documents")]
+    ConflictingParsingConfig(String),
+
+    #[error("chunk size {0} is out of the allowed range of 100 to 500 tokens")]
+    InvalidChunkSize(i32),
+
+    #[error("page size {0} exceeds the maximum of {1}")]
+    PageSizeTooLarge(i64, i64),
+
+    #[error("{0:?} is not an orderable field")]
+    UnknownOrderByField(String),
+
+    #[error("refresh interval {0:?} is neither 0 nor between 30 minutes and 7 days")]
+    InvalidRefreshInterval(std::time::Duration),
+
+    #[error("invalid sync mode {0:?}, expected one of PERIODIC or STREAMING")]
+    InvalidSyncMode(String),
+
+    #[error("invalid data store schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("document does not match the data store schema: {}", .0.join("; "))]
+    SchemaViolation(Vec<String>),
+
+    #[error("raw request body must be a JSON object, got {0}")]
+    InvalidRequestBody(String),
 }
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::{client, discovery_engine, generative};
+
 #[derive(Debug, Error)]
 pub enum VertexError {
     #[error("provider error")]
@@ -11,9 +13,82 @@ pub enum VertexError {
     #[error("url parsing error reason: {0}")]
     UrlParseError(String),
 
+    #[error("invalid value for header {0}")]
+    InvalidHeader(String),
+
     #[error("HTTP status error: {0}")]
     HttpStatus(String),
 
     #[error("JSON parsing error")]
     ResponseJsonParsing(#[from] reqwest::Error),
+
+    #[error("circuit open, calls resume in {0:?}")]
+    CircuitOpen(std::time::Duration),
+
+    #[error("fixture error: {0}")]
+    Fixture(String),
+
+    #[error("request timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    #[error("discovery engine error")]
+    DiscoveryEngineError(#[from] discovery_engine::error::Error),
+
+    #[error("generative model error")]
+    GenerativeError(#[from] generative::error::Error),
+}
+
+// The client error has the same variants, so it is flattened instead of wrapped.
+impl From<client::error::Error> for VertexError {
+    fn from(error: client::error::Error) -> Self {
+        match error {
+            client::error::Error::ProviderError(e) => VertexError::ProviderError(e),
+            client::error::Error::ClientError(e) => VertexError::ClientError(e),
+            client::error::Error::UrlParseError(e) => VertexError::UrlParseError(e),
+            client::error::Error::InvalidHeader(e) => VertexError::InvalidHeader(e),
+            client::error::Error::HttpStatus(e) => VertexError::HttpStatus(e),
+            client::error::Error::ResponseJsonParsing(e) => VertexError::ResponseJsonParsing(e),
+            client::error::Error::CircuitOpen(e) => VertexError::CircuitOpen(e),
+            client::error::Error::Fixture(e) => VertexError::Fixture(e),
+            client::error::Error::Timeout(e) => VertexError::Timeout(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_call() -> Result<(), client::error::Error> {
+        Err(client::error::Error::UrlParseError(
+            "relative URL without a base".to_string(),
+        ))
+    }
+
+    fn data_store_call() -> Result<(), discovery_engine::error::Error> {
+        client_call()?;
+        Ok(())
+    }
+
+    fn vertex_call() -> Result<(), VertexError> {
+        data_store_call()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_error_propagates_through_data_store_call() {
+        match vertex_call() {
+            Err(VertexError::DiscoveryEngineError(
+                discovery_engine::error::Error::ClientError(client::error::Error::UrlParseError(
+                    reason,
+                )),
+            )) => assert_eq!(reason, "relative URL without a base"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match client_call().map_err(VertexError::from) {
+            Err(VertexError::UrlParseError(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,441 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::Client;
+use crate::discovery_engine::client::Status;
+use crate::generative::error::Error;
+
+/// Model of a `GenerativeClient` unless it was built `with_model`.
+pub const DEFAULT_MODEL: &str = "gemini-1.5-flash-002";
+
+/// Location of a `GenerativeClient` unless it was built `with_location`.
+pub const DEFAULT_LOCATION: &str = "us-central1";
+
+/// Generates text with the Gemini models of Vertex AI. Unlike the answer API of the Discovery
+/// Engine, which answers a query from the search results of a data store, the model writes from
+/// the text it is given.
+// GenerativeClient is cheap to clone, clones share the http connection pool and token provider
+#[derive(Clone)]
+pub struct GenerativeClient {
+    client: Client,
+    location: String,
+    model: String,
+    // sent with every generate_text request, the defaults of the model apply when empty
+    safety_settings: Vec<SafetySetting>,
+}
+
+impl GenerativeClient {
+    pub async fn new() -> Result<Self, Error> {
+        let client = Client::new().await?;
+        Ok(Self::with_client(client))
+    }
+
+    // with_client builds a GenerativeClient on top of a configured Client, e.g. the one of a
+    // DataStoreClient so both share their tokens
+    pub fn with_client(client: Client) -> Self {
+        Self {
+            client,
+            location: DEFAULT_LOCATION.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            safety_settings: Vec::new(),
+        }
+    }
+
+    // with_location sends the requests to the model served in `location`, e.g. europe-west1
+    pub fn with_location(mut self, location: impl Into<String>) -> Self {
+        self.location = location.into();
+        self
+    }
+
+    // with_model generates with `model`, e.g. gemini-1.5-pro-002
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    // with_safety_settings blocks the generate_text prompts and answers rated harmful as
+    // configured in `safety_settings` instead of with the default thresholds of the model
+    pub fn with_safety_settings(mut self, safety_settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = safety_settings;
+        self
+    }
+
+    /// # With Timeout
+    /// Fails the generations that take longer than `timeout` with a client `Error::Timeout`,
+    /// see `Client::with_timeout`. Long answers of large models can take tens of seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    /// # Generate Content
+    /// Sends `request` to the `generateContent` method of the model of the client, billed to
+    /// `project_id`.
+    ///
+    /// # Returns
+    /// Returns the `GenerateContentResponse`, or an `Error` in case of an error.
+    pub async fn generate_content(
+        &self,
+        project_id: &str,
+        request: &GenerateContentRequest,
+    ) -> Result<GenerateContentResponse, Error> {
+        let url = model_url(&self.location, project_id, &self.model, "generateContent");
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, request)
+            .await?;
+        parse_json(response).await
+    }
+
+    /// # Generate Text
+    /// Writes text following `instructions` from `material`, the instructions are the system
+    /// instruction of the model and the material the message of the user.
+    ///
+    /// # Returns
+    /// Returns the text of the first candidate, `Error::NoText` when the prompt or the
+    /// candidate was blocked, or an `Error` in case of an error.
+    pub async fn generate_text(
+        &self,
+        project_id: &str,
+        instructions: &str,
+        material: &str,
+    ) -> Result<String, Error> {
+        let request = GenerateContentRequest::new(material)
+            .with_system_instruction(instructions)
+            .with_safety_settings(self.safety_settings.clone());
+        self.generate_content(project_id, &request).await?.text()
+    }
+}
+
+// model_url is the url of `method` of a Google model, e.g. generateContent or predict
+pub(crate) fn model_url(location: &str, project_id: &str, model: &str, method: &str) -> String {
+    let endpoint = match location {
+        "global" => "https://aiplatform.googleapis.com".to_string(),
+        location => format!("https://{}-aiplatform.googleapis.com", location),
+    };
+    format!(
+        "{}/v1/projects/{}/locations/{}/publishers/google/models/{}:{}",
+        endpoint, project_id, location, model, method
+    )
+}
+
+// ErrorResponse is the body Google APIs send back with a non-2xx status
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: Status,
+}
+
+// parse_json deserializes a successful response into `T`. A failed request becomes
+// Error::ApiError with the status sent by the API, or with the raw body when it is not a Google
+// error.
+pub(crate) async fn parse_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, Error> {
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(Error::ResponseTextRetrieval)?;
+    if !status.is_success() {
+        let error = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(response) => response.error,
+            Err(_) => Status {
+                code: i32::from(status.as_u16()),
+                message: body,
+                status: String::new(),
+                details: Vec::new(),
+            },
+        };
+        return Err(Error::ApiError(error));
+    }
+    serde_json::from_str(&body).map_err(|source| Error::InvalidResponse { source, body })
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    pub contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_settings: Vec<SafetySetting>,
+}
+
+impl GenerateContentRequest {
+    /// A request with `text` as the only message of the user.
+    pub fn new(text: &str) -> Self {
+        Self {
+            contents: vec![Content::user(text)],
+            ..Default::default()
+        }
+    }
+
+    pub fn with_system_instruction(mut self, instruction: &str) -> Self {
+        self.system_instruction = Some(Content {
+            role: None,
+            parts: vec![Part::text(instruction)],
+        });
+        self
+    }
+
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.generation_config = Some(config);
+        self
+    }
+
+    pub fn with_safety_settings(mut self, safety_settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = safety_settings;
+        self
+    }
+}
+
+// SafetySetting blocks the prompts and candidates whose probability of being harmful in
+// `category` reaches `threshold`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SafetySetting {
+    pub category: HarmCategory,
+    pub threshold: HarmBlockThreshold,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum HarmCategory {
+    #[serde(rename = "HARM_CATEGORY_HARASSMENT")]
+    Harassment,
+    #[serde(rename = "HARM_CATEGORY_HATE_SPEECH")]
+    HateSpeech,
+    #[serde(rename = "HARM_CATEGORY_SEXUALLY_EXPLICIT")]
+    SexuallyExplicit,
+    #[serde(rename = "HARM_CATEGORY_DANGEROUS_CONTENT")]
+    DangerousContent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum HarmBlockThreshold {
+    BlockNone,
+    BlockLowAndAbove,
+    BlockMediumAndAbove,
+    BlockOnlyHigh,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Content {
+    // user or model, the system instruction has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+impl Content {
+    pub fn user(text: &str) -> Self {
+        Self {
+            role: Some("user".to_string()),
+            parts: vec![Part::text(text)],
+        }
+    }
+}
+
+// Part is a piece of a message, only text parts are modeled
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Part {
+    #[serde(default)]
+    pub text: String,
+}
+
+impl Part {
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    pub prompt_feedback: Option<PromptFeedback>,
+}
+
+impl GenerateContentResponse {
+    /// The text of the first candidate, `Error::NoText` with the reason when there is none,
+    /// e.g. because the prompt was blocked by the safety filters.
+    pub fn text(&self) -> Result<String, Error> {
+        if let Some(reason) = self
+            .prompt_feedback
+            .as_ref()
+            .and_then(|feedback| feedback.block_reason.as_deref())
+        {
+            return Err(Error::NoText(format!("prompt blocked: {}", reason)));
+        }
+        let Some(candidate) = self.candidates.first() else {
+            return Err(Error::NoText("no candidates".to_string()));
+        };
+        let text: String = candidate
+            .content
+            .iter()
+            .flat_map(|content| &content.parts)
+            .map(|part| part.text.as_str())
+            .collect();
+        if text.is_empty() {
+            let reason = candidate.finish_reason.as_deref().unwrap_or("none");
+            return Err(Error::NoText(format!("finish reason {}", reason)));
+        }
+        Ok(text)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    pub content: Option<Content>,
+    // e.g. STOP, MAX_TOKENS or SAFETY
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    pub block_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_content_request() {
+        let request = GenerateContentRequest::new("- Colombia cuts emissions by 51% by 2030")
+            .with_system_instruction("Write a one sentence report.")
+            .with_generation_config(GenerationConfig {
+                temperature: Some(0.5),
+                ..Default::default()
+            });
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "contents": [
+                    {"role": "user", "parts": [{"text": "- Colombia cuts emissions by 51% by 2030"}]}
+                ],
+                "systemInstruction": {"parts": [{"text": "Write a one sentence report."}]},
+                "generationConfig": {"temperature": 0.5}
+            })
+        );
+        assert_eq!(
+            model_url("europe-west1", "moni-429523", DEFAULT_MODEL, "generateContent"),
+            "https://europe-west1-aiplatform.googleapis.com/v1/projects/moni-429523/locations/europe-west1/publishers/google/models/gemini-1.5-flash-002:generateContent"
+        );
+        assert_eq!(
+            model_url("global", "moni-429523", "gemini-2.0-flash", "generateContent"),
+            "https://aiplatform.googleapis.com/v1/projects/moni-429523/locations/global/publishers/google/models/gemini-2.0-flash:generateContent"
+        );
+    }
+
+    #[test]
+    fn test_generate_content_request_with_safety_settings() {
+        let request = GenerateContentRequest::new("- Colombia cuts emissions by 51% by 2030")
+            .with_safety_settings(vec![
+                SafetySetting {
+                    category: HarmCategory::Harassment,
+                    threshold: HarmBlockThreshold::BlockOnlyHigh,
+                },
+                SafetySetting {
+                    category: HarmCategory::DangerousContent,
+                    threshold: HarmBlockThreshold::BlockLowAndAbove,
+                },
+            ]);
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["safetySettings"],
+            serde_json::json!([
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_ONLY_HIGH"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_LOW_AND_ABOVE"}
+            ])
+        );
+        // without safety settings the defaults of the model apply
+        let json =
+            serde_json::to_value(GenerateContentRequest::new("- Solar tax exemptions")).unwrap();
+        assert!(json.get("safetySettings").is_none());
+    }
+
+    #[test]
+    fn test_generated_text() {
+        let response: GenerateContentResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Colombia "}, {"text": "commits to a 51% cut."}]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 20, "candidatesTokenCount": 9}
+        }))
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "Colombia commits to a 51% cut.");
+
+        let blocked: GenerateContentResponse = serde_json::from_value(
+            serde_json::json!({"promptFeedback": {"blockReason": "SAFETY"}}),
+        )
+        .unwrap();
+        assert!(
+            matches!(blocked.text(), Err(Error::NoText(reason)) if reason == "prompt blocked: SAFETY")
+        );
+
+        let stopped: GenerateContentResponse =
+            serde_json::from_value(serde_json::json!({"candidates": [{"finishReason": "SAFETY"}]}))
+                .unwrap();
+        assert!(
+            matches!(stopped.text(), Err(Error::NoText(reason)) if reason == "finish reason SAFETY")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_text_sends_instructions_and_material() {
+        let client = GenerativeClient::with_client(
+            Client::new()
+                .await
+                .unwrap()
+                .with_fixture(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/testdata/fixtures/generate_content.json"
+                ))
+                .unwrap(),
+        );
+
+        let text = client
+            .generate_text(
+                "moni-429523",
+                "Write a one sentence report.",
+                "- Colombia cuts emissions by 51% by 2030",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            text,
+            "Colombia commits to cutting its emissions by 51% by 2030."
+        );
+
+        // the fixture answers a second request with a quota error
+        let error = client
+            .generate_text(
+                "moni-429523",
+                "Write a one sentence report.",
+                "- Solar tax exemptions",
+            )
+            .await;
+        assert!(matches!(error, Err(Error::ApiError(status)) if status.code == 429));
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use crate::client::Client;
+use crate::generative::client::{model_url, parse_json, DEFAULT_LOCATION};
+use crate::generative::error::Error;
+
+/// Embedding model of an `EmbeddingClient` unless it was built `with_model`, its vectors have
+/// 768 dimensions.
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-005";
+
+/// Embeds text with the text embedding models of Vertex AI.
+#[derive(Clone)]
+pub struct EmbeddingClient {
+    client: Client,
+    location: String,
+    model: String,
+}
+
+impl EmbeddingClient {
+    pub async fn new() -> Result<Self, Error> {
+        let client = Client::new().await?;
+        Ok(Self::with_client(client))
+    }
+
+    // with_client builds an EmbeddingClient on top of a configured Client, e.g. the one of a
+    // GenerativeClient so both share their tokens
+    pub fn with_client(client: Client) -> Self {
+        Self {
+            client,
+            location: DEFAULT_LOCATION.to_string(),
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+        }
+    }
+
+    // with_location sends the requests to the model served in `location`, e.g. europe-west1
+    pub fn with_location(mut self, location: impl Into<String>) -> Self {
+        self.location = location.into();
+        self
+    }
+
+    // with_model embeds with `model`, e.g. text-multilingual-embedding-002
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// # Embed Document
+    /// Embeds `text` as a document to be retrieved, with the `predict` method of the model of
+    /// the client, billed to `project_id`.
+    ///
+    /// # Returns
+    /// Returns the vector of `text`, `Error::NoEmbedding` when the response has none, or an
+    /// `Error` in case of an error.
+    pub async fn embed_document(&self, project_id: &str, text: &str) -> Result<Vec<f32>, Error> {
+        let url = model_url(&self.location, project_id, &self.model, "predict");
+        let request = PredictEmbeddingsRequest {
+            instances: vec![EmbeddingInstance {
+                content: text.to_string(),
+                task_type: Some(TaskType::RetrievalDocument),
+            }],
+        };
+        let response = self
+            .client
+            .api_post(&self.client.scopes(), &url, &request)
+            .await?;
+        let response: PredictEmbeddingsResponse = parse_json(response).await?;
+        response
+            .predictions
+            .into_iter()
+            .next()
+            .map(|prediction| prediction.embeddings.values)
+            .ok_or(Error::NoEmbedding)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PredictEmbeddingsRequest {
+    pub instances: Vec<EmbeddingInstance>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EmbeddingInstance {
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_type: Option<TaskType>,
+}
+
+// TaskType tells the model what the vector is used for, documents and the queries retrieving
+// them are embedded differently
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaskType {
+    RetrievalDocument,
+    RetrievalQuery,
+    SemanticSimilarity,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PredictEmbeddingsResponse {
+    #[serde(default)]
+    pub predictions: Vec<EmbeddingPrediction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EmbeddingPrediction {
+    pub embeddings: Embedding,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Embedding {
+    #[serde(default)]
+    pub values: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed_document() {
+        let client = EmbeddingClient::with_client(
+            Client::new()
+                .await
+                .unwrap()
+                .with_fixture(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/testdata/fixtures/embed_document.json"
+                ))
+                .unwrap(),
+        );
+
+        let vector = client
+            .embed_document("moni-429523", "Colombia cuts emissions by 51% by 2030")
+            .await
+            .unwrap();
+        assert_eq!(vector, vec![0.25, -0.5, 0.125]);
+
+        // the fixture answers a second request without predictions
+        let error = client
+            .embed_document("moni-429523", "Solar tax exemptions")
+            .await;
+        assert!(matches!(error, Err(Error::NoEmbedding)));
+    }
+}
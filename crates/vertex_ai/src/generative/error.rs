@@ -0,0 +1,25 @@
+use crate::discovery_engine::client::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("client error")]
+    ClientError(#[from] crate::client::error::Error),
+
+    #[error("API error {}: {}", .0.code, .0.message)]
+    ApiError(Status),
+
+    #[error("Text response error")]
+    ResponseTextRetrieval(reqwest::Error),
+
+    #[error("unable to parse response: {source}, body: {body}")]
+    InvalidResponse {
+        source: serde_json::Error,
+        body: String,
+    },
+
+    #[error("no text was generated: {0}")]
+    NoText(String),
+
+    #[error("no embedding in the response")]
+    NoEmbedding,
+}
@@ -0,0 +1,3 @@
+pub mod client;
+pub mod embeddings;
+pub mod error;
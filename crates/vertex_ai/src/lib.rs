@@ -2,3 +2,4 @@ pub mod client;
 pub mod discovery_engine;
 
 pub mod error;
+pub mod generative;
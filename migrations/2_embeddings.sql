@@ -0,0 +1,27 @@
+-- the embeddings table needs the pgvector extension. Databases without it skip the table and
+-- the app runs without an embedding store, POST /ingest then answers 503. Install pgvector and
+-- recreate the database, or run this block by hand, to enable it later.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector') THEN
+        RAISE NOTICE 'pgvector is not available, the embeddings table is not created';
+        RETURN;
+    END IF;
+
+    CREATE EXTENSION IF NOT EXISTS vector;
+
+    -- 768 is the output size of the Vertex AI text embedding models
+    CREATE TABLE IF NOT EXISTS embeddings (
+        id TEXT primary key,
+        document_id TEXT,
+        chunk TEXT NOT NULL,
+        embedding vector(768) NOT NULL,
+        metadata JSONB NOT NULL DEFAULT '{}'
+    );
+
+    CREATE INDEX IF NOT EXISTS embeddings_document_id_idx ON embeddings (document_id);
+    -- nearest neighbors are ranked by cosine distance, see PgEmbeddingStore::query
+    CREATE INDEX IF NOT EXISTS embeddings_embedding_idx
+        ON embeddings USING hnsw (embedding vector_cosine_ops);
+END
+$$;
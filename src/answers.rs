@@ -0,0 +1,166 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+use vertex_ai::discovery_engine::client::{
+    Answer, AnswerGenerationSpec, AnswerRequest, AnswerSearchResult, ChunkInfo, DataStoreClient,
+    DiscoveryEngineAnswerRequest, ModelPromptSpec, ModelSpec, Query, RelatedQuestionsSpec,
+    SafetySpec, SearchParams, SearchSpec,
+};
+use vertex_ai::discovery_engine::error::Error as SearchError;
+use vertex_ai::generative::client::GenerativeClient;
+
+use crate::Search;
+
+pub type AnswerFuture<'a> = Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+// AnswerProvider answers `query` from `context` with a model, object safe so the app state can
+// hold the backend picked in the settings, the Discovery Engine answer method or Gemini
+pub trait AnswerProvider: Send + Sync {
+    fn answer<'a>(
+        &'a self,
+        project_id: &'a str,
+        query: &'a str,
+        context: &'a str,
+    ) -> AnswerFuture<'a>;
+}
+
+// the context is the only search result the answer is grounded on, the data stores are not
+// searched
+impl AnswerProvider for DataStoreClient {
+    fn answer<'a>(
+        &'a self,
+        project_id: &'a str,
+        query: &'a str,
+        context: &'a str,
+    ) -> AnswerFuture<'a> {
+        Box::pin(async move {
+            let result = AnswerSearchResult {
+                chunk_info: ChunkInfo {
+                    content: context.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            self.answer_with_results(project_id, query, vec![result])
+                .await
+                .map(|response| response.answer.answer_text)
+                .map_err(|e| format!("{:?}", e))
+        })
+    }
+}
+
+impl AnswerProvider for GenerativeClient {
+    fn answer<'a>(
+        &'a self,
+        project_id: &'a str,
+        query: &'a str,
+        context: &'a str,
+    ) -> AnswerFuture<'a> {
+        Box::pin(async move {
+            let instructions = format!(
+                "Answer the question in its language, only using the context you are given: {}",
+                query
+            );
+            self.generate_text(project_id, &instructions, context)
+                .await
+                .map_err(|e| format!("{:?}", e))
+        })
+    }
+}
+
+// AnswerQuery is the body of POST /api/answer
+#[derive(Deserialize, Debug)]
+pub struct AnswerQuery {
+    pub query: String,
+    #[serde(default)]
+    pub context: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProvidedAnswer {
+    pub answer: String,
+}
+
+// DocumentAnswerer answers a question with the answer method of the Discovery Engine
+pub(crate) trait DocumentAnswerer {
+    async fn answer(&self, request: AnswerRequest) -> Result<Answer, SearchError>;
+}
+
+impl DocumentAnswerer for DataStoreClient {
+    async fn answer(&self, request: AnswerRequest) -> Result<Answer, SearchError> {
+        let response = DataStoreClient::answer(self, request).await?;
+        Ok(response.answer)
+    }
+}
+
+// GroundedAnswer is the answer to a chat question with the follow-up questions suggested for it
+pub struct GroundedAnswer {
+    pub question: String,
+    pub text: String,
+    // at most Search::related_questions of them
+    pub related_questions: Vec<String>,
+}
+
+// answer_request asks for an answer grounded on the document `document_id` of the data store,
+// with citations and related questions. The id comes from the url, it is escaped so it stays a
+// single string of the filter
+pub(crate) fn answer_request<C, G>(
+    search: &Search<C, G>,
+    document_id: &str,
+    question: &str,
+) -> AnswerRequest {
+    AnswerRequest {
+        project_id: search.project_id.clone(),
+        serving_config: None,
+        discovery_engine_answer_request: DiscoveryEngineAnswerRequest {
+            query: Query {
+                query_id: String::new(),
+                text: question.to_string(),
+            },
+            safety_spec: SafetySpec {
+                enable: search.safe_search,
+            },
+            related_questions_spec: RelatedQuestionsSpec { enable: true },
+            answer_generation_spec: AnswerGenerationSpec {
+                model_spec: ModelSpec {
+                    version: search.summary_model_version.clone(),
+                },
+                prompt_spec: ModelPromptSpec {
+                    preamble: search.summary_preamble.clone(),
+                },
+                include_citations: true,
+                ..Default::default()
+            },
+            search_spec: SearchSpec {
+                search_params: Some(SearchParams {
+                    filter: format!(
+                        "document_id: ANY(\"{}\")",
+                        document_id.replace('\\', "\\\\").replace('"', "\\\"")
+                    ),
+                    ..Default::default()
+                }),
+                search_result_list: None,
+            },
+            ..Default::default()
+        },
+    }
+}
+
+pub(crate) async fn answer_question<A: DocumentAnswerer, G>(
+    client: &A,
+    search: &Search<A, G>,
+    document_id: &str,
+    question: &str,
+) -> Result<GroundedAnswer, SearchError> {
+    let answer = client
+        .answer(answer_request(search, document_id, question))
+        .await?;
+    let mut related_questions = answer.related_questions;
+    related_questions.truncate(search.related_questions);
+    Ok(GroundedAnswer {
+        question: question.to_string(),
+        text: answer.answer_text,
+        related_questions,
+    })
+}
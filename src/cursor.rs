@@ -0,0 +1,77 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::documents::SearchMode;
+
+#[derive(Debug, Error, PartialEq)]
+#[error("invalid cursor")]
+pub struct InvalidCursor;
+
+// Cursor is the opaque ?cursor= of the document listing. It carries the search to continue and
+// the Discovery Engine page token, so page tokens never show up in URLs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub query: String,
+    pub page_size: u32,
+    pub mode: SearchMode,
+    pub page_token: String,
+}
+
+impl Cursor {
+    // encode returns URL safe base64, the cursor can be put in a query string as is
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursor is serializable"))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, InvalidCursor> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| InvalidCursor)?;
+        let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| InvalidCursor)?;
+        if cursor.page_token.is_empty() {
+            return Err(InvalidCursor);
+        }
+        Ok(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            query: "carbon credits & forestry?".to_string(),
+            page_size: 25,
+            mode: SearchMode::Chunks,
+            page_token: "AeQzZmNxYWR2ZjM0LW9n".to_string(),
+        };
+
+        let encoded = cursor.encode();
+
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Cursor::decode(&encoded), Ok(cursor));
+    }
+
+    #[test]
+    fn test_malformed_cursors_are_rejected() {
+        let no_token = Cursor {
+            query: "climate".to_string(),
+            page_size: 10,
+            mode: SearchMode::Documents,
+            page_token: String::new(),
+        };
+        for cursor in [
+            "",
+            "not a cursor!",
+            &URL_SAFE_NO_PAD.encode("climate"),
+            &URL_SAFE_NO_PAD.encode(r#"{"query": "climate"}"#),
+            &no_token.encode(),
+        ] {
+            assert_eq!(Cursor::decode(cursor), Err(InvalidCursor), "{:?}", cursor);
+        }
+    }
+}
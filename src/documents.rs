@@ -1,7 +1,18 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
 use serde::{Deserialize, Serialize};
+use vertex_ai::discovery_engine::client::{
+    data_store_name, ContentData, DataStoreClient, DiscoveryEngineSearchRequest, ImageQuery,
+    SearchRequest, SearchResponse, SearchResult, SearchResultMode, Snippet, Summary,
+    SummarySkippedReason, UserInfo, MAX_SEARCH_PAGE_SIZE,
+};
+use vertex_ai::discovery_engine::error::Error as SearchError;
 use axum::extract::Path as AxumPath;
 use chrono::prelude::*;
 use askama_axum::IntoResponse;
+use crate::cursor::{Cursor, InvalidCursor};
+use crate::session::UserSession;
+use crate::Search;
 use crate::templates::DocumentDetailsTemplate;
 #[derive(Deserialize)]
 pub struct DocumentCard {
@@ -12,7 +23,62 @@ pub struct DocumentCard {
 pub struct Document {
     pub url: String,
     pub title: String,
-    pub id: u32,
+    pub id: String,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+impl Document {
+    // mime type of the document, guessed from the url extension when the store did not send one
+    fn content_type(&self) -> &str {
+        if let Some(mime_type) = self.mime_type.as_deref() {
+            return mime_type;
+        }
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        match path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+            Some(ext) if ext == "pdf" => "application/pdf",
+            Some(ext) if ext == "txt" => "text/plain",
+            _ => "text/html",
+        }
+    }
+
+    pub fn mime_label(&self) -> &'static str {
+        match self.content_type() {
+            "application/pdf" => "PDF",
+            "text/html" => "HTML",
+            "text/plain" => "Text",
+            _ => "File",
+        }
+    }
+
+    pub fn icon_name(&self) -> &'static str {
+        match self.content_type() {
+            "application/pdf" => "file-earmark-pdf",
+            "text/html" => "globe",
+            _ => "file-earmark",
+        }
+    }
+
+    // PDFs open in the document viewer, everything else links to the original page. The url
+    // comes from the search results, only http(s) and relative links are rendered, anything
+    // else, e.g. a javascript: url, opens the document viewer instead.
+    pub fn display_link(&self) -> String {
+        let viewer = format!("/documents/{}/view", self.id);
+        match self.content_type() {
+            "application/pdf" => viewer,
+            _ if is_safe_link(&self.url) => self.url.clone(),
+            _ => viewer,
+        }
+    }
+}
+
+fn is_safe_link(url: &str) -> bool {
+    let lowercase = url.to_ascii_lowercase();
+    if lowercase.starts_with("http://") || lowercase.starts_with("https://") {
+        return true;
+    }
+    // a path of this site, `//host` and `/\host` are other sites without a scheme
+    url.starts_with('/') && !url.starts_with("//") && !url.starts_with("/\\")
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -68,12 +134,363 @@ pub async fn read_documents() -> Vec<Document> {
     documents
 }
 
+const DEFAULT_PAGE_SIZE: u32 = 10;
+const SUMMARY_RESULT_COUNT: u32 = 5;
+
+// SearchMode is the ?mode= of the document listing, chunk mode returns the passages that
+// match the query instead of whole documents. Chunks need a data store with layout chunking.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Documents,
+    Chunks,
+}
+
+impl SearchMode {
+    fn search_result_mode(self) -> SearchResultMode {
+        match self {
+            SearchMode::Documents => SearchResultMode::Documents,
+            SearchMode::Chunks => SearchResultMode::Chunks,
+        }
+    }
+}
+
+// Query string accepted by the document listing routes, both the HTML page and /api/documents
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct DocumentQuery {
+    pub q: Option<String>,
+    pub page_size: Option<u32>,
+    pub safe_search: Option<bool>,
+    #[serde(default)]
+    pub mode: SearchMode,
+    // continues a previous search, see Cursor
+    pub cursor: Option<String>,
+    // set from the cursor by with_cursor
+    #[serde(skip)]
+    pub page_token: Option<String>,
+}
+
+impl DocumentQuery {
+    // with_cursor returns the query the cursor continues, the cursor wins over q and page_size
+    // so a page link always stays on the same search
+    pub fn with_cursor(&self) -> Result<DocumentQuery, InvalidCursor> {
+        let mut query = self.clone();
+        if let Some(cursor) = query.cursor.take() {
+            let cursor = Cursor::decode(&cursor)?;
+            query.q = Some(cursor.query);
+            query.page_size = Some(cursor.page_size);
+            query.mode = cursor.mode;
+            query.page_token = Some(cursor.page_token);
+        }
+        Ok(query)
+    }
+
+    // the client rejects larger pages, clamp instead of failing the request
+    fn effective_page_size(&self) -> u32 {
+        self.page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_SEARCH_PAGE_SIZE)
+    }
+
+    // next_cursor is the cursor of the page after the results of this query
+    pub fn next_cursor(&self, next_page_token: Option<String>) -> Option<String> {
+        let cursor = Cursor {
+            query: self.q.clone().unwrap_or_default(),
+            page_size: self.effective_page_size(),
+            mode: self.mode,
+            page_token: next_page_token.filter(|token| !token.is_empty())?,
+        };
+        Some(cursor.encode())
+    }
+
+    // query_or is the query to search, `default` when the request has none
+    fn query_or(&self, default: &str) -> String {
+        match self.q.as_deref() {
+            Some(q) if !q.trim().is_empty() => q.to_string(),
+            _ => default.to_string(),
+        }
+    }
+
+    // a query can turn safe search on but never off when the deployment enforces it
+    pub fn safe_search(&self, enforced: bool) -> bool {
+        enforced || self.safe_search.unwrap_or(false)
+    }
+
+    // to_search_request blends the results of the data stores of the deployment, when it
+    // configures some, in the location and collection of the client
+    pub fn to_search_request<C, G>(
+        &self,
+        search: &Search<C, G>,
+        session: &UserSession,
+    ) -> SearchRequest {
+        let discovery_engine_search_request = search.data_stores.iter().fold(
+            DiscoveryEngineSearchRequest {
+                query: self.query_or(&search.alerting_config),
+                page_size: self.effective_page_size(),
+                page_token: self.page_token.clone().unwrap_or_default(),
+                safe_search: self.safe_search(search.safe_search),
+                user_pseudo_id: session.pseudo_id.clone(),
+                user_info: UserInfo {
+                    user_id: String::new(),
+                    user_agent: session.user_agent.clone(),
+                },
+                ..Default::default()
+            },
+            |request, data_store| {
+                request.add_data_store(data_store_name(
+                    &search.location,
+                    &search.project_id,
+                    &search.collection,
+                    data_store,
+                ))
+            },
+        );
+        let mut request = SearchRequest {
+            project_id: search.project_id.clone(),
+            serving_config: None,
+            discovery_engine_search_request,
+        };
+        request
+            .discovery_engine_search_request
+            .content_search_spec
+            .search_result_mode = self.mode.search_result_mode();
+        request
+    }
+
+    // to_image_search_request searches with the image, the query text, when there is one,
+    // narrows the search further. The image is sent base64 encoded.
+    pub fn to_image_search_request<C, G>(
+        &self,
+        search: &Search<C, G>,
+        session: &UserSession,
+        image: &[u8],
+    ) -> SearchRequest {
+        let mut request = self.to_search_request(search, session);
+        request.discovery_engine_search_request.image_query = ImageQuery {
+            image_bytes: STANDARD.encode(image),
+        };
+        request
+    }
+
+    // to_summarized_search_request also asks for a summary of the top results, only when
+    // there is a query to summarize. Chunk results are the relevant passages already, they are
+    // not summarized.
+    pub fn to_summarized_search_request<C, G>(
+        &self,
+        search: &Search<C, G>,
+        session: &UserSession,
+    ) -> SearchRequest {
+        let mut request = self.to_search_request(search, session);
+        if self.mode == SearchMode::Documents
+            && self.q.as_deref().is_some_and(|q| !q.trim().is_empty())
+        {
+            let spec = &mut request.discovery_engine_search_request.content_search_spec;
+            let mut summarized = std::mem::take(spec).with_summary(SUMMARY_RESULT_COUNT);
+            if let Some(summary) = &mut summarized.summary_spec {
+                summary.model_prompt_spec.preamble = search.summary_preamble.clone();
+                summary.model_spec.version = search.summary_model_version.clone();
+            }
+            // the snippets a skipped summary is generated from
+            if search.summary_fallback {
+                summarized = summarized.with_snippets();
+            }
+            *spec = summarized;
+        }
+        request
+    }
+}
+
+// SearchSummary is shown above the search results, a note replaces the summary when the
+// engine skipped it
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchSummary {
+    Text(String),
+    Skipped(String),
+}
+
+impl SearchSummary {
+    fn from_summary(summary: Summary) -> Option<Self> {
+        if let Some(reason) = summary.summary_skipped_reasons.unwrap_or_default().first() {
+            let note = match reason {
+                SummarySkippedReason::AdversarialQueryIgnored
+                | SummarySkippedReason::PotentialPolicyViolation => {
+                    "No summary was generated for this query."
+                }
+                SummarySkippedReason::NonSummarySeekingQueryIgnored => {
+                    "Ask a question to get a summary of the results."
+                }
+                SummarySkippedReason::OutOfDomainQueryIgnored => {
+                    "The documents do not seem to cover this question, no summary was generated."
+                }
+                SummarySkippedReason::LlmAddonNotEnabled => {
+                    "Summaries are not enabled for this search engine."
+                }
+                SummarySkippedReason::SummarySkippedReasonUnspecified => {
+                    "No summary is available for this query."
+                }
+            };
+            return Some(SearchSummary::Skipped(note.to_string()));
+        }
+        summary
+            .summary_text
+            .filter(|text| !text.trim().is_empty())
+            .map(SearchSummary::Text)
+    }
+}
+
+// DocumentChunk is a passage of a document returned by a chunk mode search
+#[derive(Serialize, Clone, Debug)]
+pub struct DocumentChunk {
+    pub document: Document,
+    pub content: String,
+    pub page_start: Option<i32>,
+    pub page_end: Option<i32>,
+}
+
+impl DocumentChunk {
+    // e.g. "Page 3" or "Pages 3-4", empty when the parser did not record the pages
+    pub fn page_label(&self) -> String {
+        match (self.page_start, self.page_end) {
+            (Some(start), Some(end)) if start != end => format!("Pages {}-{}", start, end),
+            (Some(page), _) | (None, Some(page)) => format!("Page {}", page),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+pub struct DocumentResults {
+    // in chunk mode, the documents the chunks come from
+    pub documents: Vec<Document>,
+    pub chunks: Vec<DocumentChunk>,
+    pub summary: Option<SearchSummary>,
+    pub summary_skipped_reason: Option<SummarySkippedReason>,
+    // best snippet of each document result, in result order
+    pub snippets: Vec<Snippet>,
+    pub next_page_token: Option<String>,
+    // the engine expanded the query, some results match only part of it
+    pub query_expanded: bool,
+}
+
+// DocumentSearch searches the data stores of the engine
+pub(crate) trait DocumentSearch {
+    async fn search(&self, request: SearchRequest) -> Result<SearchResponse, SearchError>;
+}
+
+impl DocumentSearch for DataStoreClient {
+    async fn search(&self, request: SearchRequest) -> Result<SearchResponse, SearchError> {
+        DataStoreClient::search(self, request).await
+    }
+}
+
+pub(crate) async fn search_documents<S: DocumentSearch>(
+    client: &S,
+    request: SearchRequest,
+) -> Result<DocumentResults, SearchError> {
+    let response = client.search(request).await?;
+    let query_expanded = response.was_query_expanded();
+    let mut documents: Vec<Document> = Vec::new();
+    let mut chunks = Vec::new();
+    let mut snippets = Vec::new();
+    for result in response.results.unwrap_or_default() {
+        if let Some(chunk) = chunk_from_search_result(&result) {
+            if !documents.iter().any(|d| d.id == chunk.document.id) {
+                documents.push(chunk.document.clone());
+            }
+            chunks.push(chunk);
+        } else {
+            snippets.extend(result.best_snippet().map(Snippet::new));
+            documents.extend(document_from_search_result(result));
+        }
+    }
+    let summary_skipped_reason = response.summary.as_ref().and_then(|summary| {
+        summary
+            .summary_skipped_reasons
+            .as_ref()
+            .and_then(|reasons| reasons.first().copied())
+    });
+    Ok(DocumentResults {
+        documents,
+        chunks,
+        summary: response.summary.and_then(SearchSummary::from_summary),
+        summary_skipped_reason,
+        snippets,
+        next_page_token: response.next_page_token,
+        query_expanded,
+    })
+}
+
+// document_snippets searches the documents of `request` and returns the ones with a snippet, as
+// plain text
+pub(crate) async fn document_snippets<S: DocumentSearch>(
+    client: &S,
+    request: SearchRequest,
+) -> Result<Vec<(Document, String)>, SearchError> {
+    let response = client.search(request).await?;
+    Ok(response
+        .results
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|result| {
+            let snippet = Snippet::new(result.best_snippet()?).plain_text();
+            Some((document_from_search_result(result)?, snippet))
+        })
+        .collect())
+}
+
+// Unstructured documents carry their title and link in derivedStructData, the id is the one of
+// the data store, e.g. a hash for documents imported from Cloud Storage
+fn document_from_search_result(result: SearchResult) -> Option<Document> {
+    let document = result.document?;
+
+    let uri = match document.content.as_ref().and_then(|c| c.content.as_ref()) {
+        Some(ContentData::Uri { uri }) => Some(uri.as_str()),
+        _ => None,
+    };
+    Some(Document {
+        url: document.get_str("link").or(uri).unwrap_or_default().to_string(),
+        title: document.title().unwrap_or(&document.id).to_string(),
+        id: document.id.clone(),
+        mime_type: document.content.map(|content| content.mime_type),
+    })
+}
+
+// Chunks only carry the title and uri of their document, its id is the path segment after
+// documents/ in the chunk name
+fn chunk_from_search_result(result: &SearchResult) -> Option<DocumentChunk> {
+    let chunk = result.chunk.as_ref()?;
+    let metadata = chunk.document_metadata.as_ref();
+    let document_id = chunk
+        .name
+        .split("/documents/")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .unwrap_or_default();
+    let url = metadata.map(|m| m.uri.clone()).unwrap_or_default();
+    let title = metadata
+        .map(|m| m.title.clone())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| document_id.to_string());
+    Some(DocumentChunk {
+        document: Document {
+            url,
+            title,
+            id: document_id.to_string(),
+            mime_type: None,
+        },
+        content: chunk.content.clone(),
+        page_start: chunk.page_span.as_ref().map(|span| span.page_start),
+        page_end: chunk.page_span.as_ref().map(|span| span.page_end),
+    })
+}
+
 // Handler to view a document and its chat
-pub async fn view_document(AxumPath(id): AxumPath<u64>) -> impl IntoResponse {
+pub async fn view_document(AxumPath(id): AxumPath<String>) -> impl IntoResponse {
     let dummy_document = Document {
         url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
         title: "Example Document".to_string(),
-        id: id as u32,
+        id,
+        mime_type: Some("application/pdf".to_string()),
     };
     let dummy_chat = vec![
         DocumentMessage {
@@ -137,3 +554,218 @@ pub async fn view_document(AxumPath(id): AxumPath<u64>) -> impl IntoResponse {
     let template = DocumentDetailsTemplate { document: dummy_document, document_chat: dummy_chat };
     template
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_search;
+    use vertex_ai::discovery_engine::client::Location;
+
+    fn document(url: &str, mime_type: Option<&str>) -> Document {
+        Document {
+            url: url.to_string(),
+            title: "Climate Action Plan 2024".to_string(),
+            id: "7".to_string(),
+            mime_type: mime_type.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_pdf_and_html_documents_render_differently() {
+        let pdf = document("gs://moni-demo-1/plan.pdf", Some("application/pdf"));
+        let html = document(
+            "https://example.com/climate-action-plan-2024",
+            Some("text/html"),
+        );
+
+        assert_eq!(pdf.mime_label(), "PDF");
+        assert_eq!(pdf.display_link(), "/documents/7/view");
+        assert_eq!(html.mime_label(), "HTML");
+        assert_eq!(
+            html.display_link(),
+            "https://example.com/climate-action-plan-2024"
+        );
+        assert_ne!(pdf.icon_name(), html.icon_name());
+    }
+
+    #[test]
+    fn test_display_link_only_renders_http_and_relative_urls() {
+        for url in ["HTTPS://example.com/plan", "http://example.com/plan", "/documents/3"] {
+            assert_eq!(document(url, Some("text/html")).display_link(), url);
+        }
+        for url in [
+            "javascript:alert(document.cookie)",
+            " javascript:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "//evil.example.com",
+            "/\\evil.example.com",
+            "gs://moni-demo-1/plan.html",
+            "",
+        ] {
+            assert_eq!(
+                document(url, Some("text/html")).display_link(),
+                "/documents/7/view",
+                "{:?} should not be linked",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn test_mime_type_falls_back_to_url_extension() {
+        assert_eq!(
+            document("https://example.com/report.PDF?dl=1", None).mime_label(),
+            "PDF"
+        );
+        assert_eq!(
+            document("https://example.com/report", None).mime_label(),
+            "HTML"
+        );
+    }
+
+    #[test]
+    fn test_safe_search_is_serialized_in_search_request() {
+        let query = DocumentQuery {
+            q: Some("climate".to_string()),
+            safe_search: Some(true),
+            ..Default::default()
+        };
+        let search = Search::<DataStoreClient> {
+            safe_search: false,
+            ..test_search(None)
+        };
+        let request = query.to_search_request(&search, &UserSession::default());
+        let json = serde_json::to_value(&request.discovery_engine_search_request).unwrap();
+        assert_eq!(json["safeSearch"], true);
+
+        let opt_out = DocumentQuery {
+            safe_search: Some(false),
+            ..Default::default()
+        };
+        assert!(!opt_out.safe_search(false));
+        assert!(opt_out.safe_search(true));
+    }
+
+    #[test]
+    fn test_search_without_query_uses_alerting_config() {
+        let search = Search::<DataStoreClient> {
+            alerting_config: "Deforestation and water policies".to_string(),
+            ..test_search(None)
+        };
+        for q in [None, Some("  ".to_string())] {
+            let query = DocumentQuery {
+                q,
+                ..Default::default()
+            };
+            let request = query.to_search_request(&search, &UserSession::default());
+            assert_eq!(
+                request.discovery_engine_search_request.query,
+                "Deforestation and water policies"
+            );
+        }
+
+        let query = DocumentQuery {
+            q: Some("climate".to_string()),
+            ..Default::default()
+        };
+        let request = query.to_search_request(&search, &UserSession::default());
+        assert_eq!(request.discovery_engine_search_request.query, "climate");
+    }
+
+    #[test]
+    fn test_search_blends_the_configured_data_stores() {
+        let search = Search::<DataStoreClient> {
+            data_stores: vec!["policies".to_string(), "news".to_string()],
+            ..test_search(None)
+        };
+        let query = DocumentQuery {
+            q: Some("climate".to_string()),
+            ..Default::default()
+        };
+
+        let request = query.to_search_request(&search, &UserSession::default());
+
+        let json = serde_json::to_value(&request.discovery_engine_search_request).unwrap();
+        assert_eq!(
+            json["dataStoreSpecs"],
+            serde_json::json!([
+                {"dataStore": "projects/moni-429523/locations/global/collections/default_collection/dataStores/policies"},
+                {"dataStore": "projects/moni-429523/locations/global/collections/default_collection/dataStores/news"}
+            ])
+        );
+
+        // the data stores are in the location and collection of the client
+        let search = Search::<DataStoreClient> {
+            location: Location::Eu,
+            collection: "policies".to_string(),
+            ..search
+        };
+        let request = query.to_search_request(&search, &UserSession::default());
+        assert_eq!(
+            request.discovery_engine_search_request.data_store_specs[1].data_store,
+            "projects/moni-429523/locations/eu/collections/policies/dataStores/news"
+        );
+    }
+
+    #[test]
+    fn test_page_size_is_clamped_to_search_maximum() {
+        let query = DocumentQuery {
+            page_size: Some(500),
+            ..Default::default()
+        };
+        let search = Search::<DataStoreClient> {
+            safe_search: false,
+            ..test_search(None)
+        };
+        let request = query.to_search_request(&search, &UserSession::default());
+        assert_eq!(
+            request.discovery_engine_search_request.page_size,
+            MAX_SEARCH_PAGE_SIZE
+        );
+    }
+
+    struct ChunkResults;
+
+    impl DocumentSearch for ChunkResults {
+        async fn search(&self, _request: SearchRequest) -> Result<SearchResponse, SearchError> {
+            let chunk = |document_id: &str, id: &str| {
+                serde_json::json!({
+                    "id": id,
+                    "chunk": {
+                        "name": format!("projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/{}/chunks/{}", document_id, id),
+                        "id": id,
+                        "content": "Emissions are cut by 51% by 2030.",
+                        "documentMetadata": {"uri": "gs://moni-demo-1/plan.pdf", "title": ""}
+                    }
+                })
+            };
+            Ok(serde_json::from_value(serde_json::json!({
+                "results": [
+                    chunk("2c5f0e1bd8a7439be2cf1a8f6d2c9b41", "c1"),
+                    chunk("9e7a44c1f0b2d3e58a6c1b7d0f4e2a93", "c1"),
+                    chunk("2c5f0e1bd8a7439be2cf1a8f6d2c9b41", "c2"),
+                ]
+            }))
+            .unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunks_keep_the_data_store_id_of_their_document() {
+        let search: Search<DataStoreClient> = test_search(None);
+        let request = DocumentQuery::default().to_search_request(&search, &UserSession::default());
+
+        let results = search_documents(&ChunkResults, request).await.unwrap();
+
+        let ids: Vec<&str> = results.documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["2c5f0e1bd8a7439be2cf1a8f6d2c9b41", "9e7a44c1f0b2d3e58a6c1b7d0f4e2a93"]
+        );
+        assert_eq!(results.chunks.len(), 3);
+        assert_eq!(
+            results.chunks[2].document.display_link(),
+            "/documents/2c5f0e1bd8a7439be2cf1a8f6d2c9b41/view"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use embeddings::error::FileError;
+use embeddings::file::{Content, FilteredGenerator, ParagraphGenerator};
+use embeddings::pipeline::{embed_content, DEFAULT_PARALLELISM};
+use embeddings::store::{EmbeddingStore, Metadata};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+use vertex_ai::generative::embeddings::EmbeddingClient;
+
+// paragraphs shorter than this, e.g. page numbers and headers, are not worth an embedding
+const MIN_CHUNK_LEN: usize = 20;
+
+pub type EmbedFuture = Pin<Box<dyn Future<Output = Result<Vec<f32>, String>> + Send>>;
+
+// Embedder returns the vector of a chunk, e.g. by calling an embedding model
+pub type Embedder = Arc<dyn Fn(String) -> EmbedFuture + Send + Sync>;
+
+// vertex_embedder embeds the chunks as retrieval documents with the model of `client`
+pub fn vertex_embedder(client: EmbeddingClient, project_id: String) -> Embedder {
+    Arc::new(move |chunk: String| {
+        let (client, project_id) = (client.clone(), project_id.clone());
+        Box::pin(async move {
+            client
+                .embed_document(&project_id, &chunk)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    })
+}
+
+pub type StoreFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+// ChunkStore is the EmbeddingStore ingest writes to, object safe so the app state can hold the
+// Postgres store, or an in-memory one in the tests
+pub trait ChunkStore: Send + Sync {
+    fn upsert_chunk<'a>(
+        &'a self,
+        id: &'a str,
+        vector: Vec<f32>,
+        metadata: Metadata,
+    ) -> StoreFuture<'a>;
+}
+
+impl<S> ChunkStore for S
+where
+    S: EmbeddingStore + Send + Sync,
+    S::Error: Debug,
+{
+    fn upsert_chunk<'a>(
+        &'a self,
+        id: &'a str,
+        vector: Vec<f32>,
+        metadata: Metadata,
+    ) -> StoreFuture<'a> {
+        Box::pin(async move {
+            self.upsert(id, vector, metadata)
+                .await
+                .map_err(|e| format!("{:?}", e))
+        })
+    }
+}
+
+// Upload is the `file` field of a multipart ingestion request, spooled to a temporary file that
+// is removed when the upload is dropped
+pub struct Upload {
+    pub file_name: String,
+    pub mime_type: String,
+    pub path: PathBuf,
+    pub size: usize,
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// IngestReport is returned by POST /ingest, `chunks` is always `embedded` + `failed`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IngestReport {
+    pub document_id: String,
+    pub file_name: String,
+    pub chunks: usize,
+    pub embedded: usize,
+    pub failed: usize,
+}
+
+// read_upload streams the file to disk chunk by chunk, an upload larger than `max_bytes` is
+// rejected with a 413 as soon as the limit is crossed. Only the upload is streamed, ingest
+// reads the spooled file into memory to parse it.
+pub async fn read_upload(
+    multipart: &mut Multipart,
+    max_bytes: usize,
+) -> Result<Upload, (StatusCode, String)> {
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (e.status(), e.body_text()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let mut upload = Upload {
+            file_name: field.file_name().unwrap_or_default().to_string(),
+            mime_type: field.content_type().unwrap_or_default().to_string(),
+            path: std::env::temp_dir().join(format!("moni-upload-{}", Uuid::now_v7())),
+            size: 0,
+        };
+        let mut file = tokio::fs::File::create(&upload.path)
+            .await
+            .map_err(spool_error)?;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| (e.status(), e.body_text()))?
+        {
+            upload.size += chunk.len();
+            if upload.size > max_bytes {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("uploads are limited to {} bytes", max_bytes),
+                ));
+            }
+            file.write_all(&chunk).await.map_err(spool_error)?;
+        }
+        file.flush().await.map_err(spool_error)?;
+        return Ok(upload);
+    }
+    Err((
+        StatusCode::BAD_REQUEST,
+        "the request has no file field".to_string(),
+    ))
+}
+
+fn spool_error(e: std::io::Error) -> (StatusCode, String) {
+    println!("spooling the upload failed: {:?}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "unable to store the upload".to_string(),
+    )
+}
+
+// ingest extracts the text of the upload, embeds every paragraph and stores the vectors under
+// `{document_id}-{chunk index}`. The document id is the hash of the text, ingesting the same
+// document again overwrites its vectors instead of adding new ones. A chunk that fails to embed
+// is counted but does not fail the upload. The PDF parser needs the whole document, its
+// cross-reference table is at the end, so the file is read and parsed on a blocking thread.
+pub async fn ingest(
+    embedder: &Embedder,
+    store: &dyn ChunkStore,
+    upload: Upload,
+) -> Result<IngestReport, (StatusCode, String)> {
+    let (path, mime_type) = (upload.path.clone(), upload.mime_type.clone());
+    let parsed = tokio::task::spawn_blocking(move || {
+        File::open(&path).map(|file| Content::from_reader(file, &mime_type))
+    })
+    .await
+    .map_err(|e| {
+        println!("parsing the upload failed: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "unable to parse the upload".to_string(),
+        )
+    })?;
+    let content = parsed.map_err(spool_error)?.map_err(|e| match e {
+        FileError::UnsuportedFileType => (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("{:?} files are not supported", upload.mime_type),
+        ),
+        e => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("unable to read {}: {}", upload.file_name, e),
+        ),
+    })?;
+
+    let generator = FilteredGenerator::new(ParagraphGenerator::new(), MIN_CHUNK_LEN);
+    let batch = embed_content(
+        &content,
+        generator,
+        |chunk| embedder(chunk),
+        DEFAULT_PARALLELISM,
+    )
+    .await;
+    for error in &batch.errors {
+        println!("embedding chunk {} failed: {}", error.index, error.error);
+    }
+
+    let document_id = content.content_hash();
+    let report = IngestReport {
+        document_id: document_id.clone(),
+        file_name: upload.file_name.clone(),
+        chunks: batch.embeddings.len() + batch.errors.len(),
+        embedded: batch.embeddings.len(),
+        failed: batch.errors.len(),
+    };
+    for embedded in batch.embeddings {
+        let mut metadata = Metadata::from([
+            ("document_id".to_string(), document_id.clone()),
+            ("file_name".to_string(), upload.file_name.clone()),
+            ("chunk".to_string(), embedded.chunk),
+            ("chunk_index".to_string(), embedded.index.to_string()),
+        ]);
+        if let Some(page) = embedded.page {
+            metadata.insert("page".to_string(), page.to_string());
+        }
+        if let Some(language) = embedded.language {
+            metadata.insert("language".to_string(), language);
+        }
+        store
+            .upsert_chunk(
+                &format!("{}-{}", document_id, embedded.index),
+                embedded.embedding,
+                metadata,
+            )
+            .await
+            .map_err(|e| {
+                println!("storing embeddings failed: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "unable to store the embeddings".to_string(),
+                )
+            })?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use embeddings::store::InMemoryEmbeddingStore;
+    use tower::ServiceExt;
+
+    const SAMPLE_PDF: &[u8] = include_bytes!("../crates/embeddings/testdata/sample.pdf");
+
+    const MAX_UPLOAD_BYTES: usize = 1024 * 1024;
+
+    fn app(store: Arc<InMemoryEmbeddingStore>) -> Router {
+        app_with_limit(store, MAX_UPLOAD_BYTES)
+    }
+
+    fn app_with_limit(store: Arc<InMemoryEmbeddingStore>, max_bytes: usize) -> Router {
+        let embedder: Embedder =
+            Arc::new(|chunk: String| Box::pin(async move { Ok(vec![chunk.len() as f32, 1.0]) }));
+        Router::new().route(
+            "/ingest",
+            post(move |mut multipart: Multipart| {
+                let embedder = embedder.clone();
+                let store = store.clone();
+                async move {
+                    let upload = read_upload(&mut multipart, max_bytes).await?;
+                    ingest(&embedder, store.as_ref(), upload).await.map(Json)
+                }
+            }),
+        )
+    }
+
+    fn upload_request(file_name: &str, content_type: &str, bytes: &[u8]) -> Request<Body> {
+        let boundary = "moni-test-boundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary, file_name, content_type
+        )
+        .into_bytes();
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        Request::post("/ingest")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ingest_pdf_embeds_and_stores_every_chunk() {
+        let expected_chunks = Content::from_bytes(SAMPLE_PDF, "application/pdf")
+            .unwrap()
+            .gen_chunks(FilteredGenerator::new(
+                ParagraphGenerator::new(),
+                MIN_CHUNK_LEN,
+            ))
+            .len();
+        let store = Arc::new(InMemoryEmbeddingStore::new());
+
+        let response = app(store.clone())
+            .oneshot(upload_request("sample.pdf", "application/pdf", SAMPLE_PDF))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: IngestReport = serde_json::from_slice(&body).unwrap();
+        assert!(expected_chunks > 0);
+        assert_eq!(report.file_name, "sample.pdf");
+        assert_eq!(report.chunks, expected_chunks);
+        assert_eq!(report.embedded, expected_chunks);
+        assert_eq!(report.failed, 0);
+        assert_eq!(store.len(), expected_chunks);
+
+        let metadata = store
+            .metadata(&format!("{}-0", report.document_id))
+            .unwrap();
+        assert_eq!(metadata["document_id"], report.document_id);
+        assert_eq!(metadata["file_name"], "sample.pdf");
+        assert_eq!(metadata["chunk_index"], "0");
+        assert_eq!(metadata["page"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_ingesting_the_same_file_again_overwrites_its_chunks() {
+        let store = Arc::new(InMemoryEmbeddingStore::new());
+        let mut reports = Vec::new();
+        for file_name in ["sample.pdf", "sample-copy.pdf"] {
+            let response = app(store.clone())
+                .oneshot(upload_request(file_name, "application/pdf", SAMPLE_PDF))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            reports.push(serde_json::from_slice::<IngestReport>(&body).unwrap());
+        }
+
+        let expected_id = Content::from_bytes(SAMPLE_PDF, "application/pdf")
+            .unwrap()
+            .content_hash();
+        assert_eq!(reports[0].document_id, expected_id);
+        assert_eq!(reports[1].document_id, expected_id);
+        assert_eq!(store.len(), reports[0].embedded);
+        let metadata = store.metadata(&format!("{}-0", expected_id)).unwrap();
+        assert_eq!(metadata["file_name"], "sample-copy.pdf");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_oversize_uploads_before_parsing() {
+        let store = Arc::new(InMemoryEmbeddingStore::new());
+        // not a PDF, parsing it would answer 422
+        let oversize = vec![b'x'; 4096];
+
+        let response = app_with_limit(store.clone(), 1024)
+            .oneshot(upload_request("huge.pdf", "application/pdf", &oversize))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_unsupported_file_types() {
+        let store = Arc::new(InMemoryEmbeddingStore::new());
+
+        let response = app(store.clone())
+            .oneshot(upload_request("notes.txt", "text/plain", b"climate notes"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use vertex_ai::generative::error::Error as GenerationError;
+
+use crate::documents::{Document, DocumentInsight};
+use crate::llm::TextGenerator;
+
+// documents of the insights report, one generation each
+pub(crate) const REPORT_DOCUMENT_COUNT: u32 = 5;
+
+// InsightService extracts the insights of a document that match a user's alerting config
+pub(crate) struct InsightService<'a, G> {
+    generator: &'a G,
+    project_id: &'a str,
+}
+
+impl<'a, G: TextGenerator> InsightService<'a, G> {
+    pub fn new(generator: &'a G, project_id: &'a str) -> Self {
+        InsightService {
+            generator,
+            project_id,
+        }
+    }
+
+    // extract_insights asks for the statements of `document_text` relevant to `config`, e.g.
+    // "Climate and Carbon credit policies", as a bulleted list. A response without list items
+    // means the document has nothing relevant and gives no insights.
+    pub async fn extract_insights(
+        &self,
+        document_text: &str,
+        config: &str,
+    ) -> Result<Vec<String>, GenerationError> {
+        let instructions = format!(
+            "Extract the insights of the document about: {}. Answer with a bulleted markdown \
+            list, one insight per item in a single sentence, and nothing else. Answer with an \
+            empty response when the document has no such insights.",
+            config
+        );
+        let response = self
+            .generator
+            .generate(self.project_id, &instructions, document_text)
+            .await?;
+        let insights = parse_insights(&response);
+        if insights.is_empty() && !response.trim().is_empty() {
+            println!("no insights in the response: {:?}", response);
+        }
+        Ok(insights)
+    }
+
+    // document_insights extracts the insights of each document from its text, the documents the
+    // extraction fails for are left out
+    pub async fn document_insights(
+        &self,
+        documents: Vec<(Document, String)>,
+        config: &str,
+    ) -> Vec<DocumentInsight> {
+        let mut insights = Vec::new();
+        for (document, text) in documents {
+            let extracted = match self.extract_insights(&text, config).await {
+                Ok(extracted) => extracted,
+                Err(e) => {
+                    println!("insight extraction failed for {}: {:?}", document.id, e);
+                    continue;
+                }
+            };
+            for insight in extracted {
+                insights.push(DocumentInsight {
+                    document: document.clone(),
+                    insight,
+                    id: insights.len() as u32 + 1,
+                });
+            }
+        }
+        insights
+    }
+}
+
+// parse_insights returns the items of the markdown list in `response`, `-`, `*`, `•` and
+// numbered items are accepted, bold markers are dropped and every other line is ignored
+fn parse_insights(response: &str) -> Vec<String> {
+    let mut insights: Vec<String> = Vec::new();
+    for line in response.lines() {
+        let Some(item) = list_item(line.trim()) else {
+            continue;
+        };
+        let insight = item.replace("**", "").trim().to_string();
+        if !insight.is_empty() && !insights.contains(&insight) {
+            insights.push(insight);
+        }
+    }
+    insights
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    for bullet in ["- ", "* ", "• "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some(item);
+        }
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..]
+        .strip_prefix(". ")
+        .or_else(|| line[digits..].strip_prefix(") "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::FakeGenerator;
+
+    #[tokio::test]
+    async fn test_extract_insights_from_bulleted_response() {
+        let generator = FakeGenerator::new(Some(
+            "Here are the insights:\n\n\
+            - **Carbon credits** may offset at most 20% of the emissions.\n\
+            * A national registry tracks every credit.\n\
+            2. Forestry projects get priority in the first auction.\n\
+            - A national registry tracks every credit.\n",
+        ));
+
+        let insights = InsightService::new(&generator, "moni-429523")
+            .extract_insights("The plan sets a carbon tax...", "Carbon credit policies")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            insights,
+            vec![
+                "Carbon credits may offset at most 20% of the emissions.",
+                "A national registry tracks every credit.",
+                "Forestry projects get priority in the first auction.",
+            ]
+        );
+        let prompts = generator.prompts.lock().unwrap();
+        assert!(prompts[0].0.contains("Carbon credit policies"));
+        assert_eq!(prompts[0].1, "The plan sets a carbon tax...");
+    }
+
+    #[tokio::test]
+    async fn test_extract_insights_ignores_non_list_responses() {
+        for response in [
+            "",
+            "The document does not mention carbon credits.",
+            "2024 was the warmest year on record.",
+        ] {
+            let generator = FakeGenerator::new(Some(response));
+
+            let insights = InsightService::new(&generator, "moni-429523")
+                .extract_insights("The plan sets a carbon tax...", "Carbon credit policies")
+                .await
+                .unwrap();
+
+            assert!(insights.is_empty(), "insights in {:?}", response);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+use vertex_ai::generative::client::GenerativeClient;
+use vertex_ai::generative::error::Error as GenerationError;
+
+// TextGenerator writes text following `instructions` from `material`, with a Gemini model for
+// GenerativeClient
+pub(crate) trait TextGenerator {
+    async fn generate(
+        &self,
+        project_id: &str,
+        instructions: &str,
+        material: &str,
+    ) -> Result<String, GenerationError>;
+}
+
+impl TextGenerator for GenerativeClient {
+    async fn generate(
+        &self,
+        project_id: &str,
+        instructions: &str,
+        material: &str,
+    ) -> Result<String, GenerationError> {
+        self.generate_text(project_id, instructions, material).await
+    }
+}
+
+// FakeGenerator answers every prompt with `response`, or fails when it is None, and records the
+// prompts it receives, clones share them
+#[cfg(test)]
+#[derive(Default, Clone)]
+pub(crate) struct FakeGenerator {
+    pub response: Option<&'static str>,
+    pub prompts: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+#[cfg(test)]
+impl FakeGenerator {
+    pub fn new(response: Option<&'static str>) -> Self {
+        FakeGenerator {
+            response,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+impl TextGenerator for FakeGenerator {
+    async fn generate(
+        &self,
+        _project_id: &str,
+        instructions: &str,
+        material: &str,
+    ) -> Result<String, GenerationError> {
+        self.prompts
+            .lock()
+            .unwrap()
+            .push((instructions.to_string(), material.to_string()));
+        self.response
+            .map(String::from)
+            .ok_or(GenerationError::NoText("no candidates".to_string()))
+    }
+}
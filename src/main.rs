@@ -1,20 +1,110 @@
 #![allow(dead_code)]
+mod answers;
 mod data_sources;
+mod cursor;
+mod database;
 mod documents;
+mod ingest;
+mod insights;
+mod llm;
+mod metrics;
 mod models;
+mod negotiation;
+mod rate_limit;
+mod reports;
 mod router;
 mod routes;
+mod session;
+mod shutdown;
 mod settings;
+mod suggestions;
+mod summaries;
 mod templates;
 
 use std::sync::Arc;
+use std::time::Duration;
+use tower_http::cors::CorsLayer;
 
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use answers::AnswerProvider;
+use ingest::{vertex_embedder, ChunkStore, Embedder};
+use metrics::Metrics;
+use models::embeddings::PgEmbeddingStore;
+use rate_limit::RateLimiter;
+use settings::AnswerBackend;
+use sqlx::PgPool;
+use vertex_ai::client::metrics::ApiMetrics;
+use vertex_ai::client::Client;
+use vertex_ai::discovery_engine::client::{DataStoreClient, Location};
+use vertex_ai::generative::client::GenerativeClient;
+use vertex_ai::generative::embeddings::EmbeddingClient;
 
 #[derive(Clone)]
 struct AppState {
     pg_pool: PgPool,
     vector_db: VectorDB,
+    // None when the database has no pgvector, POST /ingest then answers 503
+    embedding_store: Option<Arc<dyn ChunkStore>>,
+    // None without Google credentials, POST /ingest then answers 503
+    embedder: Option<Embedder>,
+    // the backend of the answers settings, None without Google credentials
+    answer_provider: Option<Arc<dyn AnswerProvider>>,
+    // uploads are streamed to disk, larger ones are rejected with a 413
+    max_upload_bytes: usize,
+    rate_limiter: RateLimiter,
+    // applied to the JSON API routes
+    cors: CorsLayer,
+    metrics: Metrics,
+    search: Search,
+}
+
+// Search is what the search routes and the services need to call Google. They only use the client
+// and the generator through traits, e.g. DocumentSearch and TextGenerator, so the tests give them
+// fakes and run without Google credentials.
+#[derive(Clone)]
+struct Search<C = DataStoreClient, G = GenerativeClient> {
+    project_id: String,
+    // enforced for every search when true, otherwise clients opt in with ?safe_search=true
+    safe_search: bool,
+    summary_preamble: String,
+    summary_model_version: String,
+    // summarize the result snippets when the engine skips the summary
+    summary_fallback: bool,
+    // follow-up questions shown under a chat answer
+    related_questions: usize,
+    // data store the search box suggestions are completed from
+    data_store_id: String,
+    // searched when a request has no query
+    alerting_config: String,
+    // ids of the data stores the searches blend, every data store of the engine when empty
+    data_stores: Vec<String>,
+    // where the data stores are, the client is built with the same location and collection
+    location: Location,
+    // empty for the default collection
+    collection: String,
+    // None when no Google credentials are available, search routes then answer 503
+    client: Option<C>,
+    // writes the reports and the fallback summaries, None without Google credentials too
+    generator: Option<G>,
+}
+
+// test_search is the search config of the tests, they override the fields they are about
+#[cfg(test)]
+fn test_search<C, G>(client: Option<C>) -> Search<C, G> {
+    Search {
+        project_id: "moni-429523".to_string(),
+        safe_search: true,
+        summary_preamble: String::new(),
+        summary_model_version: "stable".to_string(),
+        summary_fallback: false,
+        related_questions: 3,
+        data_store_id: "moni".to_string(),
+        alerting_config: "Climate and Carbon credit policies".to_string(),
+        data_stores: Vec::new(),
+        location: Location::Global,
+        collection: String::new(),
+        client,
+        generator: None,
+    }
 }
 
 #[derive(Clone)]
@@ -31,22 +121,102 @@ async fn main() {
     println!("{:?}", settings);
 
     // setup database
-    let db = PgPoolOptions::new()
-        .max_connections(settings.database.connections)
-        .connect(settings.database.url.as_str())
+    let db = database::connect(&settings.database)
         .await
-        .unwrap();
+        .expect("could not connect to the database");
 
     // run migrations
     sqlx::migrate!().run(&db).await.unwrap();
+    let embedding_store = PgEmbeddingStore::connect(db.clone())
+        .await
+        .expect("could not look up the embeddings table");
+    if embedding_store.is_none() {
+        println!("pgvector is not available, documents cannot be ingested");
+    }
     let v_db = VectorDB {
         key: settings.firebase_config.key,
         url: settings.firebase_config.url,
     };
 
+    let location = settings
+        .discovery_engine
+        .location
+        .as_deref()
+        .map(Location::from)
+        .unwrap_or_default();
+    let collection = settings.discovery_engine.collection.unwrap_or_default();
+    let metrics =
+        Metrics::register(prometheus::Registry::new()).expect("could not register the metrics");
+    let api_metrics =
+        ApiMetrics::register(metrics.registry()).expect("could not register the api metrics");
+    // the search and the generative clients share the tokens and the metrics of one client
+    let client = match Client::new().await {
+        Ok(client) => Some(client.with_metrics(api_metrics)),
+        Err(e) => {
+            println!("google cloud client unavailable: {:?}", e);
+            None
+        }
+    };
+    let data_store_client = client.clone().map(|client| {
+        DataStoreClient::with_client(client)
+            .with_location(location.clone())
+            .with_collection(collection.clone())
+    });
+    let embedder = client.clone().map(|client| {
+        let client = EmbeddingClient::with_client(client)
+            .with_location(settings.generative_model.location.clone())
+            .with_model(settings.generative_model.embedding_model);
+        vertex_embedder(client, settings.discovery_engine.project_id.clone())
+    });
+    let generator = client.map(|client| {
+        GenerativeClient::with_client(client)
+            .with_location(settings.generative_model.location)
+            .with_model(settings.generative_model.model)
+            .with_timeout(Duration::from_secs(settings.generative_model.timeout_secs))
+            .with_safety_settings(settings.generative_model.safety_settings)
+    });
+    let answer_provider: Option<Arc<dyn AnswerProvider>> = match settings.answers.provider {
+        AnswerBackend::DiscoveryEngine => data_store_client
+            .clone()
+            .map(|client| Arc::new(client) as Arc<dyn AnswerProvider>),
+        AnswerBackend::Gemini => generator
+            .clone()
+            .map(|client| Arc::new(client) as Arc<dyn AnswerProvider>),
+    };
+    let search = Search {
+        project_id: settings.discovery_engine.project_id,
+        safe_search: settings.discovery_engine.safe_search,
+        summary_preamble: settings.discovery_engine.summary_preamble,
+        summary_model_version: settings.discovery_engine.summary_model_version,
+        summary_fallback: settings.discovery_engine.summary_fallback,
+        related_questions: settings.discovery_engine.related_questions,
+        data_store_id: settings.discovery_engine.data_store_id,
+        alerting_config: settings.search.alerting_config,
+        data_stores: settings.search.data_stores,
+        location,
+        collection,
+        client: data_store_client,
+        generator,
+    };
+
+    let rate_limiter = RateLimiter::new(
+        settings.rate_limit.requests_per_minute,
+        settings.rate_limit.burst,
+    );
+    rate_limiter.spawn_eviction();
+
+    let pg_pool = db.clone();
     let app_state = Arc::new(AppState {
+        embedding_store: embedding_store.map(|store| Arc::new(store) as Arc<dyn ChunkStore>),
         pg_pool: db,
         vector_db: v_db,
+        embedder,
+        answer_provider,
+        max_upload_bytes: settings.ingest.max_upload_bytes,
+        rate_limiter,
+        cors: router::cors_layer(&settings.cors).expect("invalid cors settings"),
+        metrics,
+        search,
     });
 
     let app = router::init_router(app_state);
@@ -56,5 +226,14 @@ async fn main() {
             .await
             .unwrap();
     println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    shutdown::serve(
+        listener,
+        app,
+        shutdown::shutdown_signal(),
+        Duration::from_secs(settings.server.shutdown_timeout_secs),
+    )
+    .await
+    .unwrap();
+
+    pg_pool.close().await;
 }
@@ -0,0 +1,169 @@
+use embeddings::store::{EmbeddingStore, Metadata};
+use sqlx::PgPool;
+
+/// Dimensions of the `embedding` column, vectors of another size are rejected by Postgres.
+pub const EMBEDDING_DIMENSIONS: usize = 768;
+
+/// An `EmbeddingStore` backed by the `embeddings` table, nearest neighbors are ranked with the
+/// pgvector cosine distance operator `<=>`.
+#[derive(Clone)]
+pub struct PgEmbeddingStore {
+    pool: PgPool,
+}
+
+impl PgEmbeddingStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    // connect returns a store when the database has the embeddings table, the migration skips
+    // it when the pgvector extension is not available
+    pub async fn connect(pool: PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass('embeddings') IS NOT NULL")
+            .fetch_one(&pool)
+            .await?;
+        Ok(exists.then(|| Self::new(pool)))
+    }
+
+    pub async fn upsert_chunk(
+        &self,
+        id: &str,
+        document_id: Option<&str>,
+        chunk: &str,
+        vector: &[f32],
+        metadata: &Metadata,
+    ) -> Result<(), sqlx::Error> {
+        let metadata = serde_json::Value::Object(
+            metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone().into()))
+                .collect(),
+        );
+        sqlx::query(
+            "INSERT INTO embeddings (id, document_id, chunk, embedding, metadata)
+            VALUES ($1, $2, $3, $4::vector, $5::jsonb)
+            ON CONFLICT (id) DO UPDATE SET
+                document_id = EXCLUDED.document_id,
+                chunk = EXCLUDED.chunk,
+                embedding = EXCLUDED.embedding,
+                metadata = EXCLUDED.metadata",
+        )
+        .bind(id)
+        .bind(document_id)
+        .bind(chunk)
+        .bind(vector_literal(vector))
+        .bind(metadata.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+impl EmbeddingStore for PgEmbeddingStore {
+    type Error = sqlx::Error;
+
+    // the `document_id` and `chunk` metadata entries fill the columns of the same name
+    async fn upsert(
+        &self,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Metadata,
+    ) -> Result<(), sqlx::Error> {
+        let document_id = metadata.get("document_id").cloned();
+        let chunk = metadata.get("chunk").cloned().unwrap_or_default();
+        self.upsert_chunk(id, document_id.as_deref(), &chunk, &vector, &metadata)
+            .await
+    }
+
+    async fn query(&self, vector: &[f32], top_k: usize) -> Result<Vec<(String, f32)>, sqlx::Error> {
+        let rows: Vec<(String, f64)> = sqlx::query_as(
+            "SELECT id, 1 - (embedding <=> $1::vector) AS score
+            FROM embeddings
+            ORDER BY embedding <=> $1::vector
+            LIMIT $2",
+        )
+        .bind(vector_literal(vector))
+        .bind(top_k as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, score)| (id, score as f32))
+            .collect())
+    }
+}
+
+// vector_literal formats a vector as pgvector's text input, e.g. [0.1,0.2]
+fn vector_literal(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_literal() {
+        assert_eq!(vector_literal(&[0.5, -1.0, 2.25]), "[0.5,-1,2.25]");
+        assert_eq!(vector_literal(&[]), "[]");
+    }
+}
+
+// Runs against the database in TEST_DATABASE_URL, the pgvector extension has to be available:
+// TEST_DATABASE_URL=postgres://... cargo test --features pgvector-tests
+#[cfg(all(test, feature = "pgvector-tests"))]
+mod tests_integrations {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_upsert_and_query_nearest_neighbors() {
+        let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+        let pool = PgPoolOptions::new().connect(&url).await.unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let store = PgEmbeddingStore::connect(pool.clone())
+            .await
+            .unwrap()
+            .expect("the embeddings table needs the pgvector extension");
+
+        let prefix = uuid::Uuid::now_v7().to_string();
+        let id = |name: &str| format!("{}-{}", prefix, name);
+        // the column holds EMBEDDING_DIMENSIONS values, the rest of each vector is zeros
+        let vector = |values: [f32; 3]| {
+            let mut vector = vec![0.0; EMBEDDING_DIMENSIONS];
+            vector[..3].copy_from_slice(&values);
+            vector
+        };
+        let chunks = [
+            ("adaptation", vector([1.0, 0.0, 0.0])),
+            ("mitigation", vector([0.0, 1.0, 0.0])),
+            ("water", vector([0.7, 0.7, 0.0])),
+        ];
+        for (name, vector) in chunks {
+            let metadata = Metadata::from([
+                ("document_id".to_string(), prefix.clone()),
+                ("chunk".to_string(), name.to_string()),
+            ]);
+            store.upsert(&id(name), vector, metadata).await.unwrap();
+        }
+        // upserting again replaces the row
+        store
+            .upsert(&id("mitigation"), vector([0.0, 0.0, 1.0]), Metadata::new())
+            .await
+            .unwrap();
+
+        let results = store.query(&vector([0.9, 0.1, 0.0]), 2).await.unwrap();
+
+        let ids: Vec<String> = results.iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(ids, vec![id("adaptation"), id("water")]);
+        assert!(results[0].1 > results[1].1);
+
+        sqlx::query("DELETE FROM embeddings WHERE id LIKE $1")
+            .bind(format!("{}-%", prefix))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
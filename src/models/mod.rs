@@ -1,4 +1,5 @@
 pub mod documents;
+pub mod embeddings;
 pub mod error;
 pub(crate) mod store;
 
@@ -0,0 +1,86 @@
+use std::convert::Infallible;
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+
+// Format is the representation a client asked for in its Accept header: API clients sending
+// `Accept: application/json` get JSON, everyone else, browsers included, the HTML page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Json,
+}
+
+impl Format {
+    // the first of text/html and application/json listed in Accept wins, quality values are
+    // not weighed
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let accept = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for media_range in accept {
+            match media_range.split(';').next().unwrap_or_default().trim() {
+                "application/json" => return Format::Json,
+                "text/html" => return Format::Html,
+                _ => {}
+            }
+        }
+        Format::Html
+    }
+}
+
+// vary_on_accept marks a negotiated response as depending on Accept, so caches keep the two
+// representations of a page apart
+pub fn vary_on_accept(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Format::from_headers(&parts.headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_accept_header() {
+        for (accept, format) in [
+            (None, Format::Html),
+            (Some("*/*"), Format::Html),
+            (Some("application/json"), Format::Json),
+            (Some("application/json; charset=utf-8"), Format::Json),
+            (
+                Some("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+                Format::Html,
+            ),
+            (Some("application/xml, application/json"), Format::Json),
+        ] {
+            let mut headers = HeaderMap::new();
+            if let Some(accept) = accept {
+                headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+            }
+            assert_eq!(Format::from_headers(&headers), format, "{:?}", accept);
+        }
+    }
+
+    #[test]
+    fn test_negotiated_responses_vary_on_accept() {
+        let response = vary_on_accept("{}");
+        assert_eq!(response.headers()[header::VARY], "accept");
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tokio::task::JoinHandle;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// RateLimiter keeps a token bucket per client address: a bucket holds at most `burst` tokens,
+// refills at `requests_per_minute` and every request takes one token. IPv6 clients usually get a
+// whole /64, so they share the bucket of their /64 prefix.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+    per_second: f64,
+    burst: f64,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            per_second: f64::from(requests_per_minute.max(1)) / 60.0,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+
+    // check takes a token from the bucket of `address`, or returns how long until the next
+    // token is available
+    fn check(&self, address: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client_key(address)).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    // time an empty bucket takes to be full again
+    fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(self.burst / self.per_second)
+    }
+
+    fn evict_stale(&self, now: Instant) {
+        evict_stale(&self.buckets, self.refill_time(), now);
+    }
+
+    // spawn_eviction evicts the stale buckets once per refill time, off the request path, so
+    // the addresses seen once are not kept forever. The task ends with the last clone of the
+    // limiter.
+    pub fn spawn_eviction(&self) -> JoinHandle<()> {
+        let buckets = Arc::downgrade(&self.buckets);
+        let refill = self.refill_time();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refill.max(Duration::from_secs(1)));
+            loop {
+                interval.tick().await;
+                let Some(buckets) = buckets.upgrade() else {
+                    return;
+                };
+                evict_stale(&buckets, refill, Instant::now());
+            }
+        })
+    }
+}
+
+// evict_stale drops the buckets that are full again, a new bucket would be the same
+fn evict_stale(buckets: &Mutex<HashMap<IpAddr, Bucket>>, refill: Duration, now: Instant) {
+    buckets
+        .lock()
+        .unwrap()
+        .retain(|_, bucket| now.duration_since(bucket.updated) < refill);
+}
+
+// client_key is the address the bucket of `address` is kept under, its /64 prefix for IPv6
+fn client_key(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V4(_) => address,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u64::MAX as u128))),
+        },
+    }
+}
+
+// rate_limit answers 429 with a Retry-After header once a client runs out of tokens, requests
+// without a known peer address, e.g. in tests, are not limited
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(address)) = connect_info else {
+        return next.run(request).await;
+    };
+
+    match limiter.check(address.ip(), Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "too many requests",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limiter = RateLimiter::new(60, 2);
+        let address: IpAddr = [10, 0, 0, 1].into();
+        let start = Instant::now();
+
+        assert!(limiter.check(address, start).is_ok());
+        assert!(limiter.check(address, start).is_ok());
+        let wait = limiter.check(address, start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+
+        // other clients have their own bucket
+        assert!(limiter.check([10, 0, 0, 2].into(), start).is_ok());
+
+        assert!(limiter
+            .check(address, start + Duration::from_secs(1))
+            .is_ok());
+        assert!(limiter
+            .check(address, start + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_ipv6_clients_share_the_bucket_of_their_prefix() {
+        let limiter = RateLimiter::new(60, 1);
+        let start = Instant::now();
+        let address: IpAddr = "2001:db8:85a3:7::1".parse().unwrap();
+
+        assert!(limiter.check(address, start).is_ok());
+        // another address of the same /64
+        assert!(limiter
+            .check("2001:db8:85a3:7:a4f1:3c2e:9b0d:1".parse().unwrap(), start)
+            .is_err());
+        assert!(limiter
+            .check("2001:db8:85a3:8::1".parse().unwrap(), start)
+            .is_ok());
+
+        // IPv4 clients seen through an IPv6 socket share the bucket of their IPv4 address
+        assert!(limiter
+            .check("::ffff:10.0.0.1".parse().unwrap(), start)
+            .is_ok());
+        assert!(limiter.check([10, 0, 0, 2].into(), start).is_ok());
+        assert!(limiter.check([10, 0, 0, 1].into(), start).is_err());
+    }
+
+    #[test]
+    fn test_only_the_buckets_full_again_are_evicted() {
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
+        limiter.check([10, 0, 0, 1].into(), start).unwrap();
+        limiter
+            .check([10, 0, 0, 2].into(), start + Duration::from_secs(1))
+            .unwrap();
+
+        // the first bucket is full again 2s after its last request
+        limiter.evict_stale(start + Duration::from_secs(2));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert!(buckets.contains_key(&IpAddr::from([10, 0, 0, 2])));
+    }
+}
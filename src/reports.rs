@@ -0,0 +1,142 @@
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::documents::{DocumentInsight, Report};
+use crate::llm::TextGenerator;
+
+// appended to the report when the content could not be generated
+const PARTIAL_REPORT_NOTE: &str =
+    "> The report could not be generated, only the insights it was built from are listed.";
+
+// ReportRequest is the body of POST /reports
+#[derive(Deserialize, Debug)]
+pub struct ReportRequest {
+    pub title: String,
+    // markdown template the generated report follows
+    pub template: String,
+    pub insights: Vec<DocumentInsight>,
+}
+
+// ReportService synthesizes reports from the insights users collected
+pub(crate) struct ReportService<'a, W> {
+    generator: &'a W,
+    project_id: &'a str,
+}
+
+impl<'a, W: TextGenerator> ReportService<'a, W> {
+    pub fn new(generator: &'a W, project_id: &'a str) -> Self {
+        ReportService {
+            generator,
+            project_id,
+        }
+    }
+
+    // generate never fails: when the generator fails, or writes nothing, the report lists the
+    // insights followed by a note saying the content could not be generated
+    pub async fn generate(&self, request: ReportRequest) -> Report {
+        let mut report = Report {
+            date: Utc::now().to_rfc3339(),
+            title: request.title,
+            // reports are not stored yet
+            id: 0,
+            content: String::new(),
+            template: request.template,
+        };
+        let instructions = instructions(&report.title, &report.template);
+        let material = material(&request.insights);
+        match self
+            .generator
+            .generate(self.project_id, &instructions, &material)
+            .await
+        {
+            Ok(content) if !content.trim().is_empty() => report.content = content,
+            result => {
+                if let Err(e) = result {
+                    println!("report generation failed: {:?}", e);
+                }
+                report.content = format!(
+                    "{}\n{}",
+                    report.generate_report(request.insights),
+                    PARTIAL_REPORT_NOTE
+                );
+            }
+        }
+        report
+    }
+}
+
+fn instructions(title: &str, template: &str) -> String {
+    format!(
+        "Write the report \"{}\" in markdown following this template:\n\n{}\n\n\
+        Only use the insights you are given and cite the document each insight comes from.",
+        title, template
+    )
+}
+
+fn material(insights: &[DocumentInsight]) -> String {
+    insights
+        .iter()
+        .map(|insight| format!("- {} ({})", insight.insight, insight.document.title))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::Document;
+    use crate::llm::FakeGenerator;
+
+    fn request() -> ReportRequest {
+        let insight = |id: u32, title: &str, insight: &str| DocumentInsight {
+            document: Document {
+                url: format!("https://example.com/{}.pdf", id),
+                title: title.to_string(),
+                id: id.to_string(),
+                mime_type: Some("application/pdf".to_string()),
+            },
+            insight: insight.to_string(),
+            id,
+        };
+        ReportRequest {
+            title: "Carbon credits".to_string(),
+            template: "## Summary\n## Policies".to_string(),
+            insights: vec![
+                insight(1, "Climate Action Plan 2024", "Credits are capped at 20%."),
+                insight(2, "Energy Framework", "Solar gets tax exemptions."),
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_report_with_generated_content() {
+        let generator = FakeGenerator::new(Some("## Summary\nCredits are capped."));
+
+        let report = ReportService::new(&generator, "moni-429523")
+            .generate(request())
+            .await;
+
+        assert_eq!(report.title, "Carbon credits");
+        assert_eq!(report.content, "## Summary\nCredits are capped.");
+        let prompts = generator.prompts.lock().unwrap();
+        assert!(prompts[0].0.contains("## Summary\n## Policies"));
+        assert_eq!(
+            prompts[0].1,
+            "- Credits are capped at 20%. (Climate Action Plan 2024)\n\
+            - Solar gets tax exemptions. (Energy Framework)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_partial_report_when_generation_fails() {
+        for generator in [FakeGenerator::new(None), FakeGenerator::new(Some("  "))] {
+            let report = ReportService::new(&generator, "moni-429523")
+                .generate(request())
+                .await;
+
+            assert!(report.content.starts_with("# Carbon credits\n"));
+            assert!(report.content.contains("Solar gets tax exemptions."));
+            assert!(report.content.ends_with(PARTIAL_REPORT_NOTE));
+        }
+    }
+}
@@ -1,23 +1,362 @@
 use crate::templates;
 use crate::templates::{
-    AddToReportDialogueTemplate, DocumentDetailsTemplate, DocumentsTemplate, InsightReportPage,
+    AddToReportDialogueTemplate, ChatAnswerTemplate, DocumentDetailsTemplate, DocumentsTemplate,
+    ErrorTemplate, InsightReportPage, SuggestionsTemplate,
 };
+use crate::{AppState, Search};
 use askama_axum::IntoResponse;
-use axum::extract::Path as AxumPath;
+use axum::extract::{Extension, Multipart, Path as AxumPath, Query, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Form, Json};
 use chrono::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
 
-use crate::documents::read_documents;
+use crate::answers::{answer_question, AnswerQuery, DocumentAnswerer, ProvidedAnswer};
+use crate::documents::{
+    document_snippets, read_documents, search_documents, Document, DocumentQuery, DocumentSearch,
+    Report,
+};
+use crate::ingest::{ingest, read_upload, IngestReport};
+use crate::insights::{InsightService, REPORT_DOCUMENT_COUNT};
+use crate::llm::TextGenerator;
+use crate::negotiation::{vary_on_accept, Format};
+use crate::reports::{ReportRequest, ReportService};
+use crate::session::UserSession;
+use crate::suggestions::{suggest_queries, QueryCompleter};
+use crate::summaries::SummaryService;
 pub async fn home() -> impl IntoResponse {
     templates::Index
 }
 
-//get documents handler
-pub async fn get_documents() -> impl IntoResponse {
-    let template = DocumentsTemplate {
-        docs: read_documents().await,
+pub async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+//get documents handler, the HTML page or the JSON of /api/documents depending on Accept
+pub async fn get_documents(
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<UserSession>,
+    format: Format,
+    Query(query): Query<DocumentQuery>,
+) -> Response {
+    documents_response(&state.search, &session, format, &query).await
+}
+
+// json version of get_documents for clients that are not rendering html
+pub async fn api_documents(
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<UserSession>,
+    Query(query): Query<DocumentQuery>,
+) -> Result<Json<Vec<Document>>, StatusCode> {
+    documents_json(&state.search, &session, &query).await
+}
+
+// ingest_document embeds the chunks of an uploaded PDF into the embedding store, it answers 503
+// while no embedding model is configured or the database has no pgvector
+pub async fn ingest_document(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<IngestReport>, (StatusCode, String)> {
+    let Some(embedder) = &state.embedder else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no embedding model is configured".to_string(),
+        ));
     };
-    // HtmlTemplate(template)
-    template
+    let Some(embedding_store) = &state.embedding_store else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the embedding store needs the pgvector extension".to_string(),
+        ));
+    };
+    let upload = read_upload(&mut multipart, state.max_upload_bytes).await?;
+    ingest(embedder, embedding_store.as_ref(), upload)
+        .await
+        .map(Json)
+}
+
+// answer answers the query of the body from its context with the answer provider of the
+// settings
+pub async fn answer(
+    State(state): State<Arc<AppState>>,
+    Json(query): Json<AnswerQuery>,
+) -> Result<Json<ProvidedAnswer>, (StatusCode, String)> {
+    let Some(provider) = &state.answer_provider else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no answer provider is configured".to_string(),
+        ));
+    };
+    if query.query.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "the query is empty".to_string()));
+    }
+    let answer = provider
+        .answer(&state.search.project_id, &query.query, &query.context)
+        .await
+        .map_err(|e| {
+            println!("answer failed: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                "the answer provider failed".to_string(),
+            )
+        })?;
+    Ok(Json(ProvidedAnswer { answer }))
+}
+
+// search_by_image searches the documents with an uploaded image, the `q` and `mode` of the query
+// string apply to it as they do to a text search
+pub async fn search_by_image(
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<UserSession>,
+    Query(query): Query<DocumentQuery>,
+    mut multipart: Multipart,
+) -> Result<DocumentsTemplate, (StatusCode, ErrorTemplate)> {
+    image_search_page(
+        &state.search,
+        &session,
+        &query,
+        &mut multipart,
+        state.max_upload_bytes,
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    #[serde(default)]
+    pub q: String,
+}
+
+// suggest answers the search box while the user types, the box debounces the calls
+pub async fn suggest(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SuggestQuery>,
+) -> SuggestionsTemplate {
+    query_suggestions(&state.search, &query.q).await
+}
+
+pub(crate) async fn query_suggestions<C: QueryCompleter, G>(
+    search: &Search<C, G>,
+    q: &str,
+) -> SuggestionsTemplate {
+    SuggestionsTemplate {
+        suggestions: suggest_queries(search, q).await,
+    }
+}
+
+// create_report generates a report from the posted insights, it answers 503 without a search
+// client to generate it with
+pub async fn create_report(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ReportRequest>,
+) -> Result<Json<Report>, StatusCode> {
+    report_json(&state.search, request).await
+}
+
+#[derive(Deserialize)]
+pub struct AskForm {
+    pub question: String,
+}
+
+// ask_document answers a question of the document chat, the HTML of the chat turn is appended
+// to the chat messages
+pub async fn ask_document(
+    State(state): State<Arc<AppState>>,
+    AxumPath(id): AxumPath<String>,
+    Form(form): Form<AskForm>,
+) -> Result<ChatAnswerTemplate, (StatusCode, ErrorTemplate)> {
+    chat_answer(&state.search, &id, &form.question).await
+}
+
+pub(crate) async fn chat_answer<A: DocumentAnswerer, G>(
+    search: &Search<A, G>,
+    document_id: &str,
+    question: &str,
+) -> Result<ChatAnswerTemplate, (StatusCode, ErrorTemplate)> {
+    let error = |status, message: &str| {
+        (
+            status,
+            ErrorTemplate {
+                message: message.to_string(),
+            },
+        )
+    };
+    let question = question.trim();
+    if question.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Please type a question."));
+    }
+    let Some(client) = &search.client else {
+        return Err(error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The document chat is not available without a search client.",
+        ));
+    };
+    let answer = answer_question(client, search, document_id, question)
+        .await
+        .map_err(|e| {
+            println!("document answer failed: {:?}", e);
+            error(
+                StatusCode::BAD_GATEWAY,
+                "The document chat is not available right now, please try again later.",
+            )
+        })?;
+    Ok(ChatAnswerTemplate {
+        document_id: document_id.to_string(),
+        answer,
+    })
+}
+
+pub(crate) async fn report_json<C, G: TextGenerator>(
+    search: &Search<C, G>,
+    request: ReportRequest,
+) -> Result<Json<Report>, StatusCode> {
+    let generator = search
+        .generator
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let report = ReportService::new(generator, &search.project_id)
+        .generate(request)
+        .await;
+    Ok(Json(report))
+}
+
+pub(crate) async fn documents_response<S: DocumentSearch, G: TextGenerator>(
+    search: &Search<S, G>,
+    session: &UserSession,
+    format: Format,
+    query: &DocumentQuery,
+) -> Response {
+    match format {
+        Format::Json => vary_on_accept(documents_json(search, session, query).await),
+        Format::Html => vary_on_accept(documents_page(search, session, query).await),
+    }
+}
+
+// without a search client the page falls back to the test documents so it can be developed
+// locally without Google credentials, a failing search renders the error page with a 502 and
+// a malformed cursor with a 400
+pub(crate) async fn documents_page<S: DocumentSearch, G: TextGenerator>(
+    search: &Search<S, G>,
+    session: &UserSession,
+    query: &DocumentQuery,
+) -> Result<DocumentsTemplate, (StatusCode, ErrorTemplate)> {
+    let query = query.with_cursor().map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorTemplate {
+                message: "This page link is invalid, please search again.".to_string(),
+            },
+        )
+    })?;
+    let Some(client) = &search.client else {
+        return Ok(DocumentsTemplate {
+            docs: read_documents().await,
+            chunks: Vec::new(),
+            summary: None,
+            next_cursor: None,
+            query_expanded: false,
+        });
+    };
+    let request = query.to_summarized_search_request(search, session);
+    let mut results = search_documents(client, request).await.map_err(|e| {
+        println!("document search failed: {:?}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            ErrorTemplate {
+                message: "The document search is not available right now, please try again later."
+                    .to_string(),
+            },
+        )
+    })?;
+    if let (true, Some(generator)) = (search.summary_fallback, &search.generator) {
+        let q = query.q.as_deref().unwrap_or_default();
+        SummaryService::new(generator, &search.project_id)
+            .complete(q, &mut results)
+            .await;
+    }
+    Ok(DocumentsTemplate {
+        docs: results.documents,
+        chunks: results.chunks,
+        summary: results.summary,
+        next_cursor: query.next_cursor(results.next_page_token),
+        query_expanded: results.query_expanded,
+    })
+}
+
+// image_search_page renders the results of an image search, the upload must be an image. The
+// results have no next page link, a cursor cannot carry the image.
+pub(crate) async fn image_search_page<S: DocumentSearch, G>(
+    search: &Search<S, G>,
+    session: &UserSession,
+    query: &DocumentQuery,
+    multipart: &mut Multipart,
+    max_upload_bytes: usize,
+) -> Result<DocumentsTemplate, (StatusCode, ErrorTemplate)> {
+    let error = |status: StatusCode, message: &str| {
+        (
+            status,
+            ErrorTemplate {
+                message: message.to_string(),
+            },
+        )
+    };
+    let upload = read_upload(multipart, max_upload_bytes)
+        .await
+        .map_err(|(status, message)| error(status, &message))?;
+    if !upload.mime_type.starts_with("image/") {
+        return Err(error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Search by image needs an image file, e.g. a PNG or a JPEG.",
+        ));
+    }
+    let Some(client) = &search.client else {
+        return Err(error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Search by image is not available without a search client.",
+        ));
+    };
+    let image = tokio::fs::read(&upload.path).await.map_err(|e| {
+        println!("reading the uploaded image failed: {:?}", e);
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The uploaded image could not be read.",
+        )
+    })?;
+    let request = query.to_image_search_request(search, session, &image);
+    let results = search_documents(client, request).await.map_err(|e| {
+        println!("image search failed: {:?}", e);
+        error(
+            StatusCode::BAD_GATEWAY,
+            "The document search is not available right now, please try again later.",
+        )
+    })?;
+    Ok(DocumentsTemplate {
+        docs: results.documents,
+        chunks: results.chunks,
+        summary: None,
+        next_cursor: None,
+        query_expanded: results.query_expanded,
+    })
+}
+
+pub(crate) async fn documents_json<S: DocumentSearch, G>(
+    search: &Search<S, G>,
+    session: &UserSession,
+    query: &DocumentQuery,
+) -> Result<Json<Vec<Document>>, StatusCode> {
+    let query = query.with_cursor().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let client = search
+        .client
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let request = query.to_search_request(search, session);
+    let results = search_documents(client, request).await.map_err(|e| {
+        println!("document search failed: {:?}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(results.documents))
 }
 fn current_timestamp() -> String {
     Utc::now().to_rfc3339()
@@ -27,7 +366,8 @@ pub async fn add_to_repo_dialogue_document() -> impl IntoResponse {
     let dummy_document = crate::documents::Document {
         url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
         title: "Example Document".to_string(),
-        id: 123,
+        id: "123".to_string(),
+        mime_type: Some("application/pdf".to_string()),
     };
     let insights = r#"
         ## Insights
@@ -47,11 +387,12 @@ pub async fn add_to_repo_dialogue_document() -> impl IntoResponse {
     template
 }
 
-pub async fn view_document(AxumPath(id): AxumPath<u64>) -> impl IntoResponse {
+pub async fn view_document(format: Format, AxumPath(id): AxumPath<String>) -> Response {
     let dummy_document = crate::documents::Document {
         url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
         title: "Example Document".to_string(),
-        id: id as u32,
+        id,
+        mime_type: Some("application/pdf".to_string()),
     };
     let chat = vec![
         crate::documents::DocumentMessage {
@@ -116,43 +457,58 @@ pub async fn view_document(AxumPath(id): AxumPath<u64>) -> impl IntoResponse {
         document: dummy_document,
         document_chat: chat,
     };
-    // HtmlTemplate(template)
-    template
+    match format {
+        Format::Json => vary_on_accept(Json(template)),
+        Format::Html => vary_on_accept(template),
+    }
 }
 
-pub async fn insight_report_page() -> impl IntoResponse {
-    let insights = vec![
-        crate::documents::DocumentInsight {
-            document: crate::documents::Document {
-                url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
-                title: "Example Document".to_string(),
-                id: 101,
-            },
-            insight: "The world is round.".to_string(),
-            id: 1,
-        },
-        crate::documents::DocumentInsight {
-            document: crate::documents::Document {
-                url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
-                title: "Example Document".to_string(),
-                id: 102,
-            },
-            insight: "The world is flat.".to_string(),
-            id: 2,
-        },
-        crate::documents::DocumentInsight {
-            document: crate::documents::Document {
-                url: "https://pdfobject.com/pdf/sample.pdf".to_string(),
-                title: "Example Document".to_string(),
-                id: 103,
+// insight_report_page lists the insights the generator extracts from the documents about the
+// alerting config
+pub async fn insight_report_page(
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<UserSession>,
+) -> Result<InsightReportPage, (StatusCode, ErrorTemplate)> {
+    insight_report(&state.search, &session).await
+}
+
+pub(crate) async fn insight_report<S: DocumentSearch, G: TextGenerator>(
+    search: &Search<S, G>,
+    session: &UserSession,
+) -> Result<InsightReportPage, (StatusCode, ErrorTemplate)> {
+    let error = |status, message: &str| {
+        (
+            status,
+            ErrorTemplate {
+                message: message.to_string(),
             },
-            insight: "The world is a donut.".to_string(),
-            id: 3,
-        },
-    ];
+        )
+    };
+    let (Some(client), Some(generator)) = (&search.client, &search.generator) else {
+        return Err(error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The insights report is not available without a search client.",
+        ));
+    };
+    let query = DocumentQuery {
+        page_size: Some(REPORT_DOCUMENT_COUNT),
+        ..Default::default()
+    };
+    let documents = document_snippets(client, query.to_search_request(search, session))
+        .await
+        .map_err(|e| {
+            println!("insights report search failed: {:?}", e);
+            error(
+                StatusCode::BAD_GATEWAY,
+                "The insights report is not available right now, please try again later.",
+            )
+        })?;
+    let insights = InsightService::new(generator, &search.project_id)
+        .document_insights(documents, &search.alerting_config)
+        .await;
 
-    let template = InsightReportPage {
-        insights: insights,
+    Ok(InsightReportPage {
+        insights,
         report: crate::documents::Report {
             id: 1,
             content: "This is a report on the insights gathered from various documents."
@@ -161,7 +517,741 @@ pub async fn insight_report_page() -> impl IntoResponse {
             title: "Insights Report".to_string(),
             date: current_timestamp(),
         },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::FakeGenerator;
+    use crate::test_search;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request};
+    use axum::middleware;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+    use vertex_ai::discovery_engine::client::{
+        Answer, AnswerRequest, CompleteQueryRequest, CompleteQueryResponse, SearchRequest,
+        SearchResponse, SearchResultMode,
     };
-    // HtmlTemplate(template)
-    template
+    use vertex_ai::discovery_engine::error::Error as SearchError;
+
+    // FakeSearch is the client of the routes under test, it records the requests of every call
+    // in `requests` and fails them all with `fail`
+    #[derive(Default, Clone)]
+    struct FakeSearch {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+        summary: Option<serde_json::Value>,
+        next_page_token: Option<String>,
+        query_expanded: bool,
+        fail: bool,
+        related_questions: Vec<&'static str>,
+        suggestions: Vec<&'static str>,
+    }
+
+    // suggests `suggestions`
+    impl QueryCompleter for FakeSearch {
+        async fn complete_query(
+            &self,
+            request: CompleteQueryRequest,
+        ) -> Result<CompleteQueryResponse, SearchError> {
+            if self.fail {
+                return Err(SearchError::DataStoreError);
+            }
+            self.requests.lock().unwrap().push(serde_json::json!({
+                "dataStoreId": request.data_store_id,
+                "query": request.query,
+            }));
+            let suggestions: Vec<_> = self
+                .suggestions
+                .iter()
+                .map(|suggestion| serde_json::json!({"suggestion": suggestion}))
+                .collect();
+            Ok(
+                serde_json::from_value(serde_json::json!({"querySuggestions": suggestions}))
+                    .unwrap(),
+            )
+        }
+    }
+
+    // answers with `related_questions`
+    impl DocumentAnswerer for FakeSearch {
+        async fn answer(&self, request: AnswerRequest) -> Result<Answer, SearchError> {
+            if self.fail {
+                return Err(SearchError::DataStoreError);
+            }
+            self.requests
+                .lock()
+                .unwrap()
+                .push(serde_json::to_value(&request.discovery_engine_answer_request).unwrap());
+            assert_eq!(request.project_id, "moni-429523");
+            Ok(serde_json::from_value(serde_json::json!({
+                "name": "projects/moni-429523/locations/global/collections/default_collection/engines/moni/sessions/-/answers/1",
+                "state": "SUCCEEDED",
+                "answerText": "Carbon credits may offset at most 20% of the emissions.",
+                "citations": [],
+                "references": [],
+                "relatedQuestions": self.related_questions,
+                "steps": [],
+                "queryUnderstandingInfo": {"queryClassificationInfo": []},
+                "answerSkippedReasons": [],
+                "createTime": "",
+                "completeTime": ""
+            }))
+            .unwrap())
+        }
+    }
+
+    // finds two documents, or two chunks of the first one in chunk mode, with `summary` and
+    // `next_page_token` when set and the query expanded when `query_expanded` is
+    impl DocumentSearch for FakeSearch {
+        async fn search(&self, request: SearchRequest) -> Result<SearchResponse, SearchError> {
+            if self.fail {
+                return Err(SearchError::DataStoreError);
+            }
+            self.requests
+                .lock()
+                .unwrap()
+                .push(serde_json::to_value(&request.discovery_engine_search_request).unwrap());
+            assert_eq!(request.project_id, "moni-429523");
+            assert_eq!(request.discovery_engine_search_request.query, "climate");
+            assert!(request.discovery_engine_search_request.safe_search);
+            let mut response = serde_json::json!({
+                "results": [
+                    {
+                        "id": "1",
+                        "document": {
+                            "name": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/1",
+                            "id": "1",
+                            "derivedStructData": {
+                                "title": "Climate Action Plan 2024",
+                                "link": "gs://moni-demo-1/climate-action-plan-2024.pdf",
+                                "snippets": [{"snippet": "Colombia commits to a <b>51%</b> emissions cut", "snippet_status": "SUCCESS"}]
+                            },
+                            "content": {"mimeType": "application/pdf", "uri": "gs://moni-demo-1/climate-action-plan-2024.pdf"}
+                        }
+                    },
+                    {
+                        "id": "2c5f0e1bd8a7439be2cf1a8f6d2c9b41",
+                        "document": {
+                            "name": "projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/2c5f0e1bd8a7439be2cf1a8f6d2c9b41",
+                            "id": "2c5f0e1bd8a7439be2cf1a8f6d2c9b41",
+                            "derivedStructData": {
+                                "title": "Renewable Energy Policy Framework",
+                                "link": "https://example.com/renewable-energy-policy-framework"
+                            }
+                        }
+                    }
+                ]
+            });
+            if matches!(
+                request
+                    .discovery_engine_search_request
+                    .content_search_spec
+                    .search_result_mode,
+                SearchResultMode::Chunks
+            ) {
+                let chunk = |id: &str, content: &str, pages: (i32, i32)| {
+                    serde_json::json!({
+                        "id": id,
+                        "chunk": {
+                            "name": format!("projects/moni-429523/locations/global/collections/default_collection/dataStores/moni/branches/0/documents/1/chunks/{}", id),
+                            "id": id,
+                            "content": content,
+                            "documentMetadata": {
+                                "uri": "gs://moni-demo-1/climate-action-plan-2024.pdf",
+                                "title": "Climate Action Plan 2024"
+                            },
+                            "pageSpan": {"pageStart": pages.0, "pageEnd": pages.1}
+                        }
+                    })
+                };
+                response["results"] = serde_json::json!([
+                    chunk("c1", "Emissions are cut by 51% by 2030.", (3, 4)),
+                    chunk("c2", "Carbon credits are capped at 20%.", (7, 7)),
+                ]);
+            }
+            if let Some(summary) = &self.summary {
+                response["summary"] = summary.clone();
+            }
+            if let Some(token) = &self.next_page_token {
+                response["nextPageToken"] = token.clone().into();
+            }
+            if self.query_expanded {
+                response["queryExpansionInfo"] = serde_json::json!({"expandedQuery": true});
+            }
+            Ok(serde_json::from_value(response).unwrap())
+        }
+    }
+
+    fn app(client: Option<FakeSearch>) -> Router {
+        app_with_summary_fallback(client, false, FakeGenerator::default())
+    }
+
+    fn app_with_summary_fallback(
+        client: Option<FakeSearch>,
+        summary_fallback: bool,
+        generator: FakeGenerator,
+    ) -> Router {
+        let search = Arc::new(Search {
+            summary_preamble: "Cite the policy documents.".to_string(),
+            summary_fallback,
+            generator: Some(generator),
+            ..test_search(client)
+        });
+        let page_search = search.clone();
+        let chat_search = search.clone();
+        let image_search = search.clone();
+        let suggest_search = search.clone();
+        Router::new()
+            .route(
+                "/",
+                get(
+                    move |Extension(session): Extension<UserSession>,
+                          format: Format,
+                          Query(query): Query<DocumentQuery>| async move {
+                        documents_response(&page_search, &session, format, &query).await
+                    },
+                ),
+            )
+            .route("/documents/:id/view", get(view_document))
+            .route(
+                "/ask-ai/:id",
+                post(
+                    move |AxumPath(id): AxumPath<String>, Form(form): Form<AskForm>| async move {
+                        chat_answer(&chat_search, &id, &form.question).await
+                    },
+                ),
+            )
+            .route(
+                "/suggest",
+                get(move |Query(query): Query<SuggestQuery>| async move {
+                    query_suggestions(&suggest_search, &query.q).await
+                }),
+            )
+            .route(
+                "/search/image",
+                post(
+                    move |Extension(session): Extension<UserSession>,
+                          Query(query): Query<DocumentQuery>,
+                          mut multipart: Multipart| async move {
+                        image_search_page(&image_search, &session, &query, &mut multipart, 1024)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/api/documents",
+                get(
+                    move |Extension(session): Extension<UserSession>,
+                          Query(query): Query<DocumentQuery>| async move {
+                        documents_json(&search, &session, &query).await
+                    },
+                ),
+            )
+            .layer(middleware::from_fn(crate::session::user_session))
+    }
+
+    async fn get_response(app: Router, uri: &str) -> (StatusCode, String, String) {
+        send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, String, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_api_documents_returns_json_search_results() {
+        let (status, content_type, body) = get_response(
+            app(Some(FakeSearch::default())),
+            "/api/documents?q=climate&safe_search=false",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let docs: Vec<Document> = serde_json::from_str(&body).unwrap();
+        let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2c5f0e1bd8a7439be2cf1a8f6d2c9b41"]);
+        assert_eq!(docs[0].mime_type.as_deref(), Some("application/pdf"));
+        assert_eq!(
+            docs[1].url,
+            "https://example.com/renewable-energy-policy-framework"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_answer_json_or_html_depending_on_accept() {
+        let accepting = |uri: &str, accept: &str| {
+            Request::get(uri)
+                .header(header::ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        let app = app(Some(FakeSearch::default()));
+
+        let (status, content_type, body) =
+            send(app.clone(), accepting("/?q=climate", "application/json")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let docs: Vec<Document> = serde_json::from_str(&body).unwrap();
+        assert_eq!(docs.len(), 2);
+
+        let (_, content_type, body) = send(app.clone(), accepting("/?q=climate", browser)).await;
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("Climate Action Plan 2024"));
+
+        let (status, content_type, body) = send(
+            app.clone(),
+            accepting("/documents/7/view", "application/json"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let details: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(details["document"]["id"], "7");
+        assert_eq!(details["document_chat"].as_array().unwrap().len(), 8);
+
+        let response = app
+            .oneshot(accepting("/documents/7/view", browser))
+            .await
+            .unwrap();
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert_eq!(response.headers()[header::VARY], "accept");
+    }
+
+    #[tokio::test]
+    async fn test_chat_answer_renders_related_questions() {
+        let search = FakeSearch {
+            related_questions: vec![
+                "Which sectors can buy carbon credits?",
+                "How are carbon credits registered?",
+                "Do forestry projects get priority?",
+                "When does the cap take effect?",
+            ],
+            ..Default::default()
+        };
+        let requests = search.requests.clone();
+        let ask = |question: &str| {
+            Request::post("/ask-ai/7")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("question={}", question)))
+                .unwrap()
+        };
+        let app = app(Some(search));
+
+        let (status, content_type, body) =
+            send(app.clone(), ask("How+much+can+carbon+credits+offset%3F")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("How much can carbon credits offset?"));
+        assert!(body.contains("Carbon credits may offset at most 20% of the emissions."));
+        assert!(body.contains(r#"hx-post="/ask-ai/7""#));
+        assert!(body.contains(r#"value="Which sectors can buy carbon credits?""#));
+        assert!(body.contains("Do forestry projects get priority?"));
+        // limited to the 3 related questions of the settings
+        assert!(!body.contains("When does the cap take effect?"));
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(
+            request["query"]["text"],
+            "How much can carbon credits offset?"
+        );
+        assert_eq!(request["relatedQuestionsSpec"]["enable"], true);
+
+        let (status, _, _) = send(app, ask("++")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_chat_answer_is_grounded_on_the_document() {
+        let search = FakeSearch::default();
+        let requests = search.requests.clone();
+        let request = Request::post("/ask-ai/2c5f0e1bd8a7439be2cf1a8f6d2c9b41")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("question=What+does+it+cap%3F"))
+            .unwrap();
+
+        let (status, _, _) = send(app(Some(search)), request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(
+            request["searchSpec"]["searchParams"]["filter"],
+            r#"document_id: ANY("2c5f0e1bd8a7439be2cf1a8f6d2c9b41")"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_search_sends_the_uploaded_image() {
+        let png: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let upload = |content_type: &str| {
+            let boundary = "moni-test-boundary";
+            let mut body = format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"chart.png\"\r\nContent-Type: {}\r\n\r\n",
+                boundary, content_type
+            )
+            .into_bytes();
+            body.extend_from_slice(png);
+            body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+            Request::post("/search/image?q=climate")
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let search = FakeSearch::default();
+        let requests = search.requests.clone();
+        let app = app(Some(search));
+
+        let (status, _, body) = send(app.clone(), upload("image/png")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Climate Action Plan 2024"));
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(
+            request["imageQuery"]["imageBytes"],
+            "iVBORw0KGgoAAAANSUhEUg=="
+        );
+
+        let (status, _, _) = send(app, upload("application/pdf")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_suggest_renders_completions_as_options() {
+        let search = FakeSearch {
+            suggestions: vec!["climate adaptation", "climate action plan 2024"],
+            ..Default::default()
+        };
+        let requests = search.requests.clone();
+        let app = app(Some(search));
+
+        let (status, content_type, body) = get_response(app.clone(), "/suggest?q=clim").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains(r#"<option value="climate adaptation"></option>"#));
+        assert!(body.contains(r#"<option value="climate action plan 2024"></option>"#));
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            serde_json::json!({"dataStoreId": "moni", "query": "clim"})
+        );
+
+        // a single letter is not completed
+        let (status, _, body) = get_response(app, "/suggest?q=c").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains("<option"));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_html_route_renders_the_same_results() {
+        let (status, content_type, body) =
+            get_response(app(Some(FakeSearch::default())), "/?q=climate").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("Climate Action Plan 2024"));
+        assert!(body.contains("Renewable Energy Policy Framework"));
+    }
+
+    #[tokio::test]
+    async fn test_html_route_links_the_next_page_with_a_cursor() {
+        let search = FakeSearch {
+            next_page_token: Some("AeQzZmNxYWR2ZjM0LW9n".to_string()),
+            ..Default::default()
+        };
+        let requests = search.requests.clone();
+        let app = app(Some(search));
+
+        let (_, _, body) = get_response(app.clone(), "/?q=climate&page_size=5").await;
+        let cursor = body
+            .split("/?cursor=")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+        assert!(!cursor.contains("AeQzZmNxYWR2ZjM0LW9n"));
+
+        let (status, _, _) = get_response(app, &format!("/?cursor={}", cursor)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let second_page = &requests.lock().unwrap()[1];
+        assert_eq!(second_page["query"], "climate");
+        assert_eq!(second_page["pageSize"], 5);
+        assert_eq!(second_page["pageToken"], "AeQzZmNxYWR2ZjM0LW9n");
+    }
+
+    #[tokio::test]
+    async fn test_chunk_mode_renders_chunks_with_their_pages() {
+        let search = FakeSearch::default();
+        let requests = search.requests.clone();
+        let app = app(Some(search));
+
+        let (status, _, body) = get_response(app.clone(), "/?q=climate&mode=chunks").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("chunk-results"));
+        assert!(body.contains("Emissions are cut by 51% by 2030."));
+        assert!(body.contains("Pages 3-4"));
+        assert!(body.contains("Page 7"));
+        assert!(body.contains("/documents/1/view"));
+        assert!(!body.contains("Renewable Energy Policy Framework"));
+
+        let (_, _, body) = get_response(app, "/?q=climate").await;
+        assert!(!body.contains("chunk-results"));
+
+        let requests = requests.lock().unwrap();
+        let chunks_spec = &requests[0]["contentSearchSpec"];
+        assert_eq!(chunks_spec["searchResultMode"], "CHUNKS");
+        assert!(chunks_spec.get("summarySpec").is_none());
+        assert_eq!(
+            requests[1]["contentSearchSpec"]["searchResultMode"],
+            "DOCUMENTS"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_cursor_is_a_bad_request() {
+        for uri in [
+            "/?cursor=not-a-cursor",
+            "/api/documents?cursor=not-a-cursor",
+        ] {
+            let (status, _, _) = get_response(app(Some(FakeSearch::default())), uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_documents_without_search_client_is_unavailable() {
+        let (status, _, _) = get_response(app(None), "/api/documents?q=climate").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_session_cookie_is_set_and_sent_as_user_pseudo_id() {
+        let search = FakeSearch::default();
+        let requests = search.requests.clone();
+        let app = app(Some(search));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api/documents?q=climate")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Max-Age=15552000"));
+        let session = cookie
+            .split(';')
+            .next()
+            .unwrap()
+            .strip_prefix("moni_uid=")
+            .unwrap()
+            .to_string();
+
+        let response = app
+            .oneshot(
+                Request::get("/api/documents?q=climate")
+                    .header(header::COOKIE, format!("theme=dark; moni_uid={}", session))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+        let user_pseudo_ids: Vec<serde_json::Value> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request["userPseudoId"].clone())
+            .collect();
+        assert_eq!(user_pseudo_ids, vec![session.clone(), session]);
+    }
+
+    #[tokio::test]
+    async fn test_html_route_renders_summary() {
+        let search = FakeSearch {
+            summary: Some(serde_json::json!({
+                "summaryText": "Colombia's 2024 plan commits to a 51% emissions cut by 2030."
+            })),
+            ..Default::default()
+        };
+        let requests = search.requests.clone();
+
+        let (_, _, body) = get_response(app(Some(search)), "/?q=climate").await;
+
+        assert!(body.contains("Colombia&#x27;s 2024 plan commits to a 51% emissions cut by 2030."));
+        let summary_spec = &requests.lock().unwrap()[0]["contentSearchSpec"]["summarySpec"];
+        assert_eq!(
+            summary_spec["modelPromptSpec"]["preamble"],
+            "Cite the policy documents."
+        );
+        assert_eq!(summary_spec["modelSpec"]["version"], "stable");
+    }
+
+    #[tokio::test]
+    async fn test_html_route_notes_expanded_results() {
+        let (_, _, body) = get_response(app(Some(FakeSearch::default())), "/?q=climate").await;
+        assert!(!body.contains("Showing expanded results"));
+
+        let search = FakeSearch {
+            query_expanded: true,
+            ..Default::default()
+        };
+        let (status, _, body) = get_response(app(Some(search)), "/?q=climate").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Showing expanded results"));
+        assert!(body.contains("Climate Action Plan 2024"));
+    }
+
+    #[tokio::test]
+    async fn test_html_route_renders_note_for_skipped_summary() {
+        let search = FakeSearch {
+            summary: Some(serde_json::json!({
+                "summaryText": "A summary could not be generated for your search query.",
+                "summarySkippedReasons": ["OUT_OF_DOMAIN_QUERY_IGNORED"]
+            })),
+            ..Default::default()
+        };
+
+        let (status, _, body) = get_response(app(Some(search)), "/?q=climate").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("The documents do not seem to cover this question"));
+        assert!(!body.contains("A summary could not be generated"));
+        assert!(body.contains("Climate Action Plan 2024"));
+    }
+
+    #[tokio::test]
+    async fn test_skipped_summary_falls_back_to_generated_summary() {
+        let skipped = || {
+            Some(serde_json::json!({
+                "summaryText": "A summary could not be generated for your search query.",
+                "summarySkippedReasons": ["OUT_OF_DOMAIN_QUERY_IGNORED"]
+            }))
+        };
+        let search = FakeSearch {
+            summary: skipped(),
+            ..Default::default()
+        };
+        let requests = search.requests.clone();
+        let generator =
+            FakeGenerator::new(Some("The plan commits Colombia to a 51% emissions cut."));
+        let prompts = generator.prompts.clone();
+
+        let (status, _, body) = get_response(
+            app_with_summary_fallback(Some(search), true, generator),
+            "/?q=climate",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("The plan commits Colombia to a 51% emissions cut."));
+        assert!(!body.contains("The documents do not seem to cover this question"));
+        let prompt = prompts.lock().unwrap()[0].clone();
+        assert!(prompt.0.contains("climate"));
+        assert_eq!(prompt.1, "- Colombia commits to a 51% emissions cut");
+        let snippet_spec = requests.lock().unwrap()[0]["contentSearchSpec"]["snippetSpec"].clone();
+        assert_eq!(snippet_spec["returnSnippet"], true);
+
+        // without the setting, or when the generation fails, the skip note is kept
+        for (summary_fallback, generated) in [(false, Some("Generated")), (true, None)] {
+            let search = FakeSearch {
+                summary: skipped(),
+                ..Default::default()
+            };
+
+            let (_, _, body) = get_response(
+                app_with_summary_fallback(
+                    Some(search),
+                    summary_fallback,
+                    FakeGenerator::new(generated),
+                ),
+                "/?q=climate",
+            )
+            .await;
+
+            assert!(body.contains("The documents do not seem to cover this question"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insight_report_lists_the_insights_of_the_documents() {
+        let generator = FakeGenerator::new(Some(
+            "- Colombia commits to cutting its emissions by 51% by 2030.",
+        ));
+        let prompts = generator.prompts.clone();
+        let search = Search {
+            alerting_config: "climate".to_string(),
+            generator: Some(generator),
+            ..test_search(Some(FakeSearch::default()))
+        };
+
+        let Ok(page) = insight_report(&search, &UserSession::default()).await else {
+            panic!("no insights report");
+        };
+
+        // the second document has no snippet to extract insights from
+        assert_eq!(page.insights.len(), 1);
+        assert_eq!(page.insights[0].document.title, "Climate Action Plan 2024");
+        assert_eq!(
+            page.insights[0].insight,
+            "Colombia commits to cutting its emissions by 51% by 2030."
+        );
+        let prompt = prompts.lock().unwrap()[0].clone();
+        assert!(prompt.0.contains("climate"));
+        assert_eq!(prompt.1, "Colombia commits to a 51% emissions cut");
+
+        let search = Search::<FakeSearch, FakeGenerator> {
+            generator: None,
+            ..search
+        };
+        let result = insight_report(&search, &UserSession::default()).await;
+        assert!(matches!(result, Err((StatusCode::SERVICE_UNAVAILABLE, _))));
+    }
+
+    #[tokio::test]
+    async fn test_failing_search_renders_error_page() {
+        let failing = || FakeSearch {
+            fail: true,
+            ..Default::default()
+        };
+
+        let (status, content_type, body) = get_response(app(Some(failing())), "/?q=climate").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("Something went wrong"));
+        assert!(body.contains("The document search is not available right now"));
+
+        let (status, _, _) = get_response(app(Some(failing())), "/api/documents?q=climate").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
 }
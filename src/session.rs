@@ -0,0 +1,60 @@
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use uuid::Uuid;
+
+pub const SESSION_COOKIE: &str = "moni_uid";
+// 180 days, long enough for search analytics to follow a returning visitor
+const SESSION_MAX_AGE: u64 = 60 * 60 * 24 * 180;
+
+// UserSession identifies the visitor to discovery engine, `pseudo_id` becomes the
+// user_pseudo_id of the search requests
+#[derive(Clone, Debug, Default)]
+pub struct UserSession {
+    pub pseudo_id: String,
+    pub user_agent: String,
+}
+
+// user_session reads the visitor id from the session cookie, a visitor without a valid one gets
+// a new id and the cookie is set on the response
+pub async fn user_session(mut request: Request, next: Next) -> Response {
+    let existing = session_cookie(request.headers());
+    let session = UserSession {
+        pseudo_id: existing
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| Uuid::now_v7().to_string()),
+        user_agent: request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string(),
+    };
+    let pseudo_id = session.pseudo_id.clone();
+    request.extensions_mut().insert(session);
+
+    let mut response = next.run(request).await;
+    if existing.is_none() {
+        let cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+            SESSION_COOKIE, pseudo_id, SESSION_MAX_AGE
+        );
+        response
+            .headers_mut()
+            .append(header::SET_COOKIE, HeaderValue::from_str(&cookie).unwrap());
+    }
+    response
+}
+
+// only ids we issued are accepted, anything else is replaced with a new one
+fn session_cookie(headers: &HeaderMap) -> Option<Uuid> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .and_then(|(_, value)| Uuid::parse_str(value).ok())
+}
@@ -1,6 +1,7 @@
 use anyhow::{bail, Context, Error};
 use serde_derive::Deserialize;
 use std::str::FromStr;
+use vertex_ai::generative::client::SafetySetting;
 
 pub enum RunMode {
     Production,
@@ -11,12 +12,52 @@ pub enum RunMode {
 pub struct Database {
     pub url: String,
     pub connections: u32,
+    // the first connection is retried while the database is starting, see database::connect
+    pub connect_attempts: u32,
+    pub connect_retry_delay_secs: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Server {
     pub host: String,
     pub port: String,
+    // seconds in-flight requests get to finish once a shutdown signal is received
+    pub shutdown_timeout_secs: u64,
+}
+
+// per client address, see RateLimiter
+#[derive(Debug, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+// origins, methods and headers browsers may use on the API routes from other origins
+#[derive(Debug, Deserialize, Default)]
+pub struct Cors {
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ingest {
+    // size limit of a POST /ingest request body, larger uploads are answered with a 413
+    pub max_upload_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Search {
+    // topics the deployment monitors, searched when a documents request has no query, e.g. the
+    // documents listed on the home page
+    #[serde(alias = "default_query")]
+    pub alerting_config: String,
+    // data stores of the engine every search blends the results of, all of them when empty
+    #[serde(default)]
+    pub data_stores: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,12 +66,76 @@ pub struct FirebaseConfig {
     pub url: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryEngine {
+    pub project_id: String,
+    pub safe_search: bool,
+    pub summary_preamble: String,
+    pub summary_model_version: String,
+    // generate a summary from the result snippets when the engine skips it, see SummaryService
+    pub summary_fallback: bool,
+    // follow-up questions shown under a chat answer
+    pub related_questions: usize,
+    // completes the queries typed in the search box
+    pub data_store_id: String,
+    // location and collection of the engine and its data stores, global and the default
+    // collection when not set
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+// GenerativeModel is the Gemini model the reports and the fallback summaries are written with
+#[derive(Debug, Deserialize)]
+pub struct GenerativeModel {
+    // e.g. us-central1 or europe-west1, the model must be served there
+    pub location: String,
+    // e.g. gemini-1.5-flash-002
+    pub model: String,
+    // embeds the chunks of the ingested documents, its vectors must have the dimensions of the
+    // embeddings table, e.g. text-embedding-005
+    pub embedding_model: String,
+    // a generation taking longer fails, and the report or summary falls back without it
+    pub timeout_secs: u64,
+    // e.g. { category = "HARM_CATEGORY_HARASSMENT", threshold = "BLOCK_ONLY_HIGH" }, the
+    // thresholds of the model apply to the categories not listed
+    #[serde(default)]
+    pub safety_settings: Vec<SafetySetting>,
+}
+
+// AnswerBackend is the model POST /api/answer is answered with
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerBackend {
+    // the answer method of the Discovery Engine
+    #[default]
+    DiscoveryEngine,
+    // the Gemini model of generative_model
+    Gemini,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Answers {
+    #[serde(default)]
+    pub provider: AnswerBackend,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub debug: bool,
     pub database: Database,
     pub server: Server,
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub cors: Cors,
+    pub ingest: Ingest,
+    pub search: Search,
     pub firebase_config: FirebaseConfig,
+    pub discovery_engine: DiscoveryEngine,
+    pub generative_model: GenerativeModel,
+    #[serde(default)]
+    pub answers: Answers,
 }
 
 impl FromStr for RunMode {
@@ -55,10 +160,18 @@ impl Settings {
             .build()?;
 
         // You can deserialize (and thus freeze) the entire configuration as
-        let settings = s
+        let settings: Settings = s
             .try_deserialize()
             .map_err(anyhow::Error::new)
             .context("failed to deserialize")?;
+        settings.validate()?;
         Ok(settings)
     }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.search.alerting_config.trim().is_empty() {
+            bail!("search.alerting_config must not be empty");
+        }
+        Ok(())
+    }
 }
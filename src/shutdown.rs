@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+// serve runs the app until `signal` resolves, then stops accepting connections and waits up to
+// `drain_timeout` for the in-flight requests before returning
+pub async fn serve<F>(
+    listener: TcpListener,
+    app: Router,
+    signal: F,
+    drain_timeout: Duration,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (signaled, on_signal) = oneshot::channel();
+    // the peer address is needed by the rate limiter
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        signal.await;
+        println!("shutting down, draining in-flight requests");
+        let _ = signaled.send(());
+    });
+
+    tokio::select! {
+        result = server => result,
+        _ = async {
+            if on_signal.await.is_ok() {
+                tokio::time::sleep(drain_timeout).await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        } => {
+            println!("in-flight requests still running after {:?}, closing them", drain_timeout);
+            Ok(())
+        }
+    }
+}
+
+// shutdown_signal resolves on ctrl-c, or on SIGTERM which is what deployments send
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install the ctrl-c handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::task::JoinHandle;
+
+    // start serves a route that answers after `delay` and returns the address, the channel
+    // that triggers the shutdown and the server task
+    async fn start(
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/slow",
+            get(move || async move {
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        );
+        let (shutdown, on_shutdown) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            async {
+                let _ = on_shutdown.await;
+            },
+            drain_timeout,
+        ));
+        (addr, shutdown, server)
+    }
+
+    async fn get_slow(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_request_completes_after_shutdown_signal() {
+        let (addr, shutdown, server) =
+            start(Duration::from_millis(200), Duration::from_secs(5)).await;
+
+        let request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.send(()).unwrap();
+
+        let response = request.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("done"));
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_gives_up_after_drain_timeout() {
+        let (addr, shutdown, server) =
+            start(Duration::from_secs(60), Duration::from_millis(100)).await;
+
+        let request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.send(()).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server did not stop after the drain timeout")
+            .unwrap()
+            .unwrap();
+        request.abort();
+    }
+}
@@ -0,0 +1,72 @@
+use vertex_ai::discovery_engine::client::{
+    CompleteQueryRequest, CompleteQueryResponse, DataStoreClient,
+};
+use vertex_ai::discovery_engine::error::Error as SearchError;
+
+use crate::Search;
+
+// shorter queries match too much to be worth completing
+const MIN_QUERY_LEN: usize = 2;
+// the search box shows at most this many suggestions
+const MAX_SUGGESTIONS: usize = 5;
+
+// QueryCompleter completes the query typed in the search box
+pub(crate) trait QueryCompleter {
+    async fn complete_query(
+        &self,
+        request: CompleteQueryRequest,
+    ) -> Result<CompleteQueryResponse, SearchError>;
+}
+
+impl QueryCompleter for DataStoreClient {
+    async fn complete_query(
+        &self,
+        request: CompleteQueryRequest,
+    ) -> Result<CompleteQueryResponse, SearchError> {
+        DataStoreClient::complete_query(self, request).await
+    }
+}
+
+pub(crate) fn complete_query_request<C, G>(
+    search: &Search<C, G>,
+    query: &str,
+) -> CompleteQueryRequest {
+    CompleteQueryRequest {
+        project_id: search.project_id.clone(),
+        collections: String::new(),
+        data_store_id: search.data_store_id.clone(),
+        query: query.to_string(),
+        query_model: None,
+        include_tail_suggestions: true,
+    }
+}
+
+// suggest_queries completes what was typed in the search box. Suggestions are best effort:
+// without a client, for a too short query or when the call fails there are none.
+pub(crate) async fn suggest_queries<C: QueryCompleter, G>(
+    search: &Search<C, G>,
+    query: &str,
+) -> Vec<String> {
+    let query = query.trim();
+    let Some(client) = &search.client else {
+        return Vec::new();
+    };
+    if query.chars().count() < MIN_QUERY_LEN {
+        return Vec::new();
+    }
+    match client
+        .complete_query(complete_query_request(search, query))
+        .await
+    {
+        Ok(response) => response
+            .suggestions()
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(String::from)
+            .collect(),
+        Err(e) => {
+            println!("query completion failed: {:?}", e);
+            Vec::new()
+        }
+    }
+}
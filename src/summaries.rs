@@ -0,0 +1,63 @@
+use vertex_ai::discovery_engine::client::SummarySkippedReason;
+
+use crate::documents::{DocumentResults, SearchSummary};
+use crate::llm::TextGenerator;
+
+// the snippets of the top results a fallback summary is generated from
+const FALLBACK_SNIPPET_COUNT: usize = 5;
+
+// SummaryService fills in the summary of a search when the engine skipped it, generating it
+// from the snippets of the top results
+pub(crate) struct SummaryService<'a, G> {
+    generator: &'a G,
+    project_id: &'a str,
+}
+
+impl<'a, G: TextGenerator> SummaryService<'a, G> {
+    pub fn new(generator: &'a G, project_id: &'a str) -> Self {
+        SummaryService {
+            generator,
+            project_id,
+        }
+    }
+
+    // complete replaces the skipped summary of `results` with a generated one. Queries skipped
+    // as adversarial or as policy violations are never summarized, and the skip note is kept
+    // when there are no snippets or the generation fails.
+    pub async fn complete(&self, query: &str, results: &mut DocumentResults) {
+        let Some(reason) = results.summary_skipped_reason else {
+            return;
+        };
+        if matches!(
+            reason,
+            SummarySkippedReason::AdversarialQueryIgnored
+                | SummarySkippedReason::PotentialPolicyViolation
+        ) || results.snippets.is_empty()
+        {
+            return;
+        }
+        let instructions = format!(
+            "Summarize in a few sentences what the search results say about: {}. Only use the \
+            results you are given.",
+            query
+        );
+        let material = results
+            .snippets
+            .iter()
+            .take(FALLBACK_SNIPPET_COUNT)
+            .map(|snippet| format!("- {}", snippet.plain_text()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        match self
+            .generator
+            .generate(self.project_id, &instructions, &material)
+            .await
+        {
+            Ok(text) if !text.trim().is_empty() => {
+                results.summary = Some(SearchSummary::Text(text));
+            }
+            Ok(_) => println!("fallback summary is empty"),
+            Err(e) => println!("fallback summary failed: {:?}", e),
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use crate::answers::GroundedAnswer;
 use crate::documents::Document;
 use askama::Template;
+use serde::Serialize;
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Response};
-use crate::documents::{DocumentInsight, DocumentMessage, Report};
+use crate::documents::{DocumentChunk, DocumentInsight, DocumentMessage, Report, SearchSummary};
 
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -12,15 +14,45 @@ pub struct Index;
 #[template(path = "documents.html")]
 pub struct DocumentsTemplate {
     pub docs: Vec<Document>,
+    // chunk mode results, rendered instead of the document cards
+    pub chunks: Vec<DocumentChunk>,
+    pub summary: Option<SearchSummary>,
+    // links the next page when there is one
+    pub next_cursor: Option<String>,
+    // notes that the results are for an expanded query
+    pub query_expanded: bool,
 }
 
+// ErrorTemplate is rendered instead of a page when a backing service fails
 #[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorTemplate {
+    pub message: String,
+}
+
+// also the JSON of the document page
+#[derive(Template, Serialize)]
 #[template(path = "document_detail.html")]
 pub struct DocumentDetailsTemplate {
     pub document: Document,
     pub document_chat: Vec<DocumentMessage>,
 }
 
+// the options of the search box datalist, swapped in while the user types
+#[derive(Template)]
+#[template(path = "suggestions.html")]
+pub struct SuggestionsTemplate {
+    pub suggestions: Vec<String>,
+}
+
+// the chat turn appended to the document page when a question is asked
+#[derive(Template)]
+#[template(path = "chat_answer.html")]
+pub struct ChatAnswerTemplate {
+    pub document_id: String,
+    pub answer: GroundedAnswer,
+}
+
 #[derive(Template)]
 #[template(path = "add_to_report_dialogue.html")]
 pub struct AddToReportDialogueTemplate {